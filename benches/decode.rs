@@ -0,0 +1,61 @@
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use simple_redis::{
+    BulkString, RespArray, RespDecoder, RespEncoder, RespError, RespFrame, RespParser,
+};
+
+// bytes handed to the decoder per read, like a socket returning what it has so far
+const READ_SIZE: usize = 64 * 1024;
+
+// an array of `len` 32 byte bulk strings, a few megabytes once encoded
+fn encoded_array(len: usize) -> Vec<u8> {
+    let frames = (0..len)
+        .map(|i| BulkString::new(format!("{:032}", i)).into())
+        .collect::<Vec<RespFrame>>();
+    RespArray::new(frames).encode()
+}
+
+// the previous codec: decode everything buffered again on every read until the frame is complete
+fn decode_with_decoder(data: &[u8]) -> RespFrame {
+    let mut buf = BytesMut::new();
+    for chunk in data.chunks(READ_SIZE) {
+        buf.extend_from_slice(chunk);
+        match RespFrame::decode(&mut buf) {
+            Ok(frame) => return frame,
+            Err(RespError::NotComplete) => continue,
+            Err(e) => panic!("decode error {}", e),
+        }
+    }
+    panic!("incomplete frame")
+}
+
+fn decode_with_parser(data: &[u8]) -> RespFrame {
+    let mut parser = RespParser::new();
+    let mut buf = BytesMut::new();
+    for chunk in data.chunks(READ_SIZE) {
+        buf.extend_from_slice(chunk);
+        if let Some(frame) = parser.parse(&mut buf).expect("valid frame") {
+            return frame;
+        }
+    }
+    panic!("incomplete frame")
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode array");
+    group.sample_size(10);
+    for len in [25_000, 100_000] {
+        let data = encoded_array(len);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::new("decoder", data.len()), &data, |b, data| {
+            b.iter(|| decode_with_decoder(data))
+        });
+        group.bench_with_input(BenchmarkId::new("parser", data.len()), &data, |b, data| {
+            b.iter(|| decode_with_parser(data))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
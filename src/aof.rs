@@ -0,0 +1,618 @@
+/*
+   The append only file: every write command as the RESP request that made it, in the order
+   they ran, so that replaying them at startup rebuilds the dataset.
+
+   - file: "[<rdb snapshot>]<request>..."
+   - the snapshot holds the dataset as it was when appendonly was turned on, it is written
+     by another thread while the writes made meanwhile are buffered to follow it
+   - transactions are written between MULTI and EXEC, one cut short at the end is dropped
+*/
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use bytes::BytesMut;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    cmd::Command, now_ms, rdb, Backend, CommandExcetor, RespEncoder, RespFrame, RespParser,
+};
+
+pub const AOF_FILENAME: &str = "appendonly.aof";
+// how often the everysec policy flushes the file to disk
+const FSYNC_INTERVAL_MS: u64 = 1000;
+
+/// When what was appended reaches the disk, as set by `appendfsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendFsync {
+    Always,
+    EverySec,
+    No,
+}
+
+impl AppendFsync {
+    pub const ALL: [AppendFsync; 3] = [AppendFsync::Always, AppendFsync::EverySec, AppendFsync::No];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppendFsync::Always => "always",
+            AppendFsync::EverySec => "everysec",
+            AppendFsync::No => "no",
+        }
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AofError {
+    #[error("Bad file format reading the append only file at offset {0}")]
+    BadFormat(usize),
+    #[error("Unexpected end of file reading the append only file, set aof-load-truncated to yes to load it anyway")]
+    Truncated,
+    #[error("Background append only file rewriting already in progress")]
+    RewriteInProgress,
+    #[error("{0}")]
+    Rdb(#[from] rdb::RdbError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// What replaying the append only file did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AofLoadStats {
+    /// Keys loaded from the snapshot at the start of the file.
+    pub keys: usize,
+    /// Commands replayed after it.
+    pub commands: usize,
+    /// Whether an incomplete command or transaction at the end was left out.
+    pub truncated: bool,
+}
+
+/// The append only file and how it is written, changed at runtime through CONFIG SET.
+///
+/// There is no configuration file to say whether it is on, so the file being there is what
+/// turns it on at startup, and turning it off removes it rather than leaving it behind stale.
+#[derive(Debug)]
+pub struct Aof {
+    fsync: AtomicU8,
+    load_truncated: AtomicBool,
+    // whether appendonly is on, so that writes only wait for the file when there is one
+    enabled: AtomicBool,
+    // a thread is writing the snapshot a new file starts with
+    rewriting: AtomicBool,
+    state: Mutex<AofState>,
+}
+
+#[derive(Debug)]
+enum AofState {
+    Off,
+    // the snapshot is being written, the writes made meanwhile wait to be appended after it
+    Rewriting(BytesMut),
+    On(AofWriter),
+}
+
+#[derive(Debug)]
+struct AofWriter {
+    file: File,
+    // unix milliseconds of the last fsync, and whether anything was written since
+    last_fsync: u64,
+    pending: bool,
+}
+
+/// The file held by a writer, so that the file sees the writes in the order they were made.
+pub struct AofGuard<'a> {
+    aof: &'a Aof,
+    state: MutexGuard<'a, AofState>,
+}
+
+impl Default for Aof {
+    fn default() -> Self {
+        Self {
+            fsync: AtomicU8::new(1),
+            load_truncated: AtomicBool::new(true),
+            enabled: AtomicBool::new(false),
+            rewriting: AtomicBool::new(false),
+            state: Mutex::new(AofState::Off),
+        }
+    }
+}
+
+impl Aof {
+    pub fn fsync_policy(&self) -> AppendFsync {
+        AppendFsync::ALL[self.fsync.load(Ordering::Relaxed) as usize]
+    }
+    pub fn set_fsync_policy(&self, policy: AppendFsync) {
+        let index = AppendFsync::ALL.iter().position(|p| *p == policy);
+        self.fsync
+            .store(index.unwrap_or_default() as u8, Ordering::Relaxed);
+    }
+    /// Whether a file cut short by a crash is loaded up to its last complete command, rather
+    /// than refused.
+    pub fn load_truncated(&self) -> bool {
+        self.load_truncated.load(Ordering::Relaxed)
+    }
+    pub fn set_load_truncated(&self, enabled: bool) {
+        self.load_truncated.store(enabled, Ordering::Relaxed);
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+    /// Whether the snapshot a new file starts with is still being written.
+    pub fn rewrite_in_progress(&self) -> bool {
+        self.rewriting.load(Ordering::Acquire)
+    }
+    pub fn lock(&self) -> AofGuard<'_> {
+        AofGuard {
+            aof: self,
+            state: self.state.lock().unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+    /// Flush to disk what was appended since the last second, for the everysec policy. The
+    /// file is not held meanwhile, so writers don't wait for the disk.
+    pub fn fsync_if_needed(&self) {
+        let file = {
+            let mut guard = self.lock();
+            let AofState::On(writer) = &mut *guard.state else {
+                return;
+            };
+            let now = now_ms();
+            if !writer.pending || now.saturating_sub(writer.last_fsync) < FSYNC_INTERVAL_MS {
+                return;
+            }
+            writer.pending = false;
+            writer.last_fsync = now;
+            writer.file.try_clone()
+        };
+        if let Err(e) = file.and_then(|file| file.sync_data()) {
+            warn!("failed to fsync the append only file: {}", e);
+        }
+    }
+}
+
+impl AofGuard<'_> {
+    pub fn is_enabled(&self) -> bool {
+        !matches!(*self.state, AofState::Off)
+    }
+    /// Append `requests` if appendonly is on, flushing them to disk right away under the always
+    /// policy.
+    pub fn append(&mut self, requests: &[RespFrame]) {
+        if requests.is_empty() {
+            return;
+        }
+        let policy = self.aof.fsync_policy();
+        match &mut *self.state {
+            AofState::Off => {}
+            AofState::Rewriting(buf) => {
+                for request in requests {
+                    request.encode_to(buf);
+                }
+            }
+            AofState::On(writer) => {
+                let mut buf = BytesMut::new();
+                for request in requests {
+                    request.encode_to(&mut buf);
+                }
+                writer.write(&buf, policy);
+            }
+        }
+    }
+    fn set(&mut self, state: AofState) {
+        let enabled = !matches!(state, AofState::Off);
+        *self.state = state;
+        self.aof.enabled.store(enabled, Ordering::Release);
+    }
+}
+
+impl AofWriter {
+    fn write(&mut self, data: &[u8], policy: AppendFsync) {
+        let ret = self.file.write_all(data).and_then(|_| match policy {
+            AppendFsync::Always => self.file.sync_data(),
+            AppendFsync::EverySec => {
+                self.pending = true;
+                Ok(())
+            }
+            AppendFsync::No => Ok(()),
+        });
+        if let Err(e) = ret {
+            warn!("failed to write to the append only file: {}", e);
+        }
+    }
+}
+
+/// Where the append only file is, next to the snapshot file.
+pub fn path(backend: &Backend) -> PathBuf {
+    backend.save_config().dir().join(AOF_FILENAME)
+}
+
+/// Start a new append only file with a snapshot of the dataset, then append every write to it.
+///
+/// Writes are logged from now on, first to a buffer while another thread writes the snapshot,
+/// then to the file once it is there. The dataset is copied while no command runs, so that
+/// every write ends up either in the snapshot or after it, but serializing and writing the
+/// copy hold nothing.
+pub fn enable(backend: &Backend) -> Result<(), AofError> {
+    let aof = backend.aof();
+    let mut guard = aof.lock();
+    if guard.is_enabled() {
+        return Ok(());
+    }
+    // a rewrite outliving a disable has yet to remove its file
+    if aof.rewrite_in_progress() {
+        return Err(AofError::RewriteInProgress);
+    }
+    aof.rewriting.store(true, Ordering::Release);
+    guard.set(AofState::Rewriting(BytesMut::new()));
+    let backend = backend.clone();
+    std::thread::spawn(move || rewrite(&backend));
+    info!("Background append only file rewriting started");
+    Ok(())
+}
+
+// Write the snapshot a new file starts with, then switch from the buffer to the file.
+fn rewrite(backend: &Backend) {
+    let aof = backend.aof();
+    let snapshot = {
+        // the commands already running finish first, whether they were logged or not
+        let _guard = backend.lock_exclusive();
+        let snapshot = rdb::Snapshot::take(backend);
+        // what was buffered so far is in the copy
+        if let AofState::Rewriting(buf) = &mut *aof.lock().state {
+            buf.clear();
+        }
+        snapshot
+    };
+    let path = path(backend);
+    let ret = rdb::write_file(&path, &snapshot.serialize())
+        .map_err(AofError::from)
+        .and_then(|_| open(&path));
+    let mut guard = aof.lock();
+    match (ret, std::mem::replace(&mut *guard.state, AofState::Off)) {
+        (Ok(mut writer), AofState::Rewriting(buf)) => {
+            writer.write(&buf, aof.fsync_policy());
+            guard.set(AofState::On(writer));
+            info!("Background append only file rewriting terminated with success");
+        }
+        (ret, _) => {
+            if let Err(e) = ret {
+                warn!("Background append only file rewriting error: {}", e);
+            }
+            // turned off meanwhile, or never complete, either way it must not be loaded
+            guard.set(AofState::Off);
+            remove(&path);
+        }
+    }
+    aof.rewriting.store(false, Ordering::Release);
+}
+
+/// Stop appending and remove the file, it would bring back a stale dataset at startup.
+pub fn disable(backend: &Backend) -> Result<(), AofError> {
+    let mut guard = backend.aof().lock();
+    if !guard.is_enabled() {
+        return Ok(());
+    }
+    guard.set(AofState::Off);
+    match fs::remove_file(path(backend)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    info!("Append only file disabled");
+    Ok(())
+}
+
+/// Replay the append only file if there is one, then keep appending to it. A file cut short
+/// is truncated to its last complete command when `aof-load-truncated` allows it.
+pub fn load(backend: &Backend) -> Result<Option<AofLoadStats>, AofError> {
+    let path = path(backend);
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let (stats, len) = replay(&data, backend, backend.aof().load_truncated())?;
+    if len < data.len() {
+        warn!(
+            "!!! Warning: short read while loading the AOF file {}!!! AOF loaded anyway because aof-load-truncated is enabled",
+            path.display()
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len as u64)?;
+    }
+    backend.aof().lock().set(AofState::On(open(&path)?));
+    Ok(Some(stats))
+}
+
+/// Run every command of an append only file against `backend`, returning how many bytes of it
+/// were complete.
+pub fn replay(
+    data: &[u8],
+    backend: &Backend,
+    allow_truncated: bool,
+) -> Result<(AofLoadStats, usize), AofError> {
+    let mut stats = AofLoadStats::default();
+    let mut valid = 0;
+    if data.starts_with(rdb::RDB_SIGNATURE) {
+        let (rdb_stats, len) = rdb::load_prefix(data, backend)?;
+        stats.keys = rdb_stats.keys;
+        valid = len;
+    }
+    // scripts expect it, and nothing else runs yet so it is always free
+    let _guard = backend.try_lock(true);
+    let mut parser = RespParser::new();
+    let mut buf = BytesMut::from(&data[valid..]);
+    // the commands queued since MULTI
+    let mut transaction: Option<Vec<Command>> = None;
+    loop {
+        let offset = data.len() - buf.len();
+        let frame = match parser.parse(&mut buf) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(_) => return Err(AofError::BadFormat(offset)),
+        };
+        let cmd = Command::try_from(frame).map_err(|_| AofError::BadFormat(offset))?;
+        match (cmd, transaction.as_mut()) {
+            (Command::Multi(_), None) => transaction = Some(Vec::new()),
+            (Command::Exec(_), Some(_)) => {
+                for cmd in transaction.take().unwrap_or_default() {
+                    cmd.execute(backend);
+                    stats.commands += 1;
+                }
+            }
+            (cmd, Some(queued)) => queued.push(cmd),
+            (cmd, None) => {
+                cmd.execute(backend);
+                stats.commands += 1;
+            }
+        }
+        if transaction.is_none() {
+            valid = data.len() - buf.len();
+        }
+    }
+    if valid < data.len() {
+        if !allow_truncated {
+            return Err(AofError::Truncated);
+        }
+        stats.truncated = true;
+    }
+    Ok((stats, valid))
+}
+
+fn remove(path: &Path) {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            warn!("failed to remove the append only file: {}", e)
+        }
+        _ => {}
+    }
+}
+
+fn open(path: &Path) -> Result<AofWriter, AofError> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(AofWriter {
+        file,
+        last_fsync: now_ms(),
+        pending: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BulkString, RespArray};
+
+    use super::*;
+
+    // an empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-aof-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn wait_for_rewrite(backend: &Backend) {
+        for _ in 0..500 {
+            if !backend.aof().rewrite_in_progress() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("append only file rewrite didn't finish");
+    }
+
+    fn request(args: &[&str]) -> RespFrame {
+        let mut buf = BytesMut::from(&encode(&[args])[..]);
+        RespParser::new().parse(&mut buf).unwrap().unwrap()
+    }
+
+    fn encode(commands: &[&[&str]]) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        for args in commands {
+            let args = args.iter().map(|arg| BulkString::new(*arg).into());
+            RespFrame::from(RespArray::new(args.collect::<Vec<RespFrame>>())).encode_to(&mut buf);
+        }
+        buf.to_vec()
+    }
+
+    #[test]
+    fn test_replay_commands() -> anyhow::Result<()> {
+        let data = encode(&[
+            &["set", "hello", "world"],
+            &["lpush", "list", "a", "b"],
+            &["multi"],
+            &["set", "hello", "again"],
+            &["del", "list"],
+            &["exec"],
+        ]);
+        let backend = Backend::new();
+        let (stats, len) = replay(&data, &backend, false)?;
+        assert_eq!(
+            stats,
+            AofLoadStats {
+                keys: 0,
+                commands: 4,
+                truncated: false
+            }
+        );
+        assert_eq!(len, data.len());
+        assert_eq!(backend.get(b"hello"), Ok(Some("again".into())));
+        assert!(!backend.exists(b"list"));
+
+        let backend = Backend::new();
+        assert!(matches!(
+            replay(b"+OK\r\n", &backend, true),
+            Err(AofError::BadFormat(0))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_truncated() -> anyhow::Result<()> {
+        let complete = encode(&[&["set", "hello", "world"]]);
+        // a command cut short, and a transaction never executed
+        let mut data = complete.clone();
+        data.extend_from_slice(b"*3\r\n$3\r\nset\r\n$5\r\nhel");
+        let mut multi = complete.clone();
+        multi.extend(encode(&[&["multi"], &["set", "hello", "lost"]]));
+
+        for data in [data, multi] {
+            let backend = Backend::new();
+            assert!(matches!(
+                replay(&data, &backend, false),
+                Err(AofError::Truncated)
+            ));
+            let backend = Backend::new();
+            let (stats, len) = replay(&data, &backend, true)?;
+            assert!(stats.truncated);
+            assert_eq!(stats.commands, 1);
+            assert_eq!(len, complete.len());
+            assert_eq!(backend.get(b"hello"), Ok(Some("world".into())));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_truncates_file() -> anyhow::Result<()> {
+        let dir = temp_dir("load");
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        assert_eq!(load(&backend)?, None);
+        assert!(!backend.aof().is_enabled());
+
+        let complete = encode(&[&["set", "hello", "world"]]);
+        let mut data = complete.clone();
+        data.extend_from_slice(b"*2\r\n$3\r\nget");
+        fs::write(dir.join(AOF_FILENAME), &data)?;
+
+        backend.aof().set_load_truncated(false);
+        assert!(matches!(load(&backend), Err(AofError::Truncated)));
+        assert_eq!(fs::read(dir.join(AOF_FILENAME))?, data);
+
+        backend.aof().set_load_truncated(true);
+        let stats = load(&backend)?.unwrap();
+        assert!(stats.truncated);
+        assert_eq!(fs::read(dir.join(AOF_FILENAME))?, complete);
+        assert!(backend.aof().is_enabled());
+        Ok(())
+    }
+
+    #[test]
+    fn test_enable_snapshot_and_append() -> anyhow::Result<()> {
+        let dir = temp_dir("enable");
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.set("before".into(), "1".into());
+        enable(&backend)?;
+        assert!(backend.aof().is_enabled());
+        wait_for_rewrite(&backend);
+
+        backend.aof().set_fsync_policy(AppendFsync::Always);
+        backend
+            .aof()
+            .lock()
+            .append(&[request(&["set", "after", "2"])]);
+
+        let data = fs::read(dir.join(AOF_FILENAME))?;
+        assert!(data.starts_with(rdb::RDB_SIGNATURE));
+        let other = Backend::new();
+        let (stats, _) = replay(&data, &other, false)?;
+        assert_eq!((stats.keys, stats.commands), (1, 1));
+        assert_eq!(other.get(b"before"), Ok(Some("1".into())));
+        assert_eq!(other.get(b"after"), Ok(Some("2".into())));
+
+        disable(&backend)?;
+        assert!(!backend.aof().is_enabled());
+        assert!(!dir.join(AOF_FILENAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_enable_in_background() -> anyhow::Result<()> {
+        let dir = temp_dir("background");
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.set("before".into(), "1".into());
+
+        // a command still running holds the copy back, not the command turning it on
+        let guard = backend.lock_shared();
+        enable(&backend)?;
+        assert!(backend.aof().is_enabled());
+        assert!(backend.aof().rewrite_in_progress());
+        backend.set("during".into(), "2".into());
+        backend
+            .aof()
+            .lock()
+            .append(&[request(&["set", "during", "2"])]);
+        // turned off before the snapshot is written, which is then removed
+        disable(&backend)?;
+        assert!(matches!(enable(&backend), Err(AofError::RewriteInProgress)));
+        drop(guard);
+        wait_for_rewrite(&backend);
+        assert!(!backend.aof().is_enabled());
+        assert!(!dir.join(AOF_FILENAME).exists());
+
+        // the writes made before the copy are in the snapshot, and only there
+        let guard = backend.lock_shared();
+        enable(&backend)?;
+        backend.set("during".into(), "3".into());
+        backend
+            .aof()
+            .lock()
+            .append(&[request(&["set", "during", "3"])]);
+        drop(guard);
+        wait_for_rewrite(&backend);
+        backend
+            .aof()
+            .lock()
+            .append(&[request(&["set", "after", "4"])]);
+        let data = fs::read(dir.join(AOF_FILENAME))?;
+        let other = Backend::new();
+        let (stats, _) = replay(&data, &other, false)?;
+        assert_eq!((stats.keys, stats.commands), (2, 1));
+        assert_eq!(other.get(b"during"), Ok(Some("3".into())));
+        assert_eq!(other.get(b"after"), Ok(Some("4".into())));
+        disable(&backend)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_fsync_parse() {
+        for policy in AppendFsync::ALL {
+            assert_eq!(AppendFsync::parse(policy.name()), Some(policy));
+        }
+        assert_eq!(AppendFsync::parse("EVERYSEC"), Some(AppendFsync::EverySec));
+        assert_eq!(AppendFsync::parse("sometimes"), None);
+    }
+}
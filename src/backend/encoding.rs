@@ -0,0 +1,100 @@
+// a listpack starts with its total size and element count, and ends with a terminator byte
+pub(crate) const LISTPACK_HEADER_SIZE: usize = 7;
+// the largest listpack a positive list-max-listpack-size allows
+const SIZE_SAFETY_LIMIT: usize = 8192;
+// the listpack sizes selected by list-max-listpack-size -1 to -5
+const LIST_SIZE_LIMITS: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+
+/// Up to which size hashes, sets, sorted sets and lists keep their compact encoding, as set by
+/// the `*-max-listpack-*` and `set-max-intset-entries` parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingLimits {
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+    /// Positive for a number of elements, -1 to -5 for a size of 4 to 64 KiB.
+    pub list_max_listpack_size: i64,
+}
+
+impl Default for EncodingLimits {
+    fn default() -> Self {
+        Self {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+        }
+    }
+}
+
+impl EncodingLimits {
+    /// Whether a list of `len` elements taking `bytes` as a listpack can stay one.
+    pub fn list_fits(&self, bytes: usize, len: usize) -> bool {
+        let fill = self.list_max_listpack_size;
+        if fill >= 0 {
+            len <= fill as usize && bytes <= SIZE_SAFETY_LIMIT
+        } else {
+            let level = (fill.unsigned_abs() as usize).min(LIST_SIZE_LIMITS.len());
+            bytes <= LIST_SIZE_LIMITS[level - 1]
+        }
+    }
+}
+
+/// The size of `value` as a listpack entry: encoding, payload and back length.
+pub(crate) fn listpack_entry_size(value: &[u8]) -> usize {
+    let encoded = match super::value::canonical_int(value) {
+        Some(v) if (0..=127).contains(&v) => 1,
+        Some(v) if (-4096..4096).contains(&v) => 2,
+        Some(v) if i16::try_from(v).is_ok() => 3,
+        Some(v) if (-(1 << 23)..(1 << 23)).contains(&v) => 4,
+        Some(v) if i32::try_from(v).is_ok() => 5,
+        Some(_) => 9,
+        None if value.len() < 64 => 1 + value.len(),
+        None if value.len() < 4096 => 2 + value.len(),
+        None => 5 + value.len(),
+    };
+    let backlen = match encoded {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    };
+    encoded + backlen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_fits() {
+        let limits = EncodingLimits::default();
+        assert!(limits.list_fits(8192, 10_000));
+        assert!(!limits.list_fits(8193, 1));
+
+        let limits = EncodingLimits {
+            list_max_listpack_size: 3,
+            ..EncodingLimits::default()
+        };
+        assert!(limits.list_fits(100, 3));
+        assert!(!limits.list_fits(100, 4));
+        assert!(!limits.list_fits(SIZE_SAFETY_LIMIT + 1, 1));
+    }
+
+    #[test]
+    fn test_listpack_entry_size() {
+        assert_eq!(listpack_entry_size(b"7"), 2);
+        assert_eq!(listpack_entry_size(b"1000"), 3);
+        assert_eq!(listpack_entry_size(b"hello"), 7);
+        assert_eq!(listpack_entry_size(&[b'x'; 200]), 204);
+    }
+}
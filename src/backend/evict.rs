@@ -0,0 +1,271 @@
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use dashmap::DashMap;
+
+use super::{keyspace_event, memory::random, Backend, MaxMemoryPolicy};
+
+// the best candidates seen so far are kept across samples, like the redis eviction pool
+const EVICTION_POOL_SIZE: usize = 16;
+
+impl Backend {
+    /// Evict keys as `maxmemory-policy` says until the used memory is back under `maxmemory`,
+    /// returning whether it is.
+    pub fn perform_evictions(&self) -> bool {
+        let maxmemory = self.memory.maxmemory();
+        if maxmemory == 0 {
+            return true;
+        }
+        let policy = self.memory.policy();
+        // candidates ordered from the least to the most evictable
+        let mut pool: Vec<(u64, Bytes)> = Vec::with_capacity(EVICTION_POOL_SIZE + 1);
+        while self.used_memory() > maxmemory {
+            let candidate = match policy {
+                MaxMemoryPolicy::NoEviction => None,
+                MaxMemoryPolicy::AllKeysRandom | MaxMemoryPolicy::VolatileRandom => {
+                    self.sample_keys(policy.is_volatile(), 1).pop()
+                }
+                _ => self.best_candidate(policy, &mut pool),
+            };
+            let Some(key) = candidate else {
+                return false;
+            };
+            if self.remove(&key) {
+                self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                self.notify(keyspace_event::EVICTED, "evicted", &key);
+            }
+        }
+        true
+    }
+    /// Whether the used memory is over `maxmemory`, without evicting anything.
+    pub fn over_maxmemory(&self) -> bool {
+        let maxmemory = self.memory.maxmemory();
+        maxmemory > 0 && self.used_memory() > maxmemory
+    }
+    /// How many keys were evicted so far.
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys.load(Ordering::Relaxed)
+    }
+
+    // sample a few more keys into the pool and take the most evictable one still around
+    fn best_candidate(
+        &self,
+        policy: MaxMemoryPolicy,
+        pool: &mut Vec<(u64, Bytes)>,
+    ) -> Option<Bytes> {
+        for key in self.sample_keys(policy.is_volatile(), self.memory.samples()) {
+            let Some(score) = self.eviction_score(policy, &key) else {
+                continue;
+            };
+            if pool.iter().any(|(_, k)| *k == key) {
+                continue;
+            }
+            if pool.len() == EVICTION_POOL_SIZE && score <= pool[0].0 {
+                continue;
+            }
+            let at = pool.partition_point(|(s, _)| *s < score);
+            pool.insert(at, (score, key));
+            if pool.len() > EVICTION_POOL_SIZE {
+                pool.remove(0);
+            }
+        }
+        while let Some((_, key)) = pool.pop() {
+            if self.keyspace.contains_key(&key) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    // the higher, the better a candidate `key` is
+    fn eviction_score(&self, policy: MaxMemoryPolicy, key: &[u8]) -> Option<u64> {
+        match policy {
+            MaxMemoryPolicy::VolatileTtl => self.expires.get(key).map(|at| u64::MAX - *at),
+            MaxMemoryPolicy::AllKeysLfu | MaxMemoryPolicy::VolatileLfu => self
+                .keyspace
+                .get(key)
+                .map(|entry| (u8::MAX - entry.freq(&self.memory)) as u64),
+            _ => self.keyspace.get(key).map(|entry| entry.idle_ms()),
+        }
+    }
+
+    // up to `count` keys from random places of the keyspace, or of the keys with an expire
+    fn sample_keys(&self, volatile: bool, count: usize) -> Vec<Bytes> {
+        if volatile {
+            sample(&self.expires, count)
+        } else {
+            sample(&self.keyspace, count)
+        }
+    }
+}
+
+// Like dictGetSomeKeys: walk a few buckets from a random one of a random shard, until enough
+// keys were found or too many buckets visited, so that sampling costs the same whatever the
+// size of the map and only ever holds one shard. At least one key is found unless it is empty,
+// and every key when there are no more than `count` of them.
+pub(super) fn sample<V>(map: &DashMap<Bytes, V>, count: usize) -> Vec<Bytes> {
+    if map.len() <= count {
+        return map.iter().map(|entry| entry.key().clone()).collect();
+    }
+    let shards = map.shards();
+    let mut keys = Vec::with_capacity(count);
+    let mut steps = count.saturating_mul(10);
+    while keys.len() < count && (steps > 0 || keys.is_empty()) {
+        let first = random() as usize % shards.len();
+        let table = (0..shards.len())
+            .map(|i| shards[(first + i) % shards.len()].read())
+            .find(|table| !table.is_empty());
+        let Some(table) = table else {
+            break;
+        };
+        let buckets = table.buckets();
+        let start = random() as usize % buckets;
+        let walk = buckets.min(count);
+        for i in 0..walk {
+            let index = (start + i) % buckets;
+            // SAFETY: the index is below the number of buckets, and the read guard keeps the
+            // table from changing while the key is cloned
+            if unsafe { table.is_bucket_full(index) } {
+                let (key, _) = unsafe { table.bucket(index).as_ref() };
+                keys.push(key.clone());
+                if keys.len() == count {
+                    break;
+                }
+            }
+        }
+        steps = steps.saturating_sub(walk);
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{now_ms, Backend, Subscriber};
+
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let map = DashMap::new();
+        assert!(sample(&map, 5).is_empty());
+        map.insert(Bytes::from("only"), ());
+        assert_eq!(sample(&map, 1), vec![Bytes::from("only")]);
+        // however they are spread across shards, a few keys are all found
+        map.insert(Bytes::from("other"), ());
+        let mut keys = sample(&map, 5);
+        keys.sort();
+        assert_eq!(keys, vec![Bytes::from("only"), Bytes::from("other")]);
+        for i in 0..10000 {
+            map.insert(Bytes::from(format!("key:{}", i)), ());
+        }
+        let keys = sample(&map, 5);
+        assert_eq!(keys.len(), 5);
+        assert!(keys.iter().all(|key| map.contains_key(key)));
+    }
+
+    fn fill(backend: &Backend, n: usize) {
+        for i in 0..n {
+            backend.set(format!("key:{}", i).into(), "some value".into());
+        }
+    }
+
+    #[test]
+    fn test_noeviction() {
+        let backend = Backend::new();
+        fill(&backend, 10);
+        assert!(backend.perform_evictions());
+        backend
+            .memory_config()
+            .set_maxmemory(backend.used_memory() / 2);
+        assert!(backend.over_maxmemory());
+        assert!(!backend.perform_evictions());
+        assert_eq!(backend.evicted_keys(), 0);
+        assert!(backend.exists(b"key:0"));
+    }
+
+    #[test]
+    fn test_allkeys_eviction() {
+        for policy in [
+            MaxMemoryPolicy::AllKeysLru,
+            MaxMemoryPolicy::AllKeysLfu,
+            MaxMemoryPolicy::AllKeysRandom,
+        ] {
+            let backend = Backend::new();
+            fill(&backend, 100);
+            let used = backend.used_memory();
+            backend.memory_config().set_policy(policy);
+            backend.memory_config().set_maxmemory(used / 2);
+            assert!(backend.perform_evictions());
+            assert!(backend.used_memory() <= used / 2);
+            assert!(backend.evicted_keys() >= 50);
+            assert!(!backend.over_maxmemory());
+        }
+    }
+
+    #[test]
+    fn test_volatile_eviction() {
+        let backend = Backend::new();
+        fill(&backend, 10);
+        let at = now_ms() + 60_000;
+        backend.pexpire_at(b"key:3", at);
+        backend.pexpire_at(b"key:7", at + 1000);
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::VolatileTtl);
+        backend
+            .memory_config()
+            .set_maxmemory(backend.used_memory() - 1);
+        assert!(backend.perform_evictions());
+        // the key expiring first goes first
+        assert!(!backend.exists(b"key:3"));
+        assert!(backend.exists(b"key:7"));
+
+        // only the keys with an expire can be evicted
+        backend.memory_config().set_maxmemory(1);
+        assert!(!backend.perform_evictions());
+        assert!(!backend.exists(b"key:7"));
+        assert!(backend.exists(b"key:0"));
+        assert_eq!(backend.evicted_keys(), 2);
+    }
+
+    #[test]
+    fn test_lru_evicts_idle_keys() {
+        let backend = Backend::new();
+        fill(&backend, 10);
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::AllKeysLru);
+        backend.memory_config().set_samples(10);
+        // every key but one was used a while ago
+        std::thread::sleep(Duration::from_millis(20));
+        backend.get(b"key:5").unwrap();
+        backend
+            .memory_config()
+            .set_maxmemory(backend.used_memory() - 1);
+        assert!(backend.perform_evictions());
+        assert!(backend.exists(b"key:5"));
+        assert_eq!(backend.evicted_keys(), 1);
+    }
+
+    #[test]
+    fn test_evicted_event() {
+        let backend = Backend::new();
+        let (tx, mut rx) = Subscriber::channel();
+        backend
+            .pubsub()
+            .subscribe(b"__keyevent@0__:evicted", 1, &tx);
+        backend.notify_config().set("Ee").unwrap();
+        fill(&backend, 3);
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::AllKeysRandom);
+        backend.memory_config().set_maxmemory(1);
+        assert!(backend.perform_evictions());
+        for _ in 0..3 {
+            assert!(rx.try_recv().is_ok());
+        }
+        assert!(rx.try_recv().is_err());
+    }
+}
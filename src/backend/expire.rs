@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+use super::{evict::sample, Backend};
+
+// keys with an expire looked at per round, and the percentage of them found expired that is
+// worth another round, like ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP and ACCEPTABLE_STALE
+const KEYS_PER_LOOP: usize = 20;
+const ACCEPTABLE_STALE: usize = 10;
+// a quarter of the cron interval, like the slow cycle of redis
+const CYCLE_TIME_LIMIT: Duration = Duration::from_millis(25);
+
+impl Backend {
+    /// Remove keys whose expire passed while nothing accessed them, sampling the keys with an
+    /// expire again as long as enough of them turn out expired and the cycle is within its
+    /// time limit. Returns how many keys expired.
+    pub fn active_expire_cycle(&self) -> usize {
+        let start = Instant::now();
+        let mut expired = 0;
+        loop {
+            let keys = sample(&self.expires, KEYS_PER_LOOP);
+            let stale = keys.iter().filter(|key| self.expire_if_needed(key)).count();
+            expired += stale;
+            if stale * 100 <= keys.len() * ACCEPTABLE_STALE || start.elapsed() > CYCLE_TIME_LIMIT {
+                return expired;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::{now_ms, Backend, Subscriber};
+
+    #[test]
+    fn test_active_expire_cycle() {
+        let backend = Backend::new();
+        let (tx, mut rx) = Subscriber::channel();
+        backend
+            .pubsub()
+            .subscribe(b"__keyevent@0__:expired", 1, &tx);
+        backend.notify_config().set("Ex").unwrap();
+        for i in 0..100 {
+            let key = format!("key:{}", i);
+            backend.set(Bytes::from(key.clone()), Bytes::from("v"));
+            backend.pexpire_at(key.as_bytes(), now_ms() + 10);
+        }
+        backend.set(Bytes::from("persistent"), Bytes::from("v"));
+        backend.set(Bytes::from("later"), Bytes::from("v"));
+        backend.pexpire_at(b"later", now_ms() + 60_000);
+        std::thread::sleep(Duration::from_millis(20));
+
+        // nothing touches the keys, the cycle finds them expired on its own
+        assert_eq!(backend.active_expire_cycle(), 100);
+        for _ in 0..100 {
+            assert!(rx.try_recv().is_ok());
+        }
+        assert!(rx.try_recv().is_err());
+        assert_eq!(backend.keyspace.len(), 2);
+        assert_eq!(backend.expires.len(), 1);
+        assert_eq!(backend.active_expire_cycle(), 0);
+    }
+}
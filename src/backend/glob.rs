@@ -0,0 +1,116 @@
+/// Redis style glob matching: `*`, `?`, `[abc]`, `[^a-z]` and `\` to escape.
+///
+/// Iterative: on a mismatch only the last `*` seen is retried one byte further, which keeps the
+/// matching linear in the pattern times the string however many stars the pattern holds.
+pub(crate) fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // pattern position after the last star, and the string position it is tried from
+    let mut star = None;
+    while i < s.len() {
+        if pattern.get(p) == Some(&b'*') {
+            // consecutive stars match the same as a single one
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+            if p == pattern.len() {
+                return true;
+            }
+            star = Some((p, i));
+            continue;
+        }
+        match match_one(&pattern[p..], s[i]) {
+            Some(len) => {
+                p += len;
+                i += 1;
+            }
+            None => match star {
+                Some((star_p, star_i)) => {
+                    p = star_p;
+                    i = star_i + 1;
+                    star = Some((star_p, i));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// matches `c` against the token at the start of `pattern`, returning how long the token is
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern {
+        [] => None,
+        [b'?', ..] => Some(1),
+        [b'[', rest @ ..] => match match_class(rest, c) {
+            Some((true, after)) => Some(pattern.len() - after.len()),
+            _ => None,
+        },
+        [b'\\', escaped, ..] => (*escaped == c).then_some(2),
+        [p, ..] => (*p == c).then_some(1),
+    }
+}
+
+// matches `c` against the class following a `[`, returning the pattern after the closing `]`
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negate, mut pattern) = match pattern.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negate, rest)),
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (start, end) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (start..=end).contains(&c);
+                pattern = rest;
+            }
+            [p, rest @ ..] => {
+                matched |= *p == c;
+                pattern = rest;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"news.*", b"news.tech"));
+        assert!(!glob_match(b"news.*", b"new.tech"));
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"axb"));
+        assert!(!glob_match(b"h[ae", b"ha"));
+        assert!(glob_match(b"*a*b", b"xaxxb"));
+        assert!(!glob_match(b"a*", b""));
+        assert!(glob_match(b"a**", b"a"));
+    }
+
+    #[test]
+    fn test_glob_match_many_stars() {
+        // exponential for a matcher that retries every star recursively
+        let s = vec![b'a'; 100];
+        assert!(!glob_match(b"*a*a*a*a*a*a*a*a*a*a*b", &s));
+        assert!(glob_match(b"*a*a*a*a*a*a*a*a*a*a*", &s));
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ParserLimits;
+
+/// The parser limits that can be changed at runtime, every connection picks up the current
+/// values before it reads its next request.
+#[derive(Debug)]
+pub struct ProtoLimits {
+    max_bulk_len: AtomicUsize,
+    max_query_buffer: AtomicUsize,
+}
+
+impl Default for ProtoLimits {
+    fn default() -> Self {
+        let limits = ParserLimits::default();
+        Self {
+            max_bulk_len: AtomicUsize::new(limits.max_bulk_len),
+            max_query_buffer: AtomicUsize::new(limits.max_query_buffer),
+        }
+    }
+}
+
+impl ProtoLimits {
+    pub fn max_bulk_len(&self) -> usize {
+        self.max_bulk_len.load(Ordering::Relaxed)
+    }
+    pub fn set_max_bulk_len(&self, len: usize) {
+        self.max_bulk_len.store(len, Ordering::Relaxed);
+    }
+    pub fn max_query_buffer(&self) -> usize {
+        self.max_query_buffer.load(Ordering::Relaxed)
+    }
+    pub fn set_max_query_buffer(&self, len: usize) {
+        self.max_query_buffer.store(len, Ordering::Relaxed);
+    }
+
+    pub fn parser_limits(&self) -> ParserLimits {
+        ParserLimits {
+            max_bulk_len: self.max_bulk_len(),
+            max_query_buffer: self.max_query_buffer(),
+            ..ParserLimits::default()
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+
+use super::{
+    encoding::{listpack_entry_size, LISTPACK_HEADER_SIZE},
+    now_ms, HashValue, SetValue, StoredValue, StringValue, ZSetValue,
+};
+
+// the size of a redis object header
+const OBJECT_SIZE: usize = 16;
+// a hash table entry: key, value and next pointers
+const DICT_ENTRY_SIZE: usize = 24;
+// a hash table with its two bucket arrays
+const DICT_SIZE: usize = 56;
+// a skiplist node with its score, backward pointer and an average of 1.33 levels
+const SKIPLIST_NODE_SIZE: usize = 40;
+// a quicklist header and each of its nodes
+const QUICKLIST_SIZE: usize = 40;
+const QUICKLIST_NODE_SIZE: usize = 32;
+// lists don't let a node grow past 8 KiB, whatever the fill factor
+const QUICKLIST_NODE_BYTES: usize = 8192;
+// a stream entry id and the radix tree node holding it, roughly
+const STREAM_ENTRY_SIZE: usize = 32;
+// the number of elements sampled to estimate the size of a large value
+pub const DEFAULT_SAMPLES: usize = 5;
+// the counter a new key starts with, so it isn't evicted before it gets a chance to be used
+const LFU_INIT_VAL: u8 = 5;
+
+/// What happens once the used memory goes over `maxmemory`, as set by `maxmemory-policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxMemoryPolicy {
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl MaxMemoryPolicy {
+    pub const ALL: [MaxMemoryPolicy; 8] = [
+        MaxMemoryPolicy::NoEviction,
+        MaxMemoryPolicy::AllKeysLru,
+        MaxMemoryPolicy::AllKeysLfu,
+        MaxMemoryPolicy::AllKeysRandom,
+        MaxMemoryPolicy::VolatileLru,
+        MaxMemoryPolicy::VolatileLfu,
+        MaxMemoryPolicy::VolatileRandom,
+        MaxMemoryPolicy::VolatileTtl,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaxMemoryPolicy::NoEviction => "noeviction",
+            MaxMemoryPolicy::AllKeysLru => "allkeys-lru",
+            MaxMemoryPolicy::AllKeysLfu => "allkeys-lfu",
+            MaxMemoryPolicy::AllKeysRandom => "allkeys-random",
+            MaxMemoryPolicy::VolatileLru => "volatile-lru",
+            MaxMemoryPolicy::VolatileLfu => "volatile-lfu",
+            MaxMemoryPolicy::VolatileRandom => "volatile-random",
+            MaxMemoryPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+    /// Whether only the keys with an expire can be evicted.
+    pub fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            MaxMemoryPolicy::VolatileLru
+                | MaxMemoryPolicy::VolatileLfu
+                | MaxMemoryPolicy::VolatileRandom
+                | MaxMemoryPolicy::VolatileTtl
+        )
+    }
+}
+
+/// The memory limit and how to stay under it, changed at runtime through CONFIG SET.
+#[derive(Debug)]
+pub struct MemoryConfig {
+    maxmemory: AtomicUsize,
+    policy: AtomicU8,
+    samples: AtomicUsize,
+    lfu_log_factor: AtomicU64,
+    lfu_decay_time: AtomicU64,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            maxmemory: AtomicUsize::new(0),
+            policy: AtomicU8::new(0),
+            samples: AtomicUsize::new(5),
+            lfu_log_factor: AtomicU64::new(10),
+            lfu_decay_time: AtomicU64::new(1),
+        }
+    }
+}
+
+impl MemoryConfig {
+    /// The memory limit in bytes, 0 for none.
+    pub fn maxmemory(&self) -> usize {
+        self.maxmemory.load(Ordering::Relaxed)
+    }
+    pub fn set_maxmemory(&self, bytes: usize) {
+        self.maxmemory.store(bytes, Ordering::Relaxed);
+    }
+    pub fn policy(&self) -> MaxMemoryPolicy {
+        MaxMemoryPolicy::ALL[self.policy.load(Ordering::Relaxed) as usize]
+    }
+    pub fn set_policy(&self, policy: MaxMemoryPolicy) {
+        let index = MaxMemoryPolicy::ALL.iter().position(|p| *p == policy);
+        self.policy
+            .store(index.unwrap_or_default() as u8, Ordering::Relaxed);
+    }
+    /// How many keys are sampled for each eviction.
+    pub fn samples(&self) -> usize {
+        self.samples.load(Ordering::Relaxed)
+    }
+    pub fn set_samples(&self, samples: usize) {
+        self.samples.store(samples, Ordering::Relaxed);
+    }
+    /// How many hits it takes for the access counter to grow, higher means slower.
+    pub fn lfu_log_factor(&self) -> u64 {
+        self.lfu_log_factor.load(Ordering::Relaxed)
+    }
+    pub fn set_lfu_log_factor(&self, factor: u64) {
+        self.lfu_log_factor.store(factor, Ordering::Relaxed);
+    }
+    /// The minutes it takes for the access counter of an idle key to drop by one, 0 to never
+    /// decay.
+    pub fn lfu_decay_time(&self) -> u64 {
+        self.lfu_decay_time.load(Ordering::Relaxed)
+    }
+    pub fn set_lfu_decay_time(&self, minutes: u64) {
+        self.lfu_decay_time.store(minutes, Ordering::Relaxed);
+    }
+}
+
+/// A value in the keyspace, along with its approximate size and how it is being accessed.
+#[derive(Debug)]
+pub(crate) struct Entry {
+    // shared with the snapshots being saved, copied by the first write made meanwhile
+    pub(crate) value: Arc<StoredValue>,
+    // approximate bytes taken by the key and its value
+    pub(crate) size: usize,
+    // last access, in unix milliseconds
+    accessed: AtomicU64,
+    // logarithmic access counter, as used by the lfu policies
+    freq: AtomicU8,
+}
+
+impl Entry {
+    pub(crate) fn new(key: &[u8], value: StoredValue) -> Self {
+        Self {
+            size: key_size(key) + value.memory_usage(DEFAULT_SAMPLES),
+            value: Arc::new(value),
+            accessed: AtomicU64::new(now_ms()),
+            freq: AtomicU8::new(LFU_INIT_VAL),
+        }
+    }
+    /// Measure the size again after the value changed, returning the previous one.
+    pub(crate) fn resize(&mut self, key: &[u8]) -> usize {
+        let size = key_size(key) + self.value.memory_usage(DEFAULT_SAMPLES);
+        std::mem::replace(&mut self.size, size)
+    }
+    /// Record a hit on the key.
+    pub(crate) fn access(&self, config: &MemoryConfig) {
+        let freq = self.freq(config);
+        let freq = lfu_log_incr(freq, config.lfu_log_factor());
+        self.freq.store(freq, Ordering::Relaxed);
+        self.accessed.store(now_ms(), Ordering::Relaxed);
+    }
+    /// Pretend the last access was `ms` milliseconds ago.
+    pub(crate) fn set_idle_ms(&self, ms: u64) {
+        self.accessed
+            .store(now_ms().saturating_sub(ms), Ordering::Relaxed);
+    }
+    pub(crate) fn set_freq(&self, freq: u8) {
+        self.freq.store(freq, Ordering::Relaxed);
+        // the counter decays from the last access
+        self.accessed.store(now_ms(), Ordering::Relaxed);
+    }
+    /// Milliseconds since the last access.
+    pub(crate) fn idle_ms(&self) -> u64 {
+        now_ms().saturating_sub(self.accessed.load(Ordering::Relaxed))
+    }
+    /// The access counter, decayed by the time the key has been idle.
+    pub(crate) fn freq(&self, config: &MemoryConfig) -> u8 {
+        let freq = self.freq.load(Ordering::Relaxed);
+        let periods = match config.lfu_decay_time() {
+            0 => 0,
+            minutes => self.idle_ms() / 60_000 / minutes,
+        };
+        freq.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
+
+// grow the counter with a probability getting lower as it gets higher, so that 255 stands for
+// about a million hits with the default factor of 10
+fn lfu_log_incr(freq: u8, factor: u64) -> u8 {
+    if freq == u8::MAX {
+        return freq;
+    }
+    let base = freq.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * factor as f64 + 1.0);
+    let r = random() as f64 / u64::MAX as f64;
+    if r < p {
+        freq + 1
+    } else {
+        freq
+    }
+}
+
+/// A random number, good enough to sample keys with.
+pub(crate) fn random() -> u64 {
+    // every RandomState gets new keys, hashing nothing with them is as random as they are
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(now_ms());
+    hasher.finish()
+}
+
+// a key is a sds string pointed at from a hash table entry
+pub(crate) fn key_size(key: &[u8]) -> usize {
+    DICT_ENTRY_SIZE + sds_size(key.len())
+}
+
+// a sds string with its header and terminator, rounded up to the allocator's 8 bytes
+fn sds_size(len: usize) -> usize {
+    let header = match len {
+        0..=0xff => 3,
+        0x100..=0xffff => 5,
+        0x10000..=0xffff_ffff => 9,
+        _ => 17,
+    };
+    (header + len + 1).next_multiple_of(8)
+}
+
+// a hash table holding `len` entries, without what they point to
+fn dict_size(len: usize) -> usize {
+    DICT_SIZE + len.next_power_of_two() * 8 + len * DICT_ENTRY_SIZE
+}
+
+// the size of `count` elements, extrapolated from the first `samples` sizes, all of them when
+// `samples` is 0
+fn sampled(count: usize, samples: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let samples = match samples {
+        0 => count,
+        n => n.min(count),
+    };
+    if samples == 0 {
+        return 0;
+    }
+    let total = sizes.take(samples).sum::<usize>();
+    (total as f64 / samples as f64 * count as f64) as usize
+}
+
+fn listpack_size<'a>(values: impl Iterator<Item = &'a [u8]>) -> usize {
+    LISTPACK_HEADER_SIZE + values.map(listpack_entry_size).sum::<usize>()
+}
+
+impl StoredValue {
+    /// Approximate bytes taken by the value, like redis estimating large collections from
+    /// `samples` of their elements, or all of them when 0.
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        OBJECT_SIZE
+            + match self {
+                StoredValue::String(StringValue::Int(_)) => 0,
+                StoredValue::String(StringValue::Embstr(v) | StringValue::Raw(v)) => {
+                    sds_size(v.len())
+                }
+                StoredValue::Hash(HashValue::Listpack(pairs)) => listpack_size(
+                    pairs
+                        .iter()
+                        .flat_map(|(field, value)| [field.as_ref(), value.as_ref()]),
+                ),
+                StoredValue::Hash(hash @ HashValue::Hashtable(_)) => {
+                    dict_size(hash.len())
+                        + sampled(
+                            hash.len(),
+                            samples,
+                            hash.iter().map(|(field, value)| {
+                                sds_size(field.len()) + sds_size(value.len())
+                            }),
+                        )
+                }
+                StoredValue::Set(SetValue::Intset(members)) => {
+                    let width = match members.iter().map(|v| v.unsigned_abs()).max() {
+                        Some(v) if v > i32::MAX as u64 => 8,
+                        Some(v) if v > i16::MAX as u64 => 4,
+                        _ => 2,
+                    };
+                    8 + members.len() * width
+                }
+                StoredValue::Set(SetValue::Listpack(members)) => {
+                    listpack_size(members.iter().map(Bytes::as_ref))
+                }
+                StoredValue::Set(SetValue::Hashtable(members)) => {
+                    dict_size(members.len())
+                        + sampled(
+                            members.len(),
+                            samples,
+                            members.iter().map(|member| sds_size(member.len())),
+                        )
+                }
+                StoredValue::List(list) => {
+                    let bytes = LISTPACK_HEADER_SIZE
+                        + sampled(
+                            list.len(),
+                            samples,
+                            list.iter().map(|value| listpack_entry_size(value)),
+                        );
+                    if list.encoding() == "quicklist" {
+                        let nodes = bytes.div_ceil(QUICKLIST_NODE_BYTES);
+                        QUICKLIST_SIZE
+                            + nodes * (QUICKLIST_NODE_SIZE + LISTPACK_HEADER_SIZE)
+                            + bytes
+                    } else {
+                        bytes
+                    }
+                }
+                StoredValue::ZSet(ZSetValue::Listpack(members)) => {
+                    LISTPACK_HEADER_SIZE
+                        + members
+                            .iter()
+                            .map(|(member, score)| {
+                                listpack_entry_size(member)
+                                    + listpack_entry_size(score.to_string().as_bytes())
+                            })
+                            .sum::<usize>()
+                }
+                StoredValue::ZSet(zset @ ZSetValue::Skiplist { .. }) => {
+                    dict_size(zset.len())
+                        + sampled(
+                            zset.len(),
+                            samples,
+                            zset.iter()
+                                .map(|(member, _)| sds_size(member.len()) + SKIPLIST_NODE_SIZE),
+                        )
+                }
+                StoredValue::Stream(stream) => sampled(
+                    stream.entries.len(),
+                    samples,
+                    stream.entries.values().map(|fields| {
+                        STREAM_ENTRY_SIZE
+                            + listpack_size(
+                                fields
+                                    .iter()
+                                    .flat_map(|(field, value)| [field.as_ref(), value.as_ref()]),
+                            )
+                    }),
+                ),
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Backend, EncodingLimits, WrongType};
+
+    use super::*;
+
+    #[test]
+    fn test_policy_names() {
+        for policy in MaxMemoryPolicy::ALL {
+            assert_eq!(MaxMemoryPolicy::parse(policy.name()), Some(policy));
+        }
+        assert_eq!(
+            MaxMemoryPolicy::parse("ALLKEYS-LRU"),
+            Some(MaxMemoryPolicy::AllKeysLru)
+        );
+        assert_eq!(MaxMemoryPolicy::parse("lru"), None);
+        assert!(MaxMemoryPolicy::VolatileTtl.is_volatile());
+        assert!(!MaxMemoryPolicy::AllKeysRandom.is_volatile());
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let limits = EncodingLimits::default();
+        let int = StoredValue::String(StringValue::new("12345"));
+        let short = StoredValue::String(StringValue::new("hello"));
+        let long = StoredValue::String(StringValue::new(vec![b'x'; 1000]));
+        assert_eq!(int.memory_usage(0), OBJECT_SIZE);
+        assert!(short.memory_usage(0) > int.memory_usage(0));
+        assert!(long.memory_usage(0) > 1000);
+
+        // elements of the same size extrapolate exactly
+        let members = (0..1000).map(|i| Bytes::from(format!("member:{:04}", i)));
+        let set = StoredValue::Set(SetValue::from_members(members, &limits));
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.memory_usage(5), set.memory_usage(0));
+
+        let small = StoredValue::Set(SetValue::from_members(["a".into()], &limits));
+        assert!(small.memory_usage(0) < set.memory_usage(0));
+    }
+
+    #[test]
+    fn test_used_memory_accounting() -> Result<(), WrongType> {
+        let backend = Backend::new();
+        backend.set("string".into(), "hello".into());
+        backend.hset("hash".into(), "field".into(), "value".into())?;
+        backend.sadd("set".into(), &["a".into()])?;
+        backend.lpush("list".into(), &["a".into()])?;
+        backend.rpush("list".into(), &["b".into()])?;
+        backend.zadd("zset".into(), &[("a".into(), 1.0)])?;
+        assert!(backend.used_memory() > 0);
+        for key in ["string", "hash", "set", "list", "zset"] {
+            assert!(backend.del(key.as_bytes()));
+        }
+        assert_eq!(backend.used_memory(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lfu_counter() {
+        let config = MemoryConfig::default();
+        let entry = Entry::new(b"key", StoredValue::String("v".into()));
+        assert_eq!(entry.freq(&config), LFU_INIT_VAL);
+        // the first hits above the initial value always count
+        assert_eq!(lfu_log_incr(LFU_INIT_VAL, 10), LFU_INIT_VAL + 1);
+        assert_eq!(lfu_log_incr(u8::MAX, 10), u8::MAX);
+        for _ in 0..100 {
+            entry.access(&config);
+        }
+        let freq = entry.freq(&config);
+        assert!(freq > LFU_INIT_VAL && freq < 100);
+
+        // idle for two minutes with a one minute decay time
+        entry
+            .accessed
+            .store(now_ms() - 2 * 60_000, Ordering::Relaxed);
+        assert_eq!(entry.freq(&config), freq - 2);
+        config.set_lfu_decay_time(0);
+        assert_eq!(entry.freq(&config), freq);
+    }
+}
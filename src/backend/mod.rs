@@ -1,17 +1,81 @@
-use std::{ops::Deref, sync::Arc};
+mod encoding;
+mod evict;
+mod expire;
+mod glob;
+mod limits;
+mod memory;
+mod notify;
+mod persist;
+mod pubsub;
+mod slot;
+mod value;
 
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
 use dashmap::DashMap;
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock as ExecLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{aof::Aof, ScriptEngine};
 
-use crate::RespFrame;
+pub use encoding::EncodingLimits;
+pub(crate) use glob::glob_match;
+pub use limits::ProtoLimits;
+use memory::{key_size, Entry};
+pub use memory::{MaxMemoryPolicy, MemoryConfig, DEFAULT_SAMPLES};
+pub use notify::{keyspace_event, NotifyConfig};
+use persist::SaveState;
+pub use persist::{KeySnapshot, SaveConfig, SaveRule};
+pub use pubsub::{PubSubRegistry, ShardPubSubRegistry, Subscriber};
+pub use slot::{key_slot, CLUSTER_SLOTS};
+pub use value::{
+    HashValue, ListValue, ScoredMember, SetValue, StoredValue, Stream, StreamId, StringValue,
+    WrongType, ZSetValue,
+};
 #[derive(Debug, Clone)]
 pub struct Backend(Arc<BackendInner>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct BackendInner {
-    map: DashMap<String, RespFrame>,
-    hmap: DashMap<String, DashMap<String, RespFrame>>,
-    sis: DashMap<String, Vec<String>>,
+    keyspace: DashMap<Bytes, Entry>,
+    // sum of the sizes of every entry, and the most it ever was
+    used_memory: AtomicUsize,
+    peak_memory: AtomicUsize,
+    // absolute expire time of a key, in unix milliseconds
+    expires: DashMap<Bytes, u64>,
+    // flags of the sessions watching a key, raised whenever the key is modified
+    watched: DashMap<Bytes, Vec<Arc<AtomicBool>>>,
+    // commands run under the shared side, transactions under the exclusive side, and both wait
+    // their turn in a queue so that neither side starves the other
+    exec_lock: Arc<ExecLock<()>>,
+    scripts: ScriptEngine,
+    pubsub: PubSubRegistry,
+    shard_pubsub: ShardPubSubRegistry,
+    notify: NotifyConfig,
+    proto_limits: ProtoLimits,
+    encoding_limits: RwLock<EncodingLimits>,
+    memory: MemoryConfig,
+    evicted_keys: AtomicU64,
+    // changes made since the last successful save
+    dirty: AtomicU64,
+    save_config: SaveConfig,
+    save_state: SaveState,
+    aof: Aof,
 }
+
+/// Either side of the backend execution lock.
+pub enum ExecGuard<'a> {
+    Shared(RwLockReadGuard<'a, ()>),
+    Exclusive(RwLockWriteGuard<'a, ()>),
+}
+
 impl Deref for Backend {
     type Target = BackendInner;
     fn deref(&self) -> &Self::Target {
@@ -35,48 +99,500 @@ impl Default for Backend {
 impl Backend {
     pub fn new() -> Self {
         Backend(Arc::new(BackendInner {
-            map: DashMap::new(),
-            hmap: DashMap::new(),
-            sis: DashMap::new(),
+            keyspace: DashMap::new(),
+            used_memory: AtomicUsize::new(0),
+            peak_memory: AtomicUsize::new(0),
+            expires: DashMap::new(),
+            watched: DashMap::new(),
+            exec_lock: Arc::new(ExecLock::new(())),
+            scripts: ScriptEngine::new(),
+            pubsub: PubSubRegistry::default(),
+            shard_pubsub: ShardPubSubRegistry::default(),
+            notify: NotifyConfig::default(),
+            proto_limits: ProtoLimits::default(),
+            encoding_limits: RwLock::new(EncodingLimits::default()),
+            memory: MemoryConfig::default(),
+            evicted_keys: AtomicU64::new(0),
+            dirty: AtomicU64::new(0),
+            save_config: SaveConfig::default(),
+            save_state: SaveState::default(),
+            aof: Aof::default(),
         }))
     }
-    pub fn get(&self, key: &str) -> Option<RespFrame> {
-        self.map.get(key).map(|v| v.value().clone())
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>, WrongType> {
+        self.read(key, |v| Ok(v.as_string()?.to_bytes()))
+    }
+    pub fn set(&self, key: Bytes, value: Bytes) {
+        self.touch(&key);
+        self.expires.remove(&key);
+        self.insert(key.clone(), StoredValue::String(StringValue::new(value)));
+        self.notify(keyspace_event::STRING, "set", &key);
+    }
+    pub fn hget(&self, key: &[u8], field: &[u8]) -> Result<Option<Bytes>, WrongType> {
+        self.read(key, |v| Ok(v.as_hash()?.get(field).cloned()))
+            .map(Option::flatten)
+    }
+    pub fn hset(&self, key: Bytes, field: Bytes, value: Bytes) -> Result<(), WrongType> {
+        let limits = self.encoding_limits();
+        self.write(&key, StoredValue::Hash, |v| {
+            v.as_hash_mut()?.insert(field, value, &limits);
+            Ok(())
+        })?;
+        self.notify(keyspace_event::HASH, "hset", &key);
+        Ok(())
+    }
+    pub fn hgetall(&self, key: &[u8]) -> Result<Vec<(Bytes, Bytes)>, WrongType> {
+        self.read(key, |v| {
+            Ok(v.as_hash()?
+                .iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect())
+        })
+        .map(Option::unwrap_or_default)
+    }
+    pub fn sadd(&self, key: Bytes, members: &[Bytes]) -> Result<i64, WrongType> {
+        let limits = self.encoding_limits();
+        let added = self.write(&key, StoredValue::Set, |v| {
+            let set = v.as_set_mut()?;
+            let mut added = 0;
+            for member in members {
+                if set.insert(member.clone(), &limits) {
+                    added += 1;
+                }
+            }
+            Ok(added)
+        })?;
+        if added > 0 {
+            self.notify(keyspace_event::SET, "sadd", &key);
+        }
+        Ok(added)
+    }
+    pub fn sismember(&self, key: &[u8], member: &[u8]) -> Result<i64, WrongType> {
+        let ret = self
+            .read(key, |v| Ok(v.as_set()?.contains(member)))?
+            .unwrap_or(false);
+        Ok(ret as i64)
+    }
+    pub fn lpush(&self, key: Bytes, values: &[Bytes]) -> Result<i64, WrongType> {
+        let limits = self.encoding_limits();
+        let len = self.write(&key, StoredValue::List, |v| {
+            let list = v.as_list_mut()?;
+            list.push_front(values.iter().cloned(), &limits);
+            Ok(list.len() as i64)
+        })?;
+        self.notify(keyspace_event::LIST, "lpush", &key);
+        Ok(len)
+    }
+    pub fn rpush(&self, key: Bytes, values: &[Bytes]) -> Result<i64, WrongType> {
+        let limits = self.encoding_limits();
+        let len = self.write(&key, StoredValue::List, |v| {
+            let list = v.as_list_mut()?;
+            list.push_back(values.iter().cloned(), &limits);
+            Ok(list.len() as i64)
+        })?;
+        self.notify(keyspace_event::LIST, "rpush", &key);
+        Ok(len)
+    }
+    pub fn lrange(&self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Bytes>, WrongType> {
+        self.read(key, |v| {
+            let list = v.as_list()?;
+            Ok(match normalize_range(start, stop, list.len()) {
+                Some((start, stop)) => list.range(start, stop),
+                None => vec![],
+            })
+        })
+        .map(Option::unwrap_or_default)
+    }
+    pub fn zadd(&self, key: Bytes, members: &[(Bytes, f64)]) -> Result<i64, WrongType> {
+        let limits = self.encoding_limits();
+        let added = self.write(&key, StoredValue::ZSet, |v| {
+            let zset = v.as_zset_mut()?;
+            let mut added = 0;
+            for (member, score) in members {
+                if zset.insert(member.clone(), *score, &limits) {
+                    added += 1;
+                }
+            }
+            Ok(added)
+        })?;
+        self.notify(keyspace_event::ZSET, "zadd", &key);
+        Ok(added)
+    }
+    pub fn zrange(
+        &self,
+        key: &[u8],
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<(Bytes, f64)>, WrongType> {
+        self.read(key, |v| {
+            let zset = v.as_zset()?;
+            Ok(match normalize_range(start, stop, zset.len()) {
+                Some((start, stop)) => zset.range(start, stop),
+                None => vec![],
+            })
+        })
+        .map(Option::unwrap_or_default)
+    }
+    pub fn exists(&self, key: &[u8]) -> bool {
+        self.expire_if_needed(key);
+        self.keyspace.contains_key(key)
+    }
+    pub fn del(&self, key: &[u8]) -> bool {
+        let removed = self.remove(key);
+        if removed {
+            self.notify(keyspace_event::GENERIC, "del", key);
+        }
+        removed
+    }
+    /// Delete `key` only if it still holds `value`, for MIGRATE which copies keys without
+    /// holding them. The value is compared and the key removed under the lock of its shard.
+    pub fn del_if_unchanged(&self, key: &[u8], value: &StoredValue) -> bool {
+        self.expire_if_needed(key);
+        let unchanged = |_: &Bytes, entry: &Entry| {
+            let unchanged = *entry.value == *value;
+            if unchanged {
+                self.expires.remove(key);
+            }
+            unchanged
+        };
+        let Some((_, entry)) = self.keyspace.remove_if(key, unchanged) else {
+            return false;
+        };
+        self.used_memory.fetch_sub(entry.size, Ordering::Relaxed);
+        self.touch(key);
+        self.notify(keyspace_event::GENERIC, "del", key);
+        true
     }
-    pub fn set(&self, key: String, value: RespFrame) {
-        self.map.insert(key, value);
+    // delete `key` without telling anyone but its watchers
+    fn remove(&self, key: &[u8]) -> bool {
+        self.expires.remove(key);
+        let Some((_, entry)) = self.keyspace.remove(key) else {
+            return false;
+        };
+        self.used_memory.fetch_sub(entry.size, Ordering::Relaxed);
+        self.touch(key);
+        true
+    }
+    /// Remove every key, flagging the sessions that watch any of them.
+    pub fn flush(&self) {
+        let watched = self
+            .watched
+            .iter()
+            .map(|v| v.key().clone())
+            .collect::<Vec<_>>();
+        for key in watched {
+            if self.exists(&key) {
+                self.touch(&key);
+            }
+        }
+        self.keyspace.retain(|_, entry| {
+            self.used_memory.fetch_sub(entry.size, Ordering::Relaxed);
+            self.dirty.fetch_add(1, Ordering::Relaxed);
+            false
+        });
+        self.expires.clear();
     }
-    pub fn hget(&self, key: &str, field: &str) -> Option<RespFrame> {
-        self.hmap
+    /// Set the absolute expire time of an existing key, in unix milliseconds.
+    pub fn pexpire_at(&self, key: &[u8], at: u64) -> bool {
+        if !self.exists(key) {
+            return false;
+        }
+        self.touch(key);
+        // an expire in the past deletes the key right away
+        if at <= now_ms() {
+            return self.del(key);
+        }
+        self.expires.insert(Bytes::copy_from_slice(key), at);
+        self.notify(keyspace_event::GENERIC, "expire", key);
+        true
+    }
+    /// Remaining time to live in milliseconds, `None` if the key has no expire.
+    pub fn pttl(&self, key: &[u8]) -> Option<u64> {
+        self.expire_if_needed(key);
+        self.expires
             .get(key)
-            .and_then(|v| v.get(field).map(|v| v.value().clone()))
+            .map(|at| at.value().saturating_sub(now_ms()))
+    }
+    /// A copy of whatever is stored under `key`.
+    pub fn get_value(&self, key: &[u8]) -> Option<StoredValue> {
+        self.read(key, |v| Ok(v.clone())).unwrap_or_default()
+    }
+    /// Replace whatever is stored under `key` with `value`, optionally expiring at `expire_at`.
+    pub fn set_value(&self, key: Bytes, value: StoredValue, expire_at: Option<u64>) {
+        self.remove(&key);
+        self.touch(&key);
+        self.insert(key.clone(), value);
+        if let Some(at) = expire_at {
+            self.expires.insert(key.clone(), at);
+            self.expire_if_needed(&key);
+        }
+    }
+
+    // run `f` on the value under `key`, `None` when there is no such key
+    fn read<T>(
+        &self,
+        key: &[u8],
+        f: impl FnOnce(&StoredValue) -> Result<T, WrongType>,
+    ) -> Result<Option<T>, WrongType> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.keyspace.get(key) else {
+            self.notify(keyspace_event::KEY_MISS, "keymiss", key);
+            return Ok(None);
+        };
+        entry.access(&self.memory);
+        f(&entry.value).map(Some)
     }
-    pub fn hset(&self, key: String, field: String, value: RespFrame) {
-        if !self.hmap.contains_key(&key) {
-            self.hmap.insert(key.clone(), DashMap::new());
+    // run `f` on the value under `key`, created empty with `empty` when missing
+    fn write<T, C: Default>(
+        &self,
+        key: &Bytes,
+        empty: fn(C) -> StoredValue,
+        f: impl FnOnce(&mut StoredValue) -> Result<T, WrongType>,
+    ) -> Result<T, WrongType> {
+        self.expire_if_needed(key);
+        let mut created = false;
+        let mut entry = self.keyspace.entry(key.clone()).or_insert_with(|| {
+            created = true;
+            Entry::new(key, empty(C::default()))
+        });
+        let ret = f(Arc::make_mut(&mut entry.value));
+        entry.access(&self.memory);
+        // a new entry was never counted, whatever size it started with
+        let previous = entry.resize(key);
+        self.grow(entry.size);
+        if !created {
+            self.used_memory.fetch_sub(previous, Ordering::Relaxed);
         }
-        self.hmap.get_mut(&key).unwrap().insert(field, value);
+        drop(entry);
+        let ret = ret?;
+        self.touch(key);
+        if created {
+            self.notify(keyspace_event::NEW, "new", key);
+        }
+        Ok(ret)
+    }
+    fn grow(&self, size: usize) {
+        let used = self.used_memory.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_memory.fetch_max(used, Ordering::Relaxed);
     }
-    pub fn hgetall(&self, key: &str) -> Option<DashMap<String, RespFrame>> {
-        self.hmap.get(key).map(|v| v.clone())
+    // run `f` on the entry under `key` without counting it as an access
+    fn peek<T>(&self, key: &[u8], f: impl FnOnce(&Entry) -> T) -> Option<T> {
+        self.expire_if_needed(key);
+        self.keyspace.get(key).map(|entry| f(&entry))
     }
-    pub fn sadd(&self, key: String, members: &Vec<String>) -> i64 {
-        let mut set = self.sis.entry(key).or_default();
-        let mut added = 0;
-        for member in members {
-            if !set.contains(member) {
-                set.push(member.clone());
-                added += 1;
+    // store `value` under `key`, replacing whatever was there
+    fn insert(&self, key: Bytes, value: StoredValue) {
+        let entry = Entry::new(&key, value);
+        self.grow(entry.size);
+        match self.keyspace.insert(key.clone(), entry) {
+            Some(previous) => {
+                self.used_memory.fetch_sub(previous.size, Ordering::Relaxed);
             }
+            None => self.notify(keyspace_event::NEW, "new", &key),
         }
-        added
     }
-    pub fn sismember(&self, key: String, member: String) -> i64 {
-        let ret = self.sis.get(&key).map_or(false, |v| v.contains(&member));
-        if ret {
-            1
+
+    /// Raise `flag` as soon as `key` is modified, until it is unwatched.
+    pub fn watch(&self, key: &[u8], flag: &Arc<AtomicBool>) {
+        self.expire_if_needed(key);
+        let mut flags = self.watched.entry(Bytes::copy_from_slice(key)).or_default();
+        if !flags.iter().any(|f| Arc::ptr_eq(f, flag)) {
+            flags.push(flag.clone());
+        }
+    }
+    pub fn unwatch(&self, key: &[u8], flag: &Arc<AtomicBool>) {
+        if let Some(mut flags) = self.watched.get_mut(key) {
+            flags.retain(|f| !Arc::ptr_eq(f, flag));
+        }
+        self.watched.remove_if(key, |_, flags| flags.is_empty());
+    }
+    /// Held while running a single command, so it can't interleave with a transaction. Blocks
+    /// the thread, so it is only for threads outside of the runtime.
+    pub fn lock_shared(&self) -> RwLockReadGuard<'_, ()> {
+        self.exec_lock.blocking_read()
+    }
+    /// Held while running a whole transaction, no other command runs in the meantime. Blocks
+    /// the thread, so it is only for threads outside of the runtime.
+    pub fn lock_exclusive(&self) -> RwLockWriteGuard<'_, ()> {
+        self.exec_lock.blocking_write()
+    }
+    /// Wait in line for the shared side of the execution lock.
+    pub async fn lock_shared_async(&self) -> RwLockReadGuard<'_, ()> {
+        self.exec_lock.read().await
+    }
+    /// Wait in line for the exclusive side of the execution lock, as a guard that can be moved
+    /// to the thread running the transaction.
+    pub async fn lock_exclusive_owned(&self) -> OwnedRwLockWriteGuard<()> {
+        self.exec_lock.clone().write_owned().await
+    }
+    /// Take either side of the execution lock if it is available right now.
+    pub fn try_lock(&self, exclusive: bool) -> Option<ExecGuard<'_>> {
+        if exclusive {
+            self.exec_lock.try_write().ok().map(ExecGuard::Exclusive)
         } else {
-            0
+            self.exec_lock.try_read().ok().map(ExecGuard::Shared)
         }
     }
+    pub fn scripts(&self) -> &ScriptEngine {
+        &self.scripts
+    }
+    pub fn pubsub(&self) -> &PubSubRegistry {
+        &self.pubsub
+    }
+    pub fn shard_pubsub(&self) -> &ShardPubSubRegistry {
+        &self.shard_pubsub
+    }
+    pub fn notify_config(&self) -> &NotifyConfig {
+        &self.notify
+    }
+    pub fn proto_limits(&self) -> &ProtoLimits {
+        &self.proto_limits
+    }
+    pub fn encoding_limits(&self) -> EncodingLimits {
+        *self
+            .encoding_limits
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+    /// Change the thresholds used from now on, values already stored keep their encoding.
+    pub fn update_encoding_limits(&self, f: impl FnOnce(&mut EncodingLimits)) {
+        f(&mut self
+            .encoding_limits
+            .write()
+            .unwrap_or_else(|e| e.into_inner()));
+    }
+    pub fn aof(&self) -> &Aof {
+        &self.aof
+    }
+    pub fn memory_config(&self) -> &MemoryConfig {
+        &self.memory
+    }
+    /// Approximate bytes taken by every key and value.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+    /// How many keys there are, and how many of them have an expire.
+    pub fn key_count(&self) -> (usize, usize) {
+        (self.keyspace.len(), self.expires.len())
+    }
+    /// The most memory ever used.
+    pub fn peak_memory(&self) -> usize {
+        self.peak_memory.load(Ordering::Relaxed)
+    }
+    /// The encoding of the value under `key`.
+    pub fn encoding(&self, key: &[u8]) -> Option<&'static str> {
+        self.peek(key, |entry| entry.value.encoding())
+    }
+    /// How many references redis would hold to the value under `key`: small integers are
+    /// shared by every key holding them, anything else belongs to its key.
+    pub fn refcount(&self, key: &[u8]) -> Option<i64> {
+        self.peek(key, |entry| match *entry.value {
+            StoredValue::String(StringValue::Int(v)) if (0..10_000).contains(&v) => i32::MAX as i64,
+            _ => 1,
+        })
+    }
+    /// Milliseconds since `key` was last read or written.
+    pub fn idle_time(&self, key: &[u8]) -> Option<u64> {
+        self.peek(key, |entry| entry.idle_ms())
+    }
+    /// The logarithmic access counter of `key`, as the lfu policies see it.
+    pub fn access_freq(&self, key: &[u8]) -> Option<u8> {
+        self.peek(key, |entry| entry.freq(&self.memory))
+    }
+    /// Set how long `key` has been idle, or its access counter, as carried over by RESTORE.
+    pub fn set_access(&self, key: &[u8], idle_ms: Option<u64>, freq: Option<u8>) {
+        self.peek(key, |entry| {
+            if let Some(freq) = freq {
+                entry.set_freq(freq);
+            }
+            if let Some(ms) = idle_ms {
+                entry.set_idle_ms(ms);
+            }
+        });
+    }
+    /// Approximate bytes taken by `key` and its value, sampling `samples` elements of large
+    /// values or all of them when 0.
+    pub fn memory_usage(&self, key: &[u8], samples: usize) -> Option<usize> {
+        self.peek(key, |entry| {
+            key_size(key) + entry.value.memory_usage(samples)
+        })
+    }
+
+    /// Publish a keyspace event about `key` if its class is enabled, on
+    /// `__keyspace@0__:<key>` and `__keyevent@0__:<event>` as the flags ask.
+    pub fn notify(&self, class: u32, event: &str, key: &[u8]) {
+        if !self.notify.enabled(class) {
+            return;
+        }
+        let classes = self.notify.classes();
+        if classes & keyspace_event::KEYSPACE != 0 {
+            let channel = [&b"__keyspace@0__:"[..], key].concat();
+            self.pubsub.publish(&channel, event.as_bytes());
+        }
+        if classes & keyspace_event::KEYEVENT != 0 {
+            let channel = format!("__keyevent@0__:{}", event);
+            self.pubsub.publish(channel.as_bytes(), key);
+        }
+    }
+
+    // flag the sessions watching `key` and count the change towards the next automatic save
+    fn touch(&self, key: &[u8]) {
+        self.dirty.fetch_add(1, Ordering::Relaxed);
+        if let Some(flags) = self.watched.get(key) {
+            for flag in flags.iter() {
+                flag.store(true, Ordering::Release);
+            }
+        }
+    }
+
+    // The deadline is checked and the key removed under the lock of its keyspace shard, so that
+    // a write replacing the key in between is never removed along with it. Returns whether it
+    // expired.
+    fn expire_if_needed(&self, key: &[u8]) -> bool {
+        if !self.expires.contains_key(key) {
+            return false;
+        }
+        let now = now_ms();
+        let expired =
+            |_: &Bytes, _: &Entry| self.expires.remove_if(key, |_, at| *at <= now).is_some();
+        match self.keyspace.remove_if(key, expired) {
+            Some((_, entry)) => {
+                self.used_memory.fetch_sub(entry.size, Ordering::Relaxed);
+                self.touch(key);
+                self.notify(keyspace_event::EXPIRED, "expired", key);
+                true
+            }
+            // a deadline left behind by a key removed meanwhile
+            None => {
+                self.expires.remove_if(key, |_, at| *at <= now);
+                false
+            }
+        }
+    }
+}
+
+/// Clamp a redis style inclusive range with negative indexes to `[start, stop]` within `len`.
+pub(crate) fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Classes of keyspace events, as enabled by the `notify-keyspace-events` flags.
+pub mod keyspace_event {
+    pub const KEYSPACE: u32 = 1 << 0; // K
+    pub const KEYEVENT: u32 = 1 << 1; // E
+    pub const GENERIC: u32 = 1 << 2; // g
+    pub const STRING: u32 = 1 << 3; // $
+    pub const LIST: u32 = 1 << 4; // l
+    pub const SET: u32 = 1 << 5; // s
+    pub const HASH: u32 = 1 << 6; // h
+    pub const ZSET: u32 = 1 << 7; // z
+    pub const EXPIRED: u32 = 1 << 8; // x
+    pub const EVICTED: u32 = 1 << 9; // e
+    pub const STREAM: u32 = 1 << 10; // t
+    pub const KEY_MISS: u32 = 1 << 11; // m
+    pub const MODULE: u32 = 1 << 12; // d
+    pub const NEW: u32 = 1 << 13; // n
+
+    // A, every class but key miss and new key
+    pub const ALL: u32 =
+        GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM | MODULE;
+}
+
+// letters in the order redis prints them back, `A` is only accepted as input
+const FLAGS: [(char, u32); 14] = [
+    ('g', keyspace_event::GENERIC),
+    ('$', keyspace_event::STRING),
+    ('l', keyspace_event::LIST),
+    ('s', keyspace_event::SET),
+    ('h', keyspace_event::HASH),
+    ('z', keyspace_event::ZSET),
+    ('x', keyspace_event::EXPIRED),
+    ('e', keyspace_event::EVICTED),
+    ('t', keyspace_event::STREAM),
+    ('d', keyspace_event::MODULE),
+    ('K', keyspace_event::KEYSPACE),
+    ('E', keyspace_event::KEYEVENT),
+    ('m', keyspace_event::KEY_MISS),
+    ('n', keyspace_event::NEW),
+];
+
+/// The enabled keyspace event classes, nothing is notified by default.
+#[derive(Debug, Default)]
+pub struct NotifyConfig {
+    classes: AtomicU32,
+}
+
+impl NotifyConfig {
+    pub fn classes(&self) -> u32 {
+        self.classes.load(Ordering::Relaxed)
+    }
+    /// Parse and apply a flag string such as `KEA` or `Ex`, leaving the current classes alone
+    /// when it holds an unknown letter.
+    pub fn set(&self, flags: &str) -> Result<(), String> {
+        let mut classes = 0;
+        for c in flags.chars() {
+            classes |= match c {
+                'A' => keyspace_event::ALL,
+                c => match FLAGS.iter().find(|(flag, _)| *flag == c) {
+                    Some((_, class)) => *class,
+                    None => return Err(format!("Invalid event class character '{}'", c)),
+                },
+            };
+        }
+        self.classes.store(classes, Ordering::Relaxed);
+        Ok(())
+    }
+    /// The flag string of the enabled classes, with `A` standing for all of them.
+    pub fn flags(&self) -> String {
+        let mut classes = self.classes();
+        let mut flags = String::new();
+        if classes & keyspace_event::ALL == keyspace_event::ALL {
+            flags.push('A');
+            classes &= !keyspace_event::ALL;
+        }
+        for (flag, class) in FLAGS {
+            if classes & class != 0 {
+                flags.push(flag);
+            }
+        }
+        flags
+    }
+    /// Whether events of `class` go out on either kind of channel, without K or E there is
+    /// nowhere to deliver them.
+    pub fn enabled(&self, class: u32) -> bool {
+        let classes = self.classes();
+        classes & class != 0 && classes & (keyspace_event::KEYSPACE | keyspace_event::KEYEVENT) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{Backend, BulkString, RespFrame, RespPush, Subscriber};
+
+    fn message(channel: &str, payload: &str) -> RespFrame {
+        RespPush::new([
+            BulkString::new("message").into(),
+            BulkString::new(channel).into(),
+            BulkString::new(payload).into(),
+        ])
+        .into()
+    }
+
+    #[test]
+    fn test_notify_flags() {
+        let config = NotifyConfig::default();
+        assert!(!config.enabled(keyspace_event::GENERIC));
+
+        config.set("KEA").unwrap();
+        assert_eq!(config.flags(), "AKE");
+        assert!(config.enabled(keyspace_event::EXPIRED));
+        assert!(!config.enabled(keyspace_event::KEY_MISS));
+
+        config.set("Ex$").unwrap();
+        assert_eq!(config.flags(), "$xE");
+        assert!(config.enabled(keyspace_event::STRING));
+        assert!(!config.enabled(keyspace_event::HASH));
+
+        // classes alone are kept as set but not delivered anywhere
+        config.set("g").unwrap();
+        assert_eq!(config.flags(), "g");
+        assert!(!config.enabled(keyspace_event::GENERIC));
+        assert!(config.set("Kq").is_err());
+        assert_eq!(config.flags(), "g");
+
+        config.set("Kmn").unwrap();
+        assert_eq!(config.flags(), "Kmn");
+        assert!(config.enabled(keyspace_event::KEY_MISS));
+        assert!(config.enabled(keyspace_event::NEW));
+    }
+
+    #[test]
+    fn test_backend_notifications() {
+        let backend = Backend::new();
+        let (tx, mut rx) = Subscriber::channel();
+        backend
+            .pubsub()
+            .subscribe(b"__keyspace@0__:greeting", 1, &tx);
+        backend
+            .pubsub()
+            .subscribe(b"__keyevent@0__:expired", 1, &tx);
+        backend.pubsub().subscribe(b"__keyevent@0__:del", 1, &tx);
+
+        // disabled by default
+        backend.set("greeting".into(), "hi".into());
+        assert!(rx.try_recv().is_err());
+
+        backend.notify_config().set("K$Exg").unwrap();
+        backend.set("greeting".into(), "hello".into());
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyspace@0__:greeting", "set")
+        );
+        // hash events are not enabled
+        backend
+            .hset("user".into(), "name".into(), "alice".into())
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+
+        backend.pexpire_at(b"greeting", crate::now_ms() + 1);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyspace@0__:greeting", "expire")
+        );
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(!backend.exists(b"greeting"));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyspace@0__:greeting", "expired")
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:expired", "greeting")
+        );
+
+        assert!(backend.del(b"user"));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:del", "user")
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_binary_key_notifications() {
+        let backend = Backend::new();
+        let (tx, mut rx) = Subscriber::channel();
+        let key = b"\xff\x00key";
+        let channel = [&b"__keyspace@0__:"[..], key].concat();
+        backend.pubsub().subscribe(&channel, 1, &tx);
+        // a lossy name would be the same for any other invalid key
+        backend
+            .pubsub()
+            .subscribe("__keyspace@0__:\u{fffd}\0key".as_bytes(), 1, &tx);
+        backend.notify_config().set("K$").unwrap();
+        backend.set(Bytes::from_static(key), "v".into());
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            RespPush::new([
+                BulkString::new("message").into(),
+                BulkString::new(channel).into(),
+                BulkString::new("set").into(),
+            ])
+            .into()
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_key_miss_and_new_notifications() {
+        let backend = Backend::new();
+        let (tx, mut rx) = Subscriber::channel();
+        backend
+            .pubsub()
+            .subscribe(b"__keyevent@0__:keymiss", 1, &tx);
+        backend.pubsub().subscribe(b"__keyevent@0__:new", 1, &tx);
+        backend.pubsub().subscribe(b"__keyevent@0__:hset", 1, &tx);
+        // A leaves both out
+        backend.notify_config().set("EA").unwrap();
+        assert_eq!(backend.get(b"missing").unwrap(), None);
+        backend.set("greeting".into(), "hi".into());
+        assert!(rx.try_recv().is_err());
+
+        backend.notify_config().set("Emnh").unwrap();
+        assert_eq!(backend.get(b"missing").unwrap(), None);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:keymiss", "missing")
+        );
+        // only the first write creates the key
+        for value in ["alice", "bob"] {
+            backend
+                .hset("user".into(), "name".into(), value.into())
+                .unwrap();
+        }
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:new", "user")
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:hset", "user")
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:hset", "user")
+        );
+        backend.set("greeting".into(), "hello".into());
+        assert!(rx.try_recv().is_err());
+        backend.set("other".into(), "hello".into());
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            message("__keyevent@0__:new", "other")
+        );
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use bytes::Bytes;
+
+use super::{now_ms, Backend, StoredValue};
+
+/// Save once at least `changes` keys were modified in the last `seconds`, as set by `save`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+/// Where snapshots are written and how often, changed at runtime through CONFIG SET.
+#[derive(Debug)]
+pub struct SaveConfig {
+    dir: RwLock<PathBuf>,
+    dbfilename: RwLock<String>,
+    rules: RwLock<Vec<SaveRule>>,
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        let rules = [(3600, 1), (300, 100), (60, 10000)]
+            .map(|(seconds, changes)| SaveRule { seconds, changes });
+        Self {
+            dir: RwLock::new(PathBuf::from(".")),
+            dbfilename: RwLock::new("dump.rdb".to_string()),
+            rules: RwLock::new(rules.to_vec()),
+        }
+    }
+}
+
+impl SaveConfig {
+    /// The directory snapshots are written to.
+    pub fn dir(&self) -> PathBuf {
+        self.dir.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    pub fn set_dir(&self, dir: PathBuf) {
+        *self.dir.write().unwrap_or_else(|e| e.into_inner()) = dir;
+    }
+    pub fn dbfilename(&self) -> String {
+        self.dbfilename
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+    pub fn set_dbfilename(&self, name: String) {
+        *self.dbfilename.write().unwrap_or_else(|e| e.into_inner()) = name;
+    }
+    /// The snapshot file, loaded at startup and replaced by every save.
+    pub fn path(&self) -> PathBuf {
+        self.dir().join(self.dbfilename())
+    }
+    /// The automatic save rules, none to only save when asked to.
+    pub fn rules(&self) -> Vec<SaveRule> {
+        self.rules.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    pub fn set_rules(&self, rules: Vec<SaveRule>) {
+        *self.rules.write().unwrap_or_else(|e| e.into_inner()) = rules;
+    }
+}
+
+/// How the last saves went.
+#[derive(Debug)]
+pub(crate) struct SaveState {
+    // unix seconds of the last successful save, and of the last background save attempt
+    lastsave: AtomicU64,
+    last_bgsave_try: AtomicU64,
+    in_progress: AtomicBool,
+    last_bgsave_ok: AtomicBool,
+}
+
+impl Default for SaveState {
+    fn default() -> Self {
+        // like a save that just happened, so the rules wait before the first one
+        let now = now_ms() / 1000;
+        Self {
+            lastsave: AtomicU64::new(now),
+            last_bgsave_try: AtomicU64::new(now),
+            in_progress: AtomicBool::new(false),
+            last_bgsave_ok: AtomicBool::new(true),
+        }
+    }
+}
+
+/// A copy of a key, taken to be saved while the keyspace keeps changing.
+#[derive(Debug)]
+pub struct KeySnapshot {
+    pub key: Bytes,
+    pub value: Arc<StoredValue>,
+    /// Absolute expire time, in unix milliseconds.
+    pub expire_at: Option<u64>,
+    pub idle_ms: u64,
+    pub freq: u8,
+}
+
+impl Backend {
+    pub fn save_config(&self) -> &SaveConfig {
+        &self.save_config
+    }
+    /// Copy every key that hasn't expired. Values are shared rather than cloned, a write
+    /// copies the value it changes while a snapshot still holds it, so the copy is quick
+    /// enough to be made with every write held off, and so from a single point in time.
+    /// Callers hold the exclusive side of the execution lock for it.
+    pub fn snapshot(&self) -> Vec<KeySnapshot> {
+        let now = now_ms();
+        let mut keys = self
+            .keyspace
+            .iter()
+            .map(|entry| KeySnapshot {
+                key: entry.key().clone(),
+                value: entry.value.clone(),
+                expire_at: None,
+                idle_ms: entry.idle_ms(),
+                freq: entry.freq(&self.memory),
+            })
+            .collect::<Vec<_>>();
+        // expires are copied apart, never holding a shard of both maps at once
+        for key in keys.iter_mut() {
+            key.expire_at = self.expires.get(&key.key).map(|at| *at);
+        }
+        keys.retain(|key| key.expire_at.is_none_or(|at| at > now));
+        keys
+    }
+    /// How many changes were made since the last successful save.
+    pub fn dirty(&self) -> u64 {
+        self.dirty.load(Ordering::Relaxed)
+    }
+    /// Unix seconds of the last successful save.
+    pub fn lastsave(&self) -> u64 {
+        self.save_state.lastsave.load(Ordering::Relaxed)
+    }
+    /// Unix seconds of the last background save attempt.
+    pub fn last_bgsave_try(&self) -> u64 {
+        self.save_state.last_bgsave_try.load(Ordering::Relaxed)
+    }
+    pub fn save_in_progress(&self) -> bool {
+        self.save_state.in_progress.load(Ordering::Acquire)
+    }
+    pub fn last_bgsave_ok(&self) -> bool {
+        self.save_state.last_bgsave_ok.load(Ordering::Relaxed)
+    }
+
+    /// Mark a save as started, `false` if another one already is.
+    pub(crate) fn begin_save(&self, background: bool) -> bool {
+        let started = self
+            .save_state
+            .in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if started && background {
+            self.save_state
+                .last_bgsave_try
+                .store(now_ms() / 1000, Ordering::Relaxed);
+        }
+        started
+    }
+    /// Mark the save started with `dirty` changes behind it as done, the changes made while it
+    /// ran still count towards the next one.
+    pub(crate) fn end_save(&self, background: bool, dirty: u64, ok: bool) {
+        if ok {
+            self.dirty.fetch_sub(dirty, Ordering::Relaxed);
+            self.save_state
+                .lastsave
+                .store(now_ms() / 1000, Ordering::Relaxed);
+        }
+        if background {
+            self.save_state.last_bgsave_ok.store(ok, Ordering::Relaxed);
+        }
+        self.save_state.in_progress.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+
+use super::{glob_match, key_slot};
+use crate::{BulkString, RespFrame, RespPush};
+
+// messages waiting to be written to a subscriber before it is considered too slow to keep
+const SUBSCRIBER_CAPACITY: usize = 4096;
+
+/// Where a connection receives the messages published to the channels it subscribed.
+///
+/// The queue is bounded: a subscriber that doesn't read its messages fast enough loses the ones
+/// that don't fit and is flagged as overflowed, so that its connection gets closed the way redis
+/// enforces `client-output-buffer-limit pubsub`.
+#[derive(Debug, Clone)]
+pub struct Subscriber {
+    sender: Sender<RespFrame>,
+    overflowed: Arc<AtomicBool>,
+}
+
+impl Subscriber {
+    pub fn channel() -> (Subscriber, Receiver<RespFrame>) {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_CAPACITY);
+        let subscriber = Subscriber {
+            sender,
+            overflowed: Arc::new(AtomicBool::new(false)),
+        };
+        (subscriber, receiver)
+    }
+
+    /// Queue `frame` for the connection, returning whether it was queued.
+    pub fn send(&self, frame: RespFrame) -> bool {
+        match self.sender.try_send(frame) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.overflowed.store(true, Ordering::Release);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Whether messages were dropped because the connection fell too far behind.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed.load(Ordering::Acquire)
+    }
+}
+
+/// Channel and pattern subscriptions of every connection, by connection id.
+#[derive(Debug, Default)]
+pub struct PubSubRegistry {
+    channels: DashMap<Bytes, HashMap<u64, Subscriber>>,
+    patterns: DashMap<Bytes, HashMap<u64, Subscriber>>,
+}
+
+impl PubSubRegistry {
+    pub fn subscribe(&self, channel: &[u8], id: u64, subscriber: &Subscriber) {
+        self.channels
+            .entry(Bytes::copy_from_slice(channel))
+            .or_default()
+            .insert(id, subscriber.clone());
+    }
+    pub fn unsubscribe(&self, channel: &[u8], id: u64) {
+        remove_subscriber(&self.channels, channel, id);
+    }
+    pub fn psubscribe(&self, pattern: &[u8], id: u64, subscriber: &Subscriber) {
+        self.patterns
+            .entry(Bytes::copy_from_slice(pattern))
+            .or_default()
+            .insert(id, subscriber.clone());
+    }
+    pub fn punsubscribe(&self, pattern: &[u8], id: u64) {
+        remove_subscriber(&self.patterns, pattern, id);
+    }
+
+    /// Deliver `message` to the subscribers of `channel` and of every matching pattern,
+    /// returning how many received it.
+    pub fn publish(&self, channel: &[u8], message: &[u8]) -> i64 {
+        let mut receivers = 0;
+        if let Some(subscribers) = self.channels.get(channel) {
+            let frame: RespFrame = RespPush::new([
+                BulkString::new("message").into(),
+                BulkString::new(channel).into(),
+                BulkString::new(message).into(),
+            ])
+            .into();
+            for subscriber in subscribers.values() {
+                if subscriber.send(frame.clone()) {
+                    receivers += 1;
+                }
+            }
+        }
+        for entry in self.patterns.iter() {
+            if !glob_match(entry.key(), channel) {
+                continue;
+            }
+            let frame: RespFrame = RespPush::new([
+                BulkString::new("pmessage").into(),
+                BulkString::from(entry.key().clone()).into(),
+                BulkString::new(channel).into(),
+                BulkString::new(message).into(),
+            ])
+            .into();
+            for subscriber in entry.value().values() {
+                if subscriber.send(frame.clone()) {
+                    receivers += 1;
+                }
+            }
+        }
+        receivers
+    }
+
+    /// Channels with at least one subscriber, optionally only those matching `pattern`.
+    pub fn channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let mut channels = self
+            .channels
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|channel| pattern.is_none_or(|p| glob_match(p, channel)))
+            .collect::<Vec<Bytes>>();
+        channels.sort();
+        channels
+    }
+    pub fn numsub(&self, channel: &[u8]) -> i64 {
+        self.channels.get(channel).map_or(0, |s| s.len() as i64)
+    }
+    /// Number of distinct patterns subscribed by any connection.
+    pub fn numpat(&self) -> i64 {
+        self.patterns.len() as i64
+    }
+}
+
+/// Shard channel subscriptions, grouped by the hash slot owning the channel name so the
+/// subscribers of a slot can be found without looking at any other.
+#[derive(Debug, Default)]
+pub struct ShardPubSubRegistry {
+    slots: DashMap<u16, DashMap<Bytes, HashMap<u64, Subscriber>>>,
+}
+
+impl ShardPubSubRegistry {
+    pub fn ssubscribe(&self, channel: &[u8], id: u64, subscriber: &Subscriber) {
+        self.slots
+            .entry(key_slot(channel))
+            .or_default()
+            .entry(Bytes::copy_from_slice(channel))
+            .or_default()
+            .insert(id, subscriber.clone());
+    }
+    pub fn sunsubscribe(&self, channel: &[u8], id: u64) {
+        let slot = key_slot(channel);
+        if let Some(channels) = self.slots.get(&slot) {
+            remove_subscriber(&channels, channel, id);
+        }
+        self.slots
+            .remove_if(&slot, |_, channels| channels.is_empty());
+    }
+
+    /// Deliver `message` to the subscribers of the shard channel, returning how many received it.
+    pub fn spublish(&self, channel: &[u8], message: &[u8]) -> i64 {
+        let Some(channels) = self.slots.get(&key_slot(channel)) else {
+            return 0;
+        };
+        let Some(subscribers) = channels.get(channel) else {
+            return 0;
+        };
+        let frame: RespFrame = RespPush::new([
+            BulkString::new("smessage").into(),
+            BulkString::new(channel).into(),
+            BulkString::new(message).into(),
+        ])
+        .into();
+        subscribers
+            .values()
+            .filter(|subscriber| subscriber.send(frame.clone()))
+            .count() as i64
+    }
+
+    /// Shard channels with at least one subscriber, optionally only those matching `pattern`.
+    pub fn channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let mut channels = self
+            .slots
+            .iter()
+            .flat_map(|slot| {
+                slot.iter()
+                    .map(|entry| entry.key().clone())
+                    .collect::<Vec<Bytes>>()
+            })
+            .filter(|channel| pattern.is_none_or(|p| glob_match(p, channel)))
+            .collect::<Vec<Bytes>>();
+        channels.sort();
+        channels
+    }
+    pub fn numsub(&self, channel: &[u8]) -> i64 {
+        self.slots
+            .get(&key_slot(channel))
+            .and_then(|channels| channels.get(channel).map(|s| s.len() as i64))
+            .unwrap_or(0)
+    }
+}
+
+fn remove_subscriber(map: &DashMap<Bytes, HashMap<u64, Subscriber>>, name: &[u8], id: u64) {
+    if let Some(mut subscribers) = map.get_mut(name) {
+        subscribers.remove(&id);
+    }
+    map.remove_if(name, |_, subscribers| subscribers.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish() {
+        let registry = PubSubRegistry::default();
+        let (tx, mut rx) = Subscriber::channel();
+        registry.subscribe(b"news.tech", 1, &tx);
+        registry.psubscribe(b"news.*", 1, &tx);
+        registry.psubscribe(b"sport.*", 1, &tx);
+
+        assert_eq!(registry.publish(b"news.tech", b"hello"), 2);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            RespPush::new([
+                BulkString::new("message").into(),
+                BulkString::new("news.tech").into(),
+                BulkString::new("hello").into(),
+            ])
+            .into()
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            RespPush::new([
+                BulkString::new("pmessage").into(),
+                BulkString::new("news.*").into(),
+                BulkString::new("news.tech").into(),
+                BulkString::new("hello").into(),
+            ])
+            .into()
+        );
+        assert_eq!(registry.publish(b"weather", b"sunny"), 0);
+
+        assert_eq!(registry.channels(None), vec![Bytes::from("news.tech")]);
+        assert_eq!(registry.channels(Some(b"sport.*")), Vec::<Bytes>::new());
+        assert_eq!(registry.numsub(b"news.tech"), 1);
+        assert_eq!(registry.numpat(), 2);
+
+        registry.unsubscribe(b"news.tech", 1);
+        registry.punsubscribe(b"news.*", 1);
+        assert_eq!(registry.numsub(b"news.tech"), 0);
+        assert_eq!(registry.numpat(), 1);
+        assert_eq!(registry.publish(b"news.tech", b"hello"), 0);
+    }
+
+    #[test]
+    fn test_subscriber_overflow() {
+        let registry = PubSubRegistry::default();
+        let (tx, mut rx) = Subscriber::channel();
+        registry.subscribe(b"news", 1, &tx);
+        for _ in 0..SUBSCRIBER_CAPACITY {
+            assert_eq!(registry.publish(b"news", b"hello"), 1);
+        }
+        assert!(!tx.overflowed());
+        assert_eq!(registry.publish(b"news", b"hello"), 0);
+        assert!(tx.overflowed());
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_spublish() {
+        let registry = ShardPubSubRegistry::default();
+        let (tx, mut rx) = Subscriber::channel();
+        registry.ssubscribe(b"{user}.orders", 1, &tx);
+        registry.ssubscribe(b"{user}.payments", 2, &tx);
+
+        assert_eq!(registry.spublish(b"{user}.orders", b"hello"), 1);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            RespPush::new([
+                BulkString::new("smessage").into(),
+                BulkString::new("{user}.orders").into(),
+                BulkString::new("hello").into(),
+            ])
+            .into()
+        );
+        assert_eq!(registry.spublish(b"{user}.other", b"hello"), 0);
+        assert_eq!(
+            registry.channels(Some(b"*orders")),
+            vec![Bytes::from("{user}.orders")]
+        );
+        assert_eq!(registry.numsub(b"{user}.payments"), 1);
+
+        registry.sunsubscribe(b"{user}.orders", 1);
+        registry.sunsubscribe(b"{user}.payments", 2);
+        assert!(registry.channels(None).is_empty());
+        assert!(registry.slots.is_empty());
+    }
+}
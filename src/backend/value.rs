@@ -0,0 +1,673 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{RespFrame, SimpleError};
+
+use super::encoding::{listpack_entry_size, EncodingLimits, LISTPACK_HEADER_SIZE};
+
+// strings up to this length are embedded in the object header by redis
+const EMBSTR_SIZE_LIMIT: usize = 44;
+// "-9223372036854775808"
+const MAX_INT_STRING_LEN: usize = 20;
+
+/// What a key holds, RespFrame is only used on the wire.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredValue {
+    String(StringValue),
+    Hash(HashValue),
+    Set(SetValue),
+    List(ListValue),
+    ZSet(ZSetValue),
+    Stream(Stream),
+}
+
+/// A string value with the encoding redis would pick for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringValue {
+    Int(i64),
+    Embstr(Bytes),
+    Raw(Bytes),
+}
+
+/// Small hashes are a flat list of pairs searched in order, large ones a hash table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashValue {
+    Listpack(Vec<(Bytes, Bytes)>),
+    Hashtable(HashMap<Bytes, Bytes>),
+}
+
+/// Small sets of integers are kept sorted, other small sets as a flat list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetValue {
+    Intset(Vec<i64>),
+    Listpack(Vec<Bytes>),
+    Hashtable(HashSet<Bytes>),
+}
+
+/// The elements of a list, flagged as a quicklist once they no longer fit a single listpack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListValue {
+    items: VecDeque<Bytes>,
+    // the size of the listpack holding the items, only tracked until it becomes a quicklist
+    listpack_bytes: usize,
+    quicklist: bool,
+}
+
+/// Small sorted sets are a flat list ordered by (score, member), large ones index the members
+/// both by name and by score.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZSetValue {
+    Listpack(Vec<(Bytes, f64)>),
+    Skiplist {
+        scores: HashMap<Bytes, f64>,
+        ordered: BTreeSet<ScoredMember>,
+    },
+}
+
+/// A sorted set member ordered by score, then by name.
+#[derive(Debug, Clone)]
+pub struct ScoredMember(pub f64, pub Bytes);
+
+/// Entries of a stream ordered by id. No command creates one yet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stream {
+    pub entries: BTreeMap<StreamId, Vec<(Bytes, Bytes)>>,
+    pub last_id: StreamId,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+pub struct WrongType;
+
+impl From<WrongType> for RespFrame {
+    fn from(e: WrongType) -> Self {
+        SimpleError::new(e.to_string()).into()
+    }
+}
+
+impl StringValue {
+    pub fn new(value: impl Into<Bytes>) -> Self {
+        let value = value.into();
+        if let Some(v) = canonical_int(&value) {
+            return StringValue::Int(v);
+        }
+        if value.len() <= EMBSTR_SIZE_LIMIT {
+            StringValue::Embstr(value)
+        } else {
+            StringValue::Raw(value)
+        }
+    }
+    /// The bytes a client reads back, integers are formatted on the way out.
+    pub fn to_bytes(&self) -> Bytes {
+        match self {
+            StringValue::Int(v) => Bytes::from(itoa::Buffer::new().format(*v).to_owned()),
+            StringValue::Embstr(v) | StringValue::Raw(v) => v.clone(),
+        }
+    }
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StringValue::Int(_) => "int",
+            StringValue::Embstr(_) => "embstr",
+            StringValue::Raw(_) => "raw",
+        }
+    }
+}
+
+impl From<&str> for StringValue {
+    fn from(v: &str) -> Self {
+        StringValue::new(Bytes::copy_from_slice(v.as_bytes()))
+    }
+}
+
+impl Default for HashValue {
+    fn default() -> Self {
+        HashValue::Listpack(Vec::new())
+    }
+}
+
+impl HashValue {
+    /// Build a hash out of `pairs`, with the encoding it would have reached field by field.
+    pub fn from_pairs(
+        pairs: impl IntoIterator<Item = (Bytes, Bytes)>,
+        limits: &EncodingLimits,
+    ) -> Self {
+        let mut hash = HashValue::default();
+        for (field, value) in pairs {
+            hash.insert(field, value, limits);
+        }
+        hash
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            HashValue::Listpack(pairs) => pairs.len(),
+            HashValue::Hashtable(map) => map.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        match self {
+            HashValue::Listpack(pairs) => pairs.iter().find(|(f, _)| f == field).map(|(_, v)| v),
+            HashValue::Hashtable(map) => map.get(field),
+        }
+    }
+    /// Set `field` to `value`, returns whether the field is new.
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: &EncodingLimits) -> bool {
+        if field.len().max(value.len()) > limits.hash_max_listpack_value {
+            self.convert();
+        }
+        let new = match self {
+            HashValue::Listpack(pairs) => match pairs.iter_mut().find(|(f, _)| *f == field) {
+                Some(pair) => {
+                    pair.1 = value;
+                    false
+                }
+                None => {
+                    pairs.push((field, value));
+                    true
+                }
+            },
+            HashValue::Hashtable(map) => map.insert(field, value).is_none(),
+        };
+        if self.len() > limits.hash_max_listpack_entries {
+            self.convert();
+        }
+        new
+    }
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + '_> {
+        match self {
+            HashValue::Listpack(pairs) => Box::new(pairs.iter().map(|(f, v)| (f, v))),
+            HashValue::Hashtable(map) => Box::new(map.iter()),
+        }
+    }
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            HashValue::Listpack(_) => "listpack",
+            HashValue::Hashtable(_) => "hashtable",
+        }
+    }
+    fn convert(&mut self) {
+        if let HashValue::Listpack(pairs) = self {
+            *self = HashValue::Hashtable(std::mem::take(pairs).into_iter().collect());
+        }
+    }
+}
+
+impl Default for SetValue {
+    fn default() -> Self {
+        SetValue::Intset(Vec::new())
+    }
+}
+
+impl SetValue {
+    /// Build a set out of `members`, with the encoding it would have reached member by member.
+    pub fn from_members(members: impl IntoIterator<Item = Bytes>, limits: &EncodingLimits) -> Self {
+        let mut set = SetValue::default();
+        for member in members {
+            set.insert(member, limits);
+        }
+        set
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            SetValue::Intset(ints) => ints.len(),
+            SetValue::Listpack(members) => members.len(),
+            SetValue::Hashtable(members) => members.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            SetValue::Intset(ints) => {
+                canonical_int(member).is_some_and(|v| ints.binary_search(&v).is_ok())
+            }
+            SetValue::Listpack(members) => members.iter().any(|m| m == member),
+            SetValue::Hashtable(members) => members.contains(member),
+        }
+    }
+    /// Add `member`, returns whether it is new.
+    pub fn insert(&mut self, member: Bytes, limits: &EncodingLimits) -> bool {
+        match self {
+            SetValue::Intset(ints) => match canonical_int(&member) {
+                Some(v) => {
+                    let Err(pos) = ints.binary_search(&v) else {
+                        return false;
+                    };
+                    ints.insert(pos, v);
+                    if ints.len() > limits.set_max_intset_entries {
+                        self.convert_to_hashtable();
+                    }
+                    true
+                }
+                None => {
+                    // like redis, assume every integer takes as much room as the longest one
+                    let longest = ints
+                        .iter()
+                        .map(|v| itoa::Buffer::new().format(*v).len())
+                        .max()
+                        .unwrap_or(0);
+                    if ints.len() < limits.set_max_listpack_entries
+                        && member.len().max(longest) <= limits.set_max_listpack_value
+                    {
+                        let members = ints.iter().map(|v| int_to_bytes(*v)).collect();
+                        *self = SetValue::Listpack(members);
+                    } else {
+                        self.convert_to_hashtable();
+                    }
+                    self.insert(member, limits)
+                }
+            },
+            SetValue::Listpack(members) => {
+                if members.contains(&member) {
+                    return false;
+                }
+                if members.len() < limits.set_max_listpack_entries
+                    && member.len() <= limits.set_max_listpack_value
+                {
+                    members.push(member);
+                    return true;
+                }
+                self.convert_to_hashtable();
+                self.insert(member, limits)
+            }
+            SetValue::Hashtable(members) => members.insert(member),
+        }
+    }
+    /// Every member, integers formatted back to strings.
+    pub fn members(&self) -> Vec<Bytes> {
+        match self {
+            SetValue::Intset(ints) => ints.iter().map(|v| int_to_bytes(*v)).collect(),
+            SetValue::Listpack(members) => members.clone(),
+            SetValue::Hashtable(members) => members.iter().cloned().collect(),
+        }
+    }
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            SetValue::Intset(_) => "intset",
+            SetValue::Listpack(_) => "listpack",
+            SetValue::Hashtable(_) => "hashtable",
+        }
+    }
+    fn convert_to_hashtable(&mut self) {
+        let members = self.members();
+        *self = SetValue::Hashtable(members.into_iter().collect());
+    }
+}
+
+impl Default for ListValue {
+    fn default() -> Self {
+        Self {
+            items: VecDeque::new(),
+            listpack_bytes: LISTPACK_HEADER_SIZE,
+            quicklist: false,
+        }
+    }
+}
+
+impl ListValue {
+    pub fn from_values(values: impl IntoIterator<Item = Bytes>, limits: &EncodingLimits) -> Self {
+        let mut list = ListValue::default();
+        list.push_back(values, limits);
+        list
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    pub fn push_front(&mut self, values: impl IntoIterator<Item = Bytes>, limits: &EncodingLimits) {
+        for value in values {
+            self.grow(&value, limits);
+            self.items.push_front(value);
+        }
+    }
+    pub fn push_back(&mut self, values: impl IntoIterator<Item = Bytes>, limits: &EncodingLimits) {
+        for value in values {
+            self.grow(&value, limits);
+            self.items.push_back(value);
+        }
+    }
+    /// The elements from `start` to `stop` included, both within bounds.
+    pub fn range(&self, start: usize, stop: usize) -> Vec<Bytes> {
+        self.items.range(start..=stop).cloned().collect()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Bytes> {
+        self.items.iter()
+    }
+    pub fn encoding(&self) -> &'static str {
+        if self.quicklist {
+            "quicklist"
+        } else {
+            "listpack"
+        }
+    }
+    fn grow(&mut self, value: &[u8], limits: &EncodingLimits) {
+        if self.quicklist {
+            return;
+        }
+        self.listpack_bytes += listpack_entry_size(value);
+        self.quicklist = !limits.list_fits(self.listpack_bytes, self.items.len() + 1);
+    }
+}
+
+impl Default for ZSetValue {
+    fn default() -> Self {
+        ZSetValue::Listpack(Vec::new())
+    }
+}
+
+impl ZSetValue {
+    /// Build a sorted set out of `members`, with the encoding it would have reached member by
+    /// member.
+    pub fn from_members(
+        members: impl IntoIterator<Item = (Bytes, f64)>,
+        limits: &EncodingLimits,
+    ) -> Self {
+        let mut zset = ZSetValue::default();
+        for (member, score) in members {
+            zset.insert(member, score, limits);
+        }
+        zset
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            ZSetValue::Listpack(members) => members.len(),
+            ZSetValue::Skiplist { scores, .. } => scores.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Add `member` or update its score, returns whether it is new.
+    pub fn insert(&mut self, member: Bytes, score: f64, limits: &EncodingLimits) -> bool {
+        if member.len() > limits.zset_max_listpack_value {
+            self.convert();
+        }
+        let new = match self {
+            ZSetValue::Listpack(members) => {
+                let old = members.iter().position(|(m, _)| *m == member);
+                if let Some(pos) = old {
+                    members.remove(pos);
+                }
+                let pos = members.partition_point(|(m, s)| {
+                    s.total_cmp(&score).then_with(|| m.cmp(&member)).is_lt()
+                });
+                members.insert(pos, (member, score));
+                old.is_none()
+            }
+            ZSetValue::Skiplist { scores, ordered } => {
+                let old = scores.insert(member.clone(), score);
+                if let Some(old) = old {
+                    ordered.remove(&ScoredMember(old, member.clone()));
+                }
+                ordered.insert(ScoredMember(score, member));
+                old.is_none()
+            }
+        };
+        if self.len() > limits.zset_max_listpack_entries {
+            self.convert();
+        }
+        new
+    }
+    /// The members ranked from `start` to `stop` included, both within bounds.
+    pub fn range(&self, start: usize, stop: usize) -> Vec<(Bytes, f64)> {
+        self.iter()
+            .skip(start)
+            .take(stop + 1 - start)
+            .map(|(m, s)| (m.clone(), s))
+            .collect()
+    }
+    /// Members in (score, member) order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, f64)> + '_> {
+        match self {
+            ZSetValue::Listpack(members) => Box::new(members.iter().map(|(m, s)| (m, *s))),
+            ZSetValue::Skiplist { ordered, .. } => Box::new(ordered.iter().map(|v| (&v.1, v.0))),
+        }
+    }
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            ZSetValue::Listpack(_) => "listpack",
+            ZSetValue::Skiplist { .. } => "skiplist",
+        }
+    }
+    fn convert(&mut self) {
+        if let ZSetValue::Listpack(members) = self {
+            let members = std::mem::take(members);
+            *self = ZSetValue::Skiplist {
+                scores: members.iter().cloned().collect(),
+                ordered: members
+                    .into_iter()
+                    .map(|(member, score)| ScoredMember(score, member))
+                    .collect(),
+            };
+        }
+    }
+}
+
+impl PartialEq for ScoredMember {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for ScoredMember {}
+impl PartialOrd for ScoredMember {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for ScoredMember {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+impl StoredValue {
+    /// The encoding reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StoredValue::String(v) => v.encoding(),
+            StoredValue::Hash(v) => v.encoding(),
+            StoredValue::Set(v) => v.encoding(),
+            StoredValue::List(v) => v.encoding(),
+            StoredValue::ZSet(v) => v.encoding(),
+            StoredValue::Stream(_) => "stream",
+        }
+    }
+    pub fn as_string(&self) -> Result<&StringValue, WrongType> {
+        match self {
+            StoredValue::String(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_hash(&self) -> Result<&HashValue, WrongType> {
+        match self {
+            StoredValue::Hash(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_hash_mut(&mut self) -> Result<&mut HashValue, WrongType> {
+        match self {
+            StoredValue::Hash(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_set(&self) -> Result<&SetValue, WrongType> {
+        match self {
+            StoredValue::Set(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_set_mut(&mut self) -> Result<&mut SetValue, WrongType> {
+        match self {
+            StoredValue::Set(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_list(&self) -> Result<&ListValue, WrongType> {
+        match self {
+            StoredValue::List(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_list_mut(&mut self) -> Result<&mut ListValue, WrongType> {
+        match self {
+            StoredValue::List(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_zset(&self) -> Result<&ZSetValue, WrongType> {
+        match self {
+            StoredValue::ZSet(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+    pub fn as_zset_mut(&mut self) -> Result<&mut ZSetValue, WrongType> {
+        match self {
+            StoredValue::ZSet(v) => Ok(v),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+fn int_to_bytes(v: i64) -> Bytes {
+    Bytes::copy_from_slice(itoa::Buffer::new().format(v).as_bytes())
+}
+
+// only strings that format back to exactly the same bytes are stored as integers
+pub(crate) fn canonical_int(s: &[u8]) -> Option<i64> {
+    if s.is_empty() || s.len() > MAX_INT_STRING_LEN {
+        return None;
+    }
+    let v: i64 = std::str::from_utf8(s).ok()?.parse().ok()?;
+    (itoa::Buffer::new().format(v).as_bytes() == s).then_some(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_limits() -> EncodingLimits {
+        EncodingLimits {
+            hash_max_listpack_entries: 2,
+            hash_max_listpack_value: 8,
+            set_max_intset_entries: 3,
+            set_max_listpack_entries: 2,
+            set_max_listpack_value: 8,
+            zset_max_listpack_entries: 2,
+            zset_max_listpack_value: 8,
+            list_max_listpack_size: 2,
+        }
+    }
+
+    #[test]
+    fn test_string_encoding() {
+        for (value, encoding) in [
+            ("12345", "int"),
+            ("-9223372036854775808", "int"),
+            ("9223372036854775808", "embstr"),
+            ("007", "embstr"),
+            ("+1", "embstr"),
+            ("", "embstr"),
+            ("hello", "embstr"),
+        ] {
+            let v = StringValue::from(value);
+            assert_eq!(v.encoding(), encoding, "{:?}", value);
+            assert_eq!(v.to_bytes(), value.as_bytes());
+        }
+        let long = "x".repeat(EMBSTR_SIZE_LIMIT + 1);
+        assert_eq!(StringValue::from(long.as_str()).encoding(), "raw");
+    }
+
+    #[test]
+    fn test_hash_encoding() {
+        let limits = small_limits();
+        let mut hash = HashValue::default();
+        assert!(hash.insert("a".into(), "1".into(), &limits));
+        assert!(!hash.insert("a".into(), "2".into(), &limits));
+        assert!(hash.insert("b".into(), "1".into(), &limits));
+        assert_eq!(hash.encoding(), "listpack");
+        assert!(hash.insert("c".into(), "1".into(), &limits));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.get(b"a"), Some(&Bytes::from("2")));
+
+        let mut hash = HashValue::default();
+        hash.insert("a".into(), "a value too long".into(), &limits);
+        assert_eq!(hash.encoding(), "hashtable");
+    }
+
+    #[test]
+    fn test_set_encoding() {
+        let limits = small_limits();
+        let mut set = SetValue::from_members(["3".into(), "1".into(), "2".into()], &limits);
+        assert_eq!(set.encoding(), "intset");
+        assert_eq!(set.members(), vec!["1", "2", "3"]);
+        assert!(set.contains(b"2"));
+        assert!(!set.contains(b"02"));
+        assert!(!set.insert("1".into(), &limits));
+        set.insert("4".into(), &limits);
+        assert_eq!(set.encoding(), "hashtable");
+
+        let mut set = SetValue::from_members(["1".into(), "a".into()], &limits);
+        assert_eq!(set.encoding(), "listpack");
+        assert!(set.contains(b"1") && set.contains(b"a"));
+        set.insert("b".into(), &limits);
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn test_list_encoding() {
+        let limits = small_limits();
+        let mut list = ListValue::from_values(["b".into(), "c".into()], &limits);
+        assert_eq!(list.encoding(), "listpack");
+        list.push_front(["a".into()], &limits);
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(list.range(0, 2), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_zset_encoding() {
+        let limits = small_limits();
+        let mut zset = ZSetValue::from_members([("b".into(), 2.0), ("a".into(), 1.0)], &limits);
+        assert_eq!(zset.encoding(), "listpack");
+        assert!(!zset.insert("a".into(), 3.0, &limits));
+        assert_eq!(zset.range(0, 1), vec![("b".into(), 2.0), ("a".into(), 3.0)]);
+
+        assert!(zset.insert("c".into(), 0.5, &limits));
+        assert_eq!(zset.encoding(), "skiplist");
+        assert!(!zset.insert("a".into(), 0.0, &limits));
+        assert_eq!(
+            zset.range(0, 2),
+            vec![("a".into(), 0.0), ("c".into(), 0.5), ("b".into(), 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut value = StoredValue::String("hello".into());
+        assert!(value.as_string().is_ok());
+        assert!(value.as_hash_mut().is_err());
+        assert_eq!(
+            RespFrame::from(WrongType),
+            SimpleError::new("WRONGTYPE Operation against a key holding the wrong kind of value")
+                .into()
+        );
+    }
+}
@@ -0,0 +1,730 @@
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{
+    aof::{self, AppendFsync},
+    glob_match, Backend, BulkString, MaxMemoryPolicy, RespArray, RespFrame, RespMap, SaveRule,
+    SimpleError,
+};
+
+use super::{
+    extract_args, extract_string, CommandError, CommandExcetor, Config, ConfigSubcommand, RESP_OK,
+};
+
+/// A runtime parameter reachable through CONFIG GET and CONFIG SET.
+struct Parameter {
+    name: &'static str,
+    get: fn(&Backend) -> String,
+    set: fn(&Backend, &str) -> Result<(), String>,
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "notify-keyspace-events",
+        get: |backend| backend.notify_config().flags(),
+        set: |backend, value| backend.notify_config().set(value),
+    },
+    Parameter {
+        name: "busy-reply-threshold",
+        get: get_time_limit,
+        set: set_time_limit,
+    },
+    Parameter {
+        name: "lua-time-limit",
+        get: get_time_limit,
+        set: set_time_limit,
+    },
+    Parameter {
+        name: "proto-max-bulk-len",
+        get: |backend| backend.proto_limits().max_bulk_len().to_string(),
+        set: |backend, value| {
+            let len = parse_memory(value, MIN_PROTO_LIMIT)?;
+            backend.proto_limits().set_max_bulk_len(len);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "client-query-buffer-limit",
+        get: |backend| backend.proto_limits().max_query_buffer().to_string(),
+        set: |backend, value| {
+            let len = parse_memory(value, MIN_PROTO_LIMIT)?;
+            backend.proto_limits().set_max_query_buffer(len);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "hash-max-listpack-entries",
+        get: |backend| {
+            backend
+                .encoding_limits()
+                .hash_max_listpack_entries
+                .to_string()
+        },
+        set: |backend, value| {
+            let v = parse_integer(value)?;
+            backend.update_encoding_limits(|limits| limits.hash_max_listpack_entries = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "hash-max-listpack-value",
+        get: |backend| {
+            backend
+                .encoding_limits()
+                .hash_max_listpack_value
+                .to_string()
+        },
+        set: |backend, value| {
+            let v = parse_memory(value, 0)?;
+            backend.update_encoding_limits(|limits| limits.hash_max_listpack_value = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "set-max-intset-entries",
+        get: |backend| backend.encoding_limits().set_max_intset_entries.to_string(),
+        set: |backend, value| {
+            let v = parse_integer(value)?;
+            backend.update_encoding_limits(|limits| limits.set_max_intset_entries = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "set-max-listpack-entries",
+        get: |backend| {
+            backend
+                .encoding_limits()
+                .set_max_listpack_entries
+                .to_string()
+        },
+        set: |backend, value| {
+            let v = parse_integer(value)?;
+            backend.update_encoding_limits(|limits| limits.set_max_listpack_entries = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "set-max-listpack-value",
+        get: |backend| backend.encoding_limits().set_max_listpack_value.to_string(),
+        set: |backend, value| {
+            let v = parse_memory(value, 0)?;
+            backend.update_encoding_limits(|limits| limits.set_max_listpack_value = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "zset-max-listpack-entries",
+        get: |backend| {
+            backend
+                .encoding_limits()
+                .zset_max_listpack_entries
+                .to_string()
+        },
+        set: |backend, value| {
+            let v = parse_integer(value)?;
+            backend.update_encoding_limits(|limits| limits.zset_max_listpack_entries = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "zset-max-listpack-value",
+        get: |backend| {
+            backend
+                .encoding_limits()
+                .zset_max_listpack_value
+                .to_string()
+        },
+        set: |backend, value| {
+            let v = parse_memory(value, 0)?;
+            backend.update_encoding_limits(|limits| limits.zset_max_listpack_value = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "list-max-listpack-size",
+        get: |backend| backend.encoding_limits().list_max_listpack_size.to_string(),
+        set: |backend, value| {
+            let v = parse_integer(value)?;
+            backend.update_encoding_limits(|limits| limits.list_max_listpack_size = v);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory",
+        get: |backend| backend.memory_config().maxmemory().to_string(),
+        set: |backend, value| {
+            let bytes = parse_memory(value, 0)?;
+            backend.memory_config().set_maxmemory(bytes);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory-policy",
+        get: |backend| backend.memory_config().policy().name().to_string(),
+        set: |backend, value| {
+            let policy = MaxMemoryPolicy::parse(value).ok_or_else(|| {
+                let names = MaxMemoryPolicy::ALL.map(|policy| policy.name());
+                format!(
+                    "argument(s) must be one of the following: {}",
+                    names.join(", ")
+                )
+            })?;
+            backend.memory_config().set_policy(policy);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "maxmemory-samples",
+        get: |backend| backend.memory_config().samples().to_string(),
+        set: |backend, value| {
+            let samples = parse_integer(value)?;
+            if !(1..=64).contains(&samples) {
+                return Err("argument must be between 1 and 64 inclusive".to_string());
+            }
+            backend.memory_config().set_samples(samples);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "lfu-log-factor",
+        get: |backend| backend.memory_config().lfu_log_factor().to_string(),
+        set: |backend, value| {
+            let factor = parse_integer(value)?;
+            backend.memory_config().set_lfu_log_factor(factor);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "lfu-decay-time",
+        get: |backend| backend.memory_config().lfu_decay_time().to_string(),
+        set: |backend, value| {
+            let minutes = parse_integer(value)?;
+            backend.memory_config().set_lfu_decay_time(minutes);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "save",
+        get: |backend| {
+            let rules = backend.save_config().rules();
+            let rules = rules
+                .iter()
+                .map(|rule| format!("{} {}", rule.seconds, rule.changes));
+            rules.collect::<Vec<_>>().join(" ")
+        },
+        set: |backend, value| {
+            let values = value.split_ascii_whitespace().collect::<Vec<_>>();
+            if values.len() % 2 != 0 {
+                return Err("Invalid save parameters".to_string());
+            }
+            let rules = values
+                .chunks(2)
+                .map(|pair| {
+                    Ok(SaveRule {
+                        seconds: parse_integer(pair[0])?,
+                        changes: parse_integer(pair[1])?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            backend.save_config().set_rules(rules);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "dir",
+        get: |backend| backend.save_config().dir().display().to_string(),
+        set: |backend, value| {
+            let dir = PathBuf::from(value);
+            if !dir.is_dir() {
+                return Err("No such file or directory".to_string());
+            }
+            backend.save_config().set_dir(dir);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "dbfilename",
+        get: |backend| backend.save_config().dbfilename(),
+        set: |backend, value| {
+            if value.is_empty() || value.contains('/') {
+                return Err("dbfilename can't be a path, just a filename".to_string());
+            }
+            backend.save_config().set_dbfilename(value.to_string());
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "appendonly",
+        get: |backend| yes_no(backend.aof().is_enabled()),
+        set: |backend, value| {
+            let ret = match parse_yes_no(value)? {
+                true => aof::enable(backend),
+                false => aof::disable(backend),
+            };
+            ret.map_err(|e| e.to_string())
+        },
+    },
+    Parameter {
+        name: "appendfsync",
+        get: |backend| backend.aof().fsync_policy().name().to_string(),
+        set: |backend, value| {
+            let policy = AppendFsync::parse(value)
+                .ok_or("argument(s) must be one of the following: always, everysec, no")?;
+            backend.aof().set_fsync_policy(policy);
+            Ok(())
+        },
+    },
+    Parameter {
+        name: "aof-load-truncated",
+        get: |backend| yes_no(backend.aof().load_truncated()),
+        set: |backend, value| {
+            backend.aof().set_load_truncated(parse_yes_no(value)?);
+            Ok(())
+        },
+    },
+];
+
+// the smallest bulk length and query buffer limit that can be configured
+const MIN_PROTO_LIMIT: usize = 1024 * 1024;
+
+fn get_time_limit(backend: &Backend) -> String {
+    backend.scripts().time_limit().as_millis().to_string()
+}
+fn set_time_limit(backend: &Backend, value: &str) -> Result<(), String> {
+    let ms = parse_integer(value)?;
+    backend.scripts().set_time_limit(Duration::from_millis(ms));
+    Ok(())
+}
+
+fn yes_no(enabled: bool) -> String {
+    if enabled { "yes" } else { "no" }.to_string()
+}
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".to_string()),
+    }
+}
+
+fn parse_integer<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| "argument couldn't be parsed into an integer".to_string())
+}
+
+/// Parse a memory amount such as `512mb` or `1g`, where `k`, `m` and `g` are powers of 1000 and
+/// `kb`, `mb` and `gb` powers of 1024.
+fn parse_memory(value: &str, min: usize) -> Result<usize, String> {
+    let lower = value.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit: usize = match &lower[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err("argument must be a memory value".to_string()),
+    };
+    let amount = digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| "argument must be a memory value".to_string())?;
+    if amount < min {
+        return Err(format!(
+            "argument must be between {} and {} inclusive",
+            min,
+            i64::MAX
+        ));
+    }
+    Ok(amount)
+}
+
+fn find_parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// Apply the `--parameter value` pairs redis-server takes on its command line, before any data
+/// is loaded. appendonly is left out, the append only file is used whenever there is one.
+pub fn configure(backend: &Backend, args: &[String]) -> Result<(), String> {
+    if !args.len().is_multiple_of(2) {
+        return Err("every parameter needs a value".to_string());
+    }
+    for pair in args.chunks(2) {
+        let name = pair[0]
+            .strip_prefix("--")
+            .ok_or_else(|| format!("expected a '--parameter', got '{}'", pair[0]))?;
+        let parameter = match find_parameter(name) {
+            Some(parameter) if parameter.name != "appendonly" => parameter,
+            _ => return Err(format!("unknown or read only parameter '{}'", name)),
+        };
+        (parameter.set)(backend, &pair[1])
+            .map_err(|e| format!("bad value for '{}' - {}", parameter.name, e))?;
+    }
+    Ok(())
+}
+
+impl CommandExcetor for Config {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match &self.subcommand {
+            ConfigSubcommand::Get(patterns) => {
+                let mut map = RespMap::new();
+                for parameter in PARAMETERS {
+                    let matched = patterns.iter().any(|pattern| {
+                        glob_match(
+                            pattern.to_ascii_lowercase().as_bytes(),
+                            parameter.name.as_bytes(),
+                        )
+                    });
+                    if matched {
+                        map.insert(
+                            parameter.name,
+                            BulkString::new((parameter.get)(backend)).into(),
+                        );
+                    }
+                }
+                map.into()
+            }
+            ConfigSubcommand::Set(values) => {
+                let mut parameters = Vec::with_capacity(values.len());
+                for (name, value) in values {
+                    match find_parameter(name) {
+                        Some(parameter) => parameters.push((parameter, value)),
+                        None => {
+                            return SimpleError::new(format!(
+                                "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                                name
+                            ))
+                            .into()
+                        }
+                    }
+                }
+                // all or nothing, put back what was already applied when a value is rejected
+                let previous = parameters
+                    .iter()
+                    .map(|(parameter, _)| (parameter.get)(backend))
+                    .collect::<Vec<String>>();
+                for (i, (parameter, value)) in parameters.iter().enumerate() {
+                    if let Err(e) = (parameter.set)(backend, value) {
+                        for (parameter, value) in parameters[..i].iter().zip(&previous) {
+                            let _ = (parameter.0.set)(backend, value);
+                        }
+                        return SimpleError::new(format!(
+                            "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                            parameter.name, e
+                        ))
+                        .into();
+                    }
+                }
+                RESP_OK.clone()
+            }
+        }
+    }
+}
+
+//CONFIG GET parameter [parameter ...] | SET parameter value [parameter value ...]
+impl TryFrom<RespArray> for Config {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"config") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let args = extract_args(&value, 1)?
+            .into_iter()
+            .map(extract_string)
+            .collect::<Result<Vec<String>, CommandError>>()?;
+        let syntax_err =
+            || CommandError::InvalidArgument("wrong number of arguments for CONFIG".to_string());
+        let (subcommand, args) = args.split_first().ok_or_else(syntax_err)?;
+        let subcommand = match subcommand.to_ascii_lowercase().as_str() {
+            "get" if !args.is_empty() => ConfigSubcommand::Get(args.to_vec()),
+            "set" if !args.is_empty() && args.len() % 2 == 0 => ConfigSubcommand::Set(
+                args.chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect(),
+            ),
+            _ => return Err(syntax_err()),
+        };
+        Ok(Config { subcommand })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_config_get_set() -> Result<()> {
+        let backend = Backend::new();
+        let set: Config = command(&[
+            "config",
+            "set",
+            "notify-keyspace-events",
+            "KEA",
+            "lua-time-limit",
+            "100",
+        ])
+        .try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+
+        let get: Config =
+            command(&["config", "get", "notify-*", "busy-reply-threshold"]).try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("notify-keyspace-events", BulkString::new("AKE").into());
+        expected.insert("busy-reply-threshold", BulkString::new("100").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        // the rejected value rolls back the ones before it
+        let set: Config = command(&[
+            "config",
+            "set",
+            "notify-keyspace-events",
+            "Kg",
+            "lua-time-limit",
+            "x",
+        ])
+        .try_into()?;
+        assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        assert_eq!(backend.notify_config().flags(), "AKE");
+
+        // read back as set, even without K or E to deliver the events on
+        let set: Config = command(&["config", "set", "notify-keyspace-events", "g"]).try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        let get: Config = command(&["config", "get", "notify-keyspace-events"]).try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("notify-keyspace-events", BulkString::new("g").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        let set: Config = command(&["config", "set", "no-such-option", "1"]).try_into()?;
+        assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        assert!(Config::try_from(command(&["config", "set", "lua-time-limit"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_proto_limits() -> Result<()> {
+        let backend = Backend::new();
+        let set: Config = command(&[
+            "config",
+            "set",
+            "proto-max-bulk-len",
+            "2mb",
+            "client-query-buffer-limit",
+            "2000k",
+        ])
+        .try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        let limits = backend.proto_limits().parser_limits();
+        assert_eq!(limits.max_bulk_len, 2 * 1024 * 1024);
+        assert_eq!(limits.max_query_buffer, 2_000_000);
+
+        let get: Config = command(&["config", "get", "proto-max-bulk-len"]).try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("proto-max-bulk-len", BulkString::new("2097152").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        for value in ["1k", "12xb", "-1", "mb"] {
+            let set: Config =
+                command(&["config", "set", "proto-max-bulk-len", value]).try_into()?;
+            assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_encoding_limits() -> Result<()> {
+        let backend = Backend::new();
+        let set: Config = command(&[
+            "config",
+            "set",
+            "hash-max-listpack-entries",
+            "8",
+            "set-max-listpack-value",
+            "1kb",
+            "list-max-listpack-size",
+            "-3",
+        ])
+        .try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        let limits = backend.encoding_limits();
+        assert_eq!(limits.hash_max_listpack_entries, 8);
+        assert_eq!(limits.set_max_listpack_value, 1024);
+        assert_eq!(limits.list_max_listpack_size, -3);
+
+        let get: Config = command(&["config", "get", "zset-max-listpack-*"]).try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("zset-max-listpack-entries", BulkString::new("128").into());
+        expected.insert("zset-max-listpack-value", BulkString::new("64").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        let set: Config = command(&["config", "set", "set-max-intset-entries", "-1"]).try_into()?;
+        assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_maxmemory() -> Result<()> {
+        let backend = Backend::new();
+        let set: Config = command(&[
+            "config",
+            "set",
+            "maxmemory",
+            "100mb",
+            "maxmemory-policy",
+            "ALLKEYS-LFU",
+            "maxmemory-samples",
+            "10",
+        ])
+        .try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        let config = backend.memory_config();
+        assert_eq!(config.maxmemory(), 100 * 1024 * 1024);
+        assert_eq!(config.policy(), MaxMemoryPolicy::AllKeysLfu);
+        assert_eq!(config.samples(), 10);
+
+        let get: Config = command(&["config", "get", "maxmemory*"]).try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("maxmemory", BulkString::new("104857600").into());
+        expected.insert("maxmemory-policy", BulkString::new("allkeys-lfu").into());
+        expected.insert("maxmemory-samples", BulkString::new("10").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        for (name, value) in [
+            ("maxmemory-policy", "lru"),
+            ("maxmemory-samples", "0"),
+            ("lfu-log-factor", "-1"),
+        ] {
+            let set: Config = command(&["config", "set", name, value]).try_into()?;
+            assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        }
+        assert_eq!(config.policy(), MaxMemoryPolicy::AllKeysLfu);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_save() -> Result<()> {
+        let backend = Backend::new();
+        let get: Config = command(&["config", "get", "save", "dbfilename"]).try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("save", BulkString::new("3600 1 300 100 60 10000").into());
+        expected.insert("dbfilename", BulkString::new("dump.rdb").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        let dir = std::env::temp_dir();
+        let set: Config = command(&[
+            "config",
+            "set",
+            "save",
+            "900 1",
+            "dir",
+            dir.to_str().unwrap(),
+            "dbfilename",
+            "other.rdb",
+        ])
+        .try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        let config = backend.save_config();
+        assert_eq!(
+            config.rules(),
+            vec![SaveRule {
+                seconds: 900,
+                changes: 1
+            }]
+        );
+        assert_eq!(config.path(), dir.join("other.rdb"));
+
+        // an empty value turns automatic saves off
+        let set: Config = command(&["config", "set", "save", ""]).try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        assert!(config.rules().is_empty());
+
+        for (name, value) in [
+            ("save", "900"),
+            ("save", "900 x"),
+            ("dir", "/no/such/dir"),
+            ("dbfilename", "a/b.rdb"),
+        ] {
+            let set: Config = command(&["config", "set", name, value]).try_into()?;
+            assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        }
+        assert_eq!(config.path(), dir.join("other.rdb"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_append_only() -> Result<()> {
+        let backend = Backend::new();
+        let get: Config = command(&[
+            "config",
+            "get",
+            "appendonly",
+            "appendfsync",
+            "aof-load-truncated",
+        ])
+        .try_into()?;
+        let mut expected = RespMap::new();
+        expected.insert("appendonly", BulkString::new("no").into());
+        expected.insert("appendfsync", BulkString::new("everysec").into());
+        expected.insert("aof-load-truncated", BulkString::new("yes").into());
+        assert_eq!(get.execute(&backend), expected.into());
+
+        let set: Config = command(&[
+            "config",
+            "set",
+            "appendfsync",
+            "always",
+            "aof-load-truncated",
+            "no",
+        ])
+        .try_into()?;
+        assert_eq!(set.execute(&backend), RESP_OK.clone());
+        assert_eq!(backend.aof().fsync_policy(), AppendFsync::Always);
+        assert!(!backend.aof().load_truncated());
+
+        for (name, value) in [
+            ("appendonly", "maybe"),
+            ("appendfsync", "sometimes"),
+            ("aof-load-truncated", "1"),
+        ] {
+            let set: Config = command(&["config", "set", name, value]).try_into()?;
+            assert!(matches!(set.execute(&backend), RespFrame::Error(_)));
+        }
+        assert!(!backend.aof().is_enabled());
+        Ok(())
+    }
+
+    #[test]
+    fn test_configure_from_args() {
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let backend = Backend::new();
+        configure(&backend, &args(&["--aof-load-truncated", "no"])).unwrap();
+        assert!(!backend.aof().load_truncated());
+
+        for bad in [
+            &["--aof-load-truncated"][..],
+            &["aof-load-truncated", "yes"],
+            &["--aof-load-truncated", "1"],
+            &["--appendonly", "yes"],
+            &["--nosuchparameter", "1"],
+        ] {
+            assert!(configure(&backend, &args(bad)).is_err());
+        }
+        assert!(!backend.aof().load_truncated());
+        assert!(!backend.aof().is_enabled());
+    }
+}
@@ -0,0 +1,602 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    keyspace_event, now_ms,
+    rdb::{dump_payload, restore_payload},
+    Backend, BulkString, RespArray, RespDecoder, RespEncoder, RespError, RespFrame, RespNull,
+    SimpleError, SimpleString, StoredValue,
+};
+
+use super::{
+    extract_args, extract_bytes, extract_integer, extract_string, validate_command, CommandError,
+    CommandExcetor, Dump, Migrate, Restore, RESP_OK,
+};
+
+impl CommandExcetor for Dump {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match backend.get_value(&self.key) {
+            Some(v) => match dump_payload(&v) {
+                Ok(payload) => BulkString::new(payload).into(),
+                Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+            },
+            None => RespFrame::Null(RespNull),
+        }
+    }
+}
+
+impl CommandExcetor for Restore {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        if !self.replace && backend.exists(&self.key) {
+            return SimpleError::new("BUSYKEY Target key name already exists.").into();
+        }
+        let value = match restore_payload(&self.payload, &backend.encoding_limits()) {
+            Ok(v) => v,
+            Err(e) => return SimpleError::new(format!("ERR {}", e)).into(),
+        };
+        let expire_at = match (self.ttl, self.absttl) {
+            (0, _) => None,
+            (ttl, true) => Some(ttl),
+            (ttl, false) => Some(now_ms() + ttl),
+        };
+        backend.set_value(self.key.clone(), value, expire_at);
+        backend.set_access(
+            &self.key,
+            self.idletime.map(|secs| secs.saturating_mul(1000)),
+            self.freq,
+        );
+        backend.notify(keyspace_event::GENERIC, "restore", &self.key);
+        RESP_OK.clone()
+    }
+}
+
+impl CommandExcetor for Migrate {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let values = self.values(backend);
+        if values.is_empty() {
+            return SimpleString::new("NOKEY").into();
+        }
+        let (acked, ret) = self.transfer(&values);
+        self.finish(backend, &values, acked, ret)
+    }
+}
+
+// milliseconds to wait on the target instance when MIGRATE is given a timeout of 0
+const MIGRATE_DEFAULT_TIMEOUT: u64 = 1000;
+
+/// A key to migrate, with its value and remaining ttl in milliseconds, 0 for none.
+pub(crate) type MigrateValue = (Bytes, StoredValue, u64);
+
+impl Migrate {
+    /// The keys to send which exist.
+    pub(crate) fn values(&self, backend: &Backend) -> Vec<MigrateValue> {
+        self.keys
+            .iter()
+            .filter_map(|key| {
+                backend
+                    .get_value(key)
+                    .map(|v| (key.clone(), v, backend.pttl(key).unwrap_or(0)))
+            })
+            .collect()
+    }
+
+    /// Send `values` to the target instance, returning how many of them it acknowledged before
+    /// the error that stopped the rest, if any. Blocks for up to the timeout on every reply.
+    pub(crate) fn transfer(&self, values: &[MigrateValue]) -> (usize, Result<(), SimpleError>) {
+        let mut conn = match self.connect() {
+            Ok(conn) => conn,
+            Err(e) => return (0, Err(e)),
+        };
+        for (acked, (key, value, ttl)) in values.iter().enumerate() {
+            let payload = match dump_payload(value) {
+                Ok(payload) => payload,
+                Err(e) => return (acked, Err(SimpleError::new(format!("ERR {}", e)))),
+            };
+            let mut args = vec![
+                "RESTORE".into(),
+                key.to_vec(),
+                ttl.to_string().into(),
+                payload,
+            ];
+            if self.replace {
+                args.push("REPLACE".into());
+            }
+            if let Err(e) = conn.call(args) {
+                return (acked, Err(e));
+            }
+        }
+        (values.len(), Ok(()))
+    }
+
+    /// Remove the keys the target acknowledged unless COPY was given, so that a transfer failing
+    /// partway never leaves a key on both instances. A key written while it was sent is kept, the
+    /// target only has the value from before.
+    pub(crate) fn finish(
+        &self,
+        backend: &Backend,
+        values: &[MigrateValue],
+        acked: usize,
+        ret: Result<(), SimpleError>,
+    ) -> RespFrame {
+        if !self.copy {
+            let mut deleted = self.deleted.lock().unwrap_or_else(|e| e.into_inner());
+            for (key, value, _) in &values[..acked] {
+                if backend.del_if_unchanged(key, value) {
+                    deleted.push(key.clone());
+                }
+            }
+        }
+        match ret {
+            Ok(()) => RESP_OK.clone(),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// The keys removed once migrated, to be logged as a DEL.
+    pub(crate) fn deleted(&self) -> Vec<Bytes> {
+        self.deleted
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // like redis, a timeout of 0 stands for the default one
+    fn timeout(&self) -> u64 {
+        match self.timeout {
+            0 => MIGRATE_DEFAULT_TIMEOUT,
+            timeout => timeout,
+        }
+    }
+
+    fn connect(&self) -> Result<TargetConnection, SimpleError> {
+        let timeout = Duration::from_millis(self.timeout());
+        let connect_err = || SimpleError::new("IOERR error or timeout connecting to the client");
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|_| connect_err())?
+            .next()
+            .ok_or_else(connect_err)?;
+        let stream = TcpStream::connect_timeout(&addr, timeout).map_err(|_| connect_err())?;
+        stream
+            .set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(timeout)))
+            .map_err(|_| connect_err())?;
+
+        let mut conn = TargetConnection {
+            stream,
+            buf: BytesMut::new(),
+        };
+        match &self.auth {
+            Some((Some(username), password)) => conn.call(vec![
+                "AUTH".into(),
+                username.as_str().into(),
+                password.as_str().into(),
+            ])?,
+            Some((None, password)) => conn.call(vec!["AUTH".into(), password.as_str().into()])?,
+            None => {}
+        }
+        if self.db != 0 {
+            conn.call(vec!["SELECT".into(), self.db.to_string().into()])?;
+        }
+        Ok(conn)
+    }
+}
+
+struct TargetConnection {
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl TargetConnection {
+    fn call(&mut self, args: Vec<Vec<u8>>) -> Result<(), SimpleError> {
+        let io_err = || SimpleError::new("IOERR error or timeout reading to target instance");
+        let frame = RespArray::new(
+            args.into_iter()
+                .map(|v| BulkString::new(v).into())
+                .collect::<Vec<RespFrame>>(),
+        );
+        self.stream
+            .write_all(&frame.encode())
+            .map_err(|_| io_err())?;
+        let mut chunk = [0u8; 4096];
+        loop {
+            match RespFrame::decode(&mut self.buf) {
+                Ok(RespFrame::Error(e)) => {
+                    return Err(SimpleError::new(format!(
+                        "ERR Target instance replied with error: {}",
+                        e.as_str()
+                    )))
+                }
+                Ok(_) => return Ok(()),
+                Err(RespError::NotComplete) => {
+                    let n = self.stream.read(&mut chunk).map_err(|_| io_err())?;
+                    if n == 0 {
+                        return Err(io_err());
+                    }
+                    self.buf.extend_from_slice(&chunk[..n]);
+                }
+                Err(_) => return Err(io_err()),
+            }
+        }
+    }
+}
+
+impl TryFrom<RespArray> for Dump {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["dump"], 1)?;
+        let args = extract_args(&value, 1)?;
+        Ok(Dump {
+            key: extract_bytes(args[0])?,
+        })
+    }
+}
+
+//RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+impl TryFrom<RespArray> for Restore {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let n_args = if value.len() > 4 { value.len() - 1 } else { 3 };
+        validate_command(&value, &["restore"], n_args)?;
+        let args = extract_args(&value, 1)?;
+        let ttl: i64 = extract_integer(args[1])?;
+        if ttl < 0 {
+            return Err(CommandError::InvalidArgument(
+                "Invalid TTL value, must be >= 0".to_string(),
+            ));
+        }
+        let payload = match args[2] {
+            RespFrame::BulkString(v) => v.to_vec(),
+            _ => {
+                return Err(CommandError::InvalidArgument(
+                    "Invalid argument".to_string(),
+                ))
+            }
+        };
+        let mut restore = Restore {
+            key: extract_bytes(args[0])?,
+            ttl: ttl as u64,
+            payload,
+            replace: false,
+            absttl: false,
+            idletime: None,
+            freq: None,
+        };
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            match extract_string(option)?.to_ascii_lowercase().as_str() {
+                "replace" => restore.replace = true,
+                "absttl" => restore.absttl = true,
+                "idletime" if restore.freq.is_none() => {
+                    let idletime: i64 = extract_integer(next_option(&mut options)?)?;
+                    if idletime < 0 {
+                        return Err(CommandError::InvalidArgument(
+                            "Invalid IDLETIME value, must be >= 0".to_string(),
+                        ));
+                    }
+                    restore.idletime = Some(idletime as u64);
+                }
+                "freq" if restore.idletime.is_none() => {
+                    let freq: i64 = extract_integer(next_option(&mut options)?)?;
+                    restore.freq = Some(u8::try_from(freq).map_err(|_| {
+                        CommandError::InvalidArgument(
+                            "Invalid FREQ value, must be >= 0 and <= 255".to_string(),
+                        )
+                    })?);
+                }
+                _ => return Err(CommandError::InvalidArgument("syntax error".to_string())),
+            }
+        }
+        Ok(restore)
+    }
+}
+
+//MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+//    [AUTH password | AUTH2 username password] [KEYS key [key ...]]
+impl TryFrom<RespArray> for Migrate {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let n_args = if value.len() > 6 { value.len() - 1 } else { 5 };
+        validate_command(&value, &["migrate"], n_args)?;
+        let args = extract_args(&value, 1)?;
+        let key = extract_bytes(args[2])?;
+        let mut migrate = Migrate {
+            host: extract_string(args[0])?,
+            port: extract_integer(args[1])?,
+            keys: vec![],
+            db: extract_integer(args[3])?,
+            timeout: extract_integer(args[4])?,
+            copy: false,
+            replace: false,
+            auth: None,
+            deleted: Mutex::new(Vec::new()),
+        };
+        let mut options = args[5..].iter();
+        while let Some(option) = options.next() {
+            match extract_string(option)?.to_ascii_lowercase().as_str() {
+                "copy" => migrate.copy = true,
+                "replace" => migrate.replace = true,
+                "auth" => {
+                    let password = extract_string(next_option(&mut options)?)?;
+                    migrate.auth = Some((None, password));
+                }
+                "auth2" => {
+                    let username = extract_string(next_option(&mut options)?)?;
+                    let password = extract_string(next_option(&mut options)?)?;
+                    migrate.auth = Some((Some(username), password));
+                }
+                "keys" => {
+                    if !key.is_empty() {
+                        return Err(CommandError::InvalidArgument(
+                            "When using MIGRATE KEYS option, the key argument must be set to the empty string".to_string(),
+                        ));
+                    }
+                    migrate.keys = options
+                        .by_ref()
+                        .map(|k| extract_bytes(k))
+                        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+                }
+                _ => return Err(CommandError::InvalidArgument("syntax error".to_string())),
+            }
+        }
+        if migrate.keys.is_empty() && !key.is_empty() {
+            migrate.keys.push(key);
+        }
+        Ok(migrate)
+    }
+}
+
+fn next_option<'a>(
+    options: &mut impl Iterator<Item = &'a &'a RespFrame>,
+) -> Result<&'a RespFrame, CommandError> {
+    options
+        .next()
+        .copied()
+        .ok_or_else(|| CommandError::InvalidArgument("syntax error".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tokio::net::TcpListener;
+
+    use crate::{network::stream_handler, EncodingLimits, SetValue};
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_restore_command() -> Result<()> {
+        let frame = command(&["restore", "key", "100", "x", "REPLACE", "IDLETIME", "10"]);
+        let result: Restore = frame.try_into()?;
+        assert_eq!(result.key, "key");
+        assert_eq!(result.ttl, 100);
+        assert!(result.replace);
+        assert!(!result.absttl);
+        assert_eq!(result.idletime, Some(10));
+
+        let frame = command(&["restore", "key", "0", "x", "IDLETIME", "1", "FREQ", "1"]);
+        assert!(Restore::try_from(frame).is_err());
+        let frame = command(&["restore", "key", "-1", "x"]);
+        assert!(Restore::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_restore() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("hello".into(), "world".into());
+        let payload = match Dump::try_from(command(&["dump", "hello"]))?.execute(&backend) {
+            RespFrame::BulkString(v) => v,
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+
+        let mut restore: Restore = command(&["restore", "copy", "0", "x"]).try_into()?;
+        restore.payload = payload.to_vec();
+        assert_eq!(restore.execute(&backend), RESP_OK.clone());
+        assert_eq!(backend.get(b"copy"), Ok(Some("world".into())));
+        assert_eq!(
+            restore.execute(&backend),
+            SimpleError::new("BUSYKEY Target key name already exists.").into()
+        );
+
+        restore.payload[0] = 0xff;
+        restore.replace = true;
+        assert!(matches!(restore.execute(&backend), RespFrame::Error(_)));
+
+        let dump: Dump = command(&["dump", "missing"]).try_into()?;
+        assert_eq!(dump.execute(&backend), RespFrame::Null(RespNull));
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_oversized_lzf() -> Result<()> {
+        // a string claiming to decompress to 2^40 bytes, with a valid footer
+        let mut payload = vec![0, 0xc3, 3, 0x81];
+        payload.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        payload.extend_from_slice(&[1, b'a', b'b', 11, 0]);
+        let crc = crate::rdb::crc64(&payload);
+        payload.extend_from_slice(&crc.to_le_bytes());
+
+        let backend = Backend::new();
+        let mut restore: Restore = command(&["restore", "key", "0", "x"]).try_into()?;
+        restore.payload = payload;
+        assert!(matches!(restore.execute(&backend), RespFrame::Error(_)));
+        assert!(!backend.exists(b"key"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_access_metadata() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("hello".into(), "world".into());
+        let payload = dump_payload(&backend.get_value(b"hello").unwrap())?;
+
+        let mut restore: Restore =
+            command(&["restore", "idle", "0", "x", "IDLETIME", "100"]).try_into()?;
+        restore.payload = payload.clone();
+        assert_eq!(restore.execute(&backend), RESP_OK.clone());
+        assert!(backend.idle_time(b"idle").unwrap() >= 100_000);
+
+        let mut restore: Restore =
+            command(&["restore", "hot", "0", "x", "FREQ", "200"]).try_into()?;
+        restore.payload = payload;
+        assert_eq!(restore.execute(&backend), RESP_OK.clone());
+        assert_eq!(backend.access_freq(b"hot"), Some(200));
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_command() -> Result<()> {
+        let frame = command(&[
+            "migrate",
+            "127.0.0.1",
+            "6380",
+            "",
+            "0",
+            "100",
+            "COPY",
+            "AUTH2",
+            "u",
+            "p",
+            "KEYS",
+            "a",
+            "b",
+        ]);
+        let result: Migrate = frame.try_into()?;
+        assert_eq!(result.keys, vec!["a", "b"]);
+        assert!(result.copy);
+        assert_eq!(result.auth, Some((Some("u".into()), "p".into())));
+
+        let frame = command(&["migrate", "127.0.0.1", "6380", "a", "0", "100", "KEYS", "b"]);
+        assert!(Migrate::try_from(frame).is_err());
+
+        let frame = command(&["migrate", "127.0.0.1", "6380", "a", "0", "0"]);
+        assert_eq!(Migrate::try_from(frame)?.timeout(), MIGRATE_DEFAULT_TIMEOUT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_keeps_keys_written_meanwhile() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("a".into(), "1".into());
+        backend.set("b".into(), "2".into());
+        let migrate: Migrate = command(&[
+            "migrate",
+            "127.0.0.1",
+            "6380",
+            "",
+            "0",
+            "100",
+            "KEYS",
+            "a",
+            "b",
+        ])
+        .try_into()?;
+        let values = migrate.values(&backend);
+        // written after it was dumped, while the transfer runs without the backend lock
+        backend.set("b".into(), "3".into());
+        assert_eq!(
+            migrate.finish(&backend, &values, values.len(), Ok(())),
+            RESP_OK.clone()
+        );
+        assert!(!backend.exists(b"a"));
+        assert_eq!(backend.get(b"b"), Ok(Some("3".into())));
+        assert_eq!(migrate.deleted(), vec![Bytes::from("a")]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_migrate_to_instance() -> Result<()> {
+        let target = Backend::new();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port().to_string();
+        let target_clone = target.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(stream_handler(stream, target_clone.clone()));
+            }
+        });
+
+        let source = Backend::new();
+        source.set("hello".into(), "world".into());
+        source
+            .sadd("myset".into(), &["a".into(), "b".into()])
+            .unwrap();
+        source.pexpire_at(b"hello", now_ms() + 100_000);
+
+        let frame = command(&[
+            "migrate",
+            "127.0.0.1",
+            &port,
+            "",
+            "0",
+            "1000",
+            "KEYS",
+            "hello",
+            "myset",
+            "missing",
+        ]);
+        let migrate: Migrate = frame.try_into()?;
+        let backend = source.clone();
+        let ret = tokio::task::spawn_blocking(move || migrate.execute(&backend)).await?;
+        assert_eq!(ret, RESP_OK.clone());
+
+        assert!(!source.exists(b"hello"));
+        assert!(!source.exists(b"myset"));
+        assert_eq!(target.get(b"hello"), Ok(Some("world".into())));
+        assert!(target.pttl(b"hello").is_some());
+        assert_eq!(
+            target.get_value(b"myset"),
+            Some(StoredValue::Set(SetValue::from_members(
+                ["a".into(), "b".into()],
+                &EncodingLimits::default()
+            )))
+        );
+
+        let migrate: Migrate =
+            command(&["migrate", "127.0.0.1", &port, "hello", "0", "1000"]).try_into()?;
+        let backend = source.clone();
+        let ret = tokio::task::spawn_blocking(move || migrate.execute(&backend)).await?;
+        assert_eq!(ret, SimpleString::new("NOKEY").into());
+
+        // the target refuses the second key, the first one is already gone from the source
+        source.set("first".into(), "1".into());
+        source.set("second".into(), "2".into());
+        target.set("second".into(), "taken".into());
+        let migrate: Migrate = command(&[
+            "migrate",
+            "127.0.0.1",
+            &port,
+            "",
+            "0",
+            "1000",
+            "KEYS",
+            "first",
+            "second",
+        ])
+        .try_into()?;
+        let (backend, migrate) = (source.clone(), std::sync::Arc::new(migrate));
+        let migrate_clone = migrate.clone();
+        let ret = tokio::task::spawn_blocking(move || migrate_clone.execute(&backend)).await?;
+        assert!(matches!(ret, RespFrame::Error(_)));
+        assert!(!source.exists(b"first"));
+        assert_eq!(source.get(b"second"), Ok(Some("2".into())));
+        assert_eq!(target.get(b"first"), Ok(Some("1".into())));
+        assert_eq!(migrate.deleted(), vec![Bytes::from("first")]);
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
-use crate::{Backend, BulkString, RespArray, RespFrame};
+use crate::{Backend, BulkString, RespArray, RespFrame, SimpleError, SimpleString};
 
-use super::{extract_args, validate_command, CommandError, CommandExcetor, Echo};
+use super::{
+    extract_args, extract_bytes, validate_command, CommandError, CommandExcetor, Echo, Ping, Quit,
+    Reset,
+};
 
 impl CommandExcetor for Echo {
     fn execute(&self, _backend: &Backend) -> RespFrame {
-        RespFrame::BulkString(BulkString::new(self.message.clone().into_bytes()))
+        RespFrame::BulkString(BulkString::from(self.message.clone()))
     }
 }
 impl TryFrom<RespArray> for Echo {
@@ -14,7 +17,7 @@ impl TryFrom<RespArray> for Echo {
         let args = extract_args(&value, 1)?;
         match args[0] {
             RespFrame::BulkString(message) => Ok(Echo {
-                message: String::from_utf8_lossy(message).to_string(),
+                message: message.0.clone(),
             }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
@@ -22,3 +25,68 @@ impl TryFrom<RespArray> for Echo {
         }
     }
 }
+
+impl Ping {
+    /// The reply of a RESP2 connection in subscribed mode, which only expects arrays.
+    pub fn subscribed_reply(&self) -> RespFrame {
+        let message = self.message.clone().unwrap_or_default();
+        RespArray::new([
+            BulkString::new("pong").into(),
+            BulkString::from(message).into(),
+        ])
+        .into()
+    }
+}
+
+impl CommandExcetor for Ping {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        match &self.message {
+            Some(message) => BulkString::from(message.clone()).into(),
+            None => SimpleString::new("PONG").into(),
+        }
+    }
+}
+
+// QUIT and RESET change the connection state and are handled by the connection session.
+impl CommandExcetor for Quit {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        SimpleError::new("ERR QUIT is only allowed on a client connection").into()
+    }
+}
+impl CommandExcetor for Reset {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        SimpleError::new("ERR RESET is only allowed on a client connection").into()
+    }
+}
+
+//PING [message]
+impl TryFrom<RespArray> for Ping {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let n_args = if value.len() > 1 { value.len() - 1 } else { 0 };
+        if n_args > 1 {
+            return Err(CommandError::InvalidArgument(
+                "wrong number of arguments for 'ping' command".to_string(),
+            ));
+        }
+        validate_command(&value, &["ping"], n_args)?;
+        let args = extract_args(&value, 1)?;
+        Ok(Ping {
+            message: args.first().map(|arg| extract_bytes(arg)).transpose()?,
+        })
+    }
+}
+impl TryFrom<RespArray> for Quit {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["quit"], 0)?;
+        Ok(Quit)
+    }
+}
+impl TryFrom<RespArray> for Reset {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["reset"], 0)?;
+        Ok(Reset)
+    }
+}
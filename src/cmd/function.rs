@@ -0,0 +1,260 @@
+use crate::{Backend, BulkString, RespArray, RespFrame, RestorePolicy};
+
+use super::{
+    extract_args, extract_string, script::extract_script_args, CommandError, CommandExcetor, FCall,
+    Function, FunctionSubcommand, RESP_OK,
+};
+
+impl Function {
+    /// LIST and DUMP only read the libraries.
+    pub fn is_write(&self) -> bool {
+        !matches!(
+            self.subcommand,
+            FunctionSubcommand::List { .. } | FunctionSubcommand::Dump | FunctionSubcommand::Kill
+        )
+    }
+}
+
+impl CommandExcetor for FCall {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        backend.scripts().fcall(
+            backend,
+            &self.function,
+            &self.keys,
+            &self.args,
+            self.read_only,
+        )
+    }
+}
+impl CommandExcetor for Function {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let scripts = backend.scripts();
+        let ret = match &self.subcommand {
+            FunctionSubcommand::Load { code, replace } => {
+                return match scripts.function_load(code, *replace) {
+                    Ok(name) => BulkString::new(name).into(),
+                    Err(e) => e.into(),
+                }
+            }
+            FunctionSubcommand::List { pattern, with_code } => {
+                return scripts.function_list(pattern.as_deref(), *with_code)
+            }
+            FunctionSubcommand::Dump => return BulkString::new(scripts.function_dump()).into(),
+            FunctionSubcommand::Kill => return scripts.kill(),
+            FunctionSubcommand::Delete(library) => scripts.function_delete(library),
+            FunctionSubcommand::Restore { payload, policy } => {
+                scripts.function_restore(payload, *policy)
+            }
+            FunctionSubcommand::Flush => {
+                scripts.function_flush();
+                Ok(())
+            }
+        };
+        match ret {
+            Ok(()) => RESP_OK.clone(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+//FCALL function numkeys [key [key ...]] [arg [arg ...]], FCALL_RO likewise
+impl TryFrom<RespArray> for FCall {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let read_only = match value.first() {
+            Some(RespFrame::BulkString(name)) => name.eq_ignore_ascii_case(b"fcall_ro"),
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        };
+        let name = if read_only { "fcall_ro" } else { "fcall" };
+        let (function, keys, args) = extract_script_args(&value, name)?;
+        Ok(FCall {
+            function,
+            keys,
+            args,
+            read_only,
+        })
+    }
+}
+
+//FUNCTION LOAD [REPLACE] code | LIST [LIBRARYNAME pattern] [WITHCODE] | DELETE library
+//    | DUMP | RESTORE payload [FLUSH | APPEND | REPLACE] | FLUSH [ASYNC | SYNC] | KILL
+impl TryFrom<RespArray> for Function {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"function") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let args = extract_args(&value, 1)?;
+        let subcommand = match args.first() {
+            Some(frame) => extract_string(frame)?.to_ascii_lowercase(),
+            None => {
+                return Err(CommandError::InvalidArgument(
+                    "function command must have a subcommand".to_string(),
+                ))
+            }
+        };
+        let syntax_err = || CommandError::InvalidArgument("syntax error".to_string());
+        let lowercase = |frame: &RespFrame| -> Result<String, CommandError> {
+            Ok(extract_string(frame)?.to_ascii_lowercase())
+        };
+        let subcommand = match (subcommand.as_str(), &args[1..]) {
+            ("load", [code]) => FunctionSubcommand::Load {
+                code: extract_string(code)?,
+                replace: false,
+            },
+            ("load", [option, code]) if lowercase(option)? == "replace" => {
+                FunctionSubcommand::Load {
+                    code: extract_string(code)?,
+                    replace: true,
+                }
+            }
+            ("list", options) => {
+                let mut pattern = None;
+                let mut with_code = false;
+                let mut options = options.iter();
+                while let Some(option) = options.next() {
+                    match lowercase(option)?.as_str() {
+                        "withcode" => with_code = true,
+                        "libraryname" => {
+                            pattern = Some(extract_string(options.next().ok_or_else(syntax_err)?)?)
+                        }
+                        _ => return Err(syntax_err()),
+                    }
+                }
+                FunctionSubcommand::List { pattern, with_code }
+            }
+            ("delete", [library]) => FunctionSubcommand::Delete(extract_string(library)?),
+            ("dump", []) => FunctionSubcommand::Dump,
+            ("restore", [payload, options @ ..]) if options.len() <= 1 => {
+                let payload = match payload {
+                    RespFrame::BulkString(payload) => payload.to_vec(),
+                    _ => return Err(syntax_err()),
+                };
+                let policy = match options.first() {
+                    None => RestorePolicy::Append,
+                    Some(option) => match lowercase(option)?.as_str() {
+                        "append" => RestorePolicy::Append,
+                        "replace" => RestorePolicy::Replace,
+                        "flush" => RestorePolicy::Flush,
+                        _ => return Err(syntax_err()),
+                    },
+                };
+                FunctionSubcommand::Restore { payload, policy }
+            }
+            ("flush", []) => FunctionSubcommand::Flush,
+            ("flush", [mode]) => match lowercase(mode)?.as_str() {
+                "async" | "sync" => FunctionSubcommand::Flush,
+                _ => return Err(syntax_err()),
+            },
+            ("kill", []) => FunctionSubcommand::Kill,
+            _ => return Err(syntax_err()),
+        };
+        Ok(Function { subcommand })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::SimpleError;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    const LIBRARY: &str = "#!lua name=counter
+redis.register_function('bump', function(keys, args)
+    local value = tonumber(redis.call('get', keys[1]) or '0') + args[1]
+    redis.call('set', keys[1], value)
+    return value
+end)
+redis.register_function{
+    function_name = 'peek',
+    callback = function(keys) return redis.call('get', keys[1]) end,
+    flags = {'no-writes'},
+}";
+
+    #[test]
+    fn test_function_command() -> Result<()> {
+        let cmd: Function = command(&["function", "load", "REPLACE", LIBRARY]).try_into()?;
+        assert!(matches!(
+            cmd.subcommand,
+            FunctionSubcommand::Load { replace: true, .. }
+        ));
+        let cmd: Function =
+            command(&["function", "list", "WITHCODE", "LIBRARYNAME", "c*"]).try_into()?;
+        assert!(!cmd.is_write());
+        assert!(matches!(
+            cmd.subcommand,
+            FunctionSubcommand::List { pattern: Some(ref p), with_code: true } if p == "c*"
+        ));
+        let cmd: Function = command(&["function", "restore", "x", "FLUSH"]).try_into()?;
+        assert!(matches!(
+            cmd.subcommand,
+            FunctionSubcommand::Restore {
+                policy: RestorePolicy::Flush,
+                ..
+            }
+        ));
+        assert!(Function::try_from(command(&["function", "restore", "x", "bad"])).is_err());
+        assert!(Function::try_from(command(&["function", "load"])).is_err());
+
+        let cmd: FCall = command(&["FCALL_RO", "peek", "1", "key"]).try_into()?;
+        assert!(cmd.read_only);
+        assert_eq!(cmd.keys, vec!["key"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_function_load_fcall_dump_restore() -> Result<()> {
+        let backend = Backend::new();
+        let load: Function = command(&["function", "load", LIBRARY]).try_into()?;
+        assert_eq!(load.execute(&backend), BulkString::new("counter").into());
+
+        let fcall: FCall = command(&["fcall", "bump", "1", "hits", "5"]).try_into()?;
+        assert_eq!(fcall.execute(&backend), RespFrame::Integer(5));
+        assert_eq!(fcall.execute(&backend), RespFrame::Integer(10));
+        let fcall: FCall = command(&["fcall_ro", "peek", "1", "hits"]).try_into()?;
+        assert_eq!(fcall.execute(&backend), b"10".into());
+        let fcall: FCall = command(&["fcall_ro", "bump", "1", "hits", "1"]).try_into()?;
+        assert!(matches!(fcall.execute(&backend), RespFrame::Error(_)));
+
+        let dump: Function = command(&["function", "dump"]).try_into()?;
+        let payload = match dump.execute(&backend) {
+            RespFrame::BulkString(payload) => payload.to_vec(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        let delete: Function = command(&["function", "delete", "counter"]).try_into()?;
+        assert_eq!(delete.execute(&backend), RESP_OK.clone());
+        assert_eq!(
+            delete.execute(&backend),
+            SimpleError::new("ERR Library not found").into()
+        );
+
+        let restore = Function {
+            subcommand: FunctionSubcommand::Restore {
+                payload,
+                policy: RestorePolicy::Append,
+            },
+        };
+        assert_eq!(restore.execute(&backend), RESP_OK.clone());
+        let fcall: FCall = command(&["fcall", "bump", "1", "hits", "1"]).try_into()?;
+        assert_eq!(fcall.execute(&backend), RespFrame::Integer(11));
+
+        let flush: Function = command(&["function", "flush"]).try_into()?;
+        assert_eq!(flush.execute(&backend), RESP_OK.clone());
+        assert_eq!(
+            fcall.execute(&backend),
+            SimpleError::new("ERR Function not found").into()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{Backend, BulkString, RespArray, RespFrame, RespMap, SimpleError};
+
+use super::{extract_args, extract_integer, extract_string, CommandError, CommandExcetor, Hello};
+
+/// The redis version whose protocol and commands are served, as reported to clients.
+pub(crate) const REDIS_VERSION: &str = "7.2.5";
+
+impl Hello {
+    /// The protocol version the connection switches to, `None` to keep the current one.
+    pub fn protocol(&self) -> Option<i64> {
+        self.protover
+    }
+    pub fn client_name(&self) -> Option<&str> {
+        self.setname.as_deref()
+    }
+
+    /// Everything HELLO can refuse before the connection state is touched.
+    pub fn check(&self) -> Result<(), SimpleError> {
+        if let Some(protover) = self.protover {
+            if !(2..=3).contains(&protover) {
+                return Err(SimpleError::new("NOPROTO unsupported protocol version"));
+            }
+        }
+        // there are no ACL users, the default user has no password
+        if let Some((username, _)) = &self.auth {
+            if username != "default" {
+                return Err(SimpleError::new(
+                    "WRONGPASS invalid username-password pair or user is disabled.",
+                ));
+            }
+        }
+        if let Some(name) = &self.setname {
+            if name.bytes().any(|c| !(b'!'..=b'~').contains(&c)) {
+                return Err(SimpleError::new(
+                    "ERR Client names cannot contain spaces, newlines or special characters.",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The server properties returned once the connection is set up.
+    pub fn reply(id: u64, protocol: i64) -> RespFrame {
+        let mut map = RespMap::new();
+        map.insert("server", BulkString::new("redis").into());
+        map.insert("version", BulkString::new(REDIS_VERSION).into());
+        map.insert("proto", RespFrame::Integer(protocol));
+        map.insert("id", RespFrame::Integer(id as i64));
+        map.insert("mode", BulkString::new("standalone").into());
+        map.insert("role", BulkString::new("master").into());
+        map.insert("modules", RespArray::new(vec![]).into());
+        map.into()
+    }
+}
+
+// HELLO changes the connection state and is handled by the connection session.
+impl CommandExcetor for Hello {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        SimpleError::new("ERR HELLO is only allowed outside of MULTI and scripts").into()
+    }
+}
+
+//HELLO [protover [AUTH username password] [SETNAME clientname]]
+impl TryFrom<RespArray> for Hello {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"hello") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let args = extract_args(&value, 1)?;
+        let mut hello = Hello {
+            protover: None,
+            auth: None,
+            setname: None,
+        };
+        let Some((protover, options)) = args.split_first() else {
+            return Ok(hello);
+        };
+        hello.protover = Some(extract_integer(protover).map_err(|_| {
+            CommandError::InvalidArgument(
+                "Protocol version is not an integer or out of range".to_string(),
+            )
+        })?);
+        let syntax_err = |option: &RespFrame| -> CommandError {
+            let option = extract_string(option).unwrap_or_default();
+            CommandError::InvalidArgument(format!("Syntax error in HELLO option '{}'", option))
+        };
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match extract_string(option)?.to_ascii_lowercase().as_str() {
+                "auth" => match (options.next(), options.next()) {
+                    (Some(username), Some(password)) => {
+                        hello.auth = Some((extract_string(username)?, extract_string(password)?))
+                    }
+                    _ => return Err(syntax_err(option)),
+                },
+                "setname" => match options.next() {
+                    Some(name) => hello.setname = Some(extract_string(name)?),
+                    None => return Err(syntax_err(option)),
+                },
+                _ => return Err(syntax_err(option)),
+            }
+        }
+        Ok(hello)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_hello_command() -> Result<()> {
+        let hello: Hello = command(&["hello"]).try_into()?;
+        assert_eq!(hello.protocol(), None);
+
+        let hello: Hello = command(&[
+            "HELLO", "3", "AUTH", "default", "secret", "SETNAME", "worker",
+        ])
+        .try_into()?;
+        assert_eq!(hello.protocol(), Some(3));
+        assert_eq!(hello.client_name(), Some("worker"));
+        assert!(hello.check().is_ok());
+
+        let hello: Hello = command(&["hello", "4"]).try_into()?;
+        assert_eq!(
+            hello.check(),
+            Err(SimpleError::new("NOPROTO unsupported protocol version"))
+        );
+        let hello: Hello = command(&["hello", "3", "auth", "admin", "secret"]).try_into()?;
+        assert!(hello.check().is_err());
+        let hello: Hello = command(&["hello", "3", "setname", "my worker"]).try_into()?;
+        assert!(hello.check().is_err());
+
+        assert!(Hello::try_from(command(&["hello", "three"])).is_err());
+        assert!(Hello::try_from(command(&["hello", "3", "auth", "default"])).is_err());
+        Ok(())
+    }
+}
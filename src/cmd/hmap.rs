@@ -2,8 +2,9 @@ use super::{
     extract_args, extract_args_hmget, validate_command, CommandError, CommandExcetor, HGet,
     HGetAll, HMget, HSet, RESP_OK,
 };
-use crate::{Backend, BulkString, RespArray, RespFrame, RespNull, SimpleString};
+use crate::{Backend, BulkString, RespArray, RespFrame, RespMap, RespNull};
 use anyhow::Result;
+use bytes::Bytes;
 
 impl TryFrom<RespArray> for HGet {
     type Error = CommandError;
@@ -12,8 +13,8 @@ impl TryFrom<RespArray> for HGet {
         let args = extract_args(&value, 1)?;
         match (args[0], args[1]) {
             (RespFrame::BulkString(key), RespFrame::BulkString(field)) => Ok(HGet {
-                key: String::from_utf8_lossy(key).to_string(),
-                field: String::from_utf8_lossy(field).to_string(),
+                key: key.0.clone(),
+                field: field.0.clone(),
             }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
@@ -28,9 +29,7 @@ impl TryFrom<RespArray> for HGetAll {
         validate_command(&value, &["hgetall"], 1)?;
         let args = extract_args(&value, 1)?;
         match args[0] {
-            RespFrame::BulkString(key) => Ok(HGetAll {
-                key: String::from_utf8_lossy(key).to_string(),
-            }),
+            RespFrame::BulkString(key) => Ok(HGetAll { key: key.0.clone() }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
             )),
@@ -44,10 +43,14 @@ impl TryFrom<RespArray> for HSet {
         validate_command(&value, &["hset"], 3)?;
         let args = extract_args(&value, 1)?;
         match (args[0], args[1], args[2]) {
-            (RespFrame::BulkString(key), RespFrame::BulkString(field), value) => Ok(HSet {
-                key: String::from_utf8_lossy(key).to_string(),
-                field: String::from_utf8_lossy(field).to_string(),
-                value: value.clone(),
+            (
+                RespFrame::BulkString(key),
+                RespFrame::BulkString(field),
+                RespFrame::BulkString(value),
+            ) => Ok(HSet {
+                key: key.0.clone(),
+                field: field.0.clone(),
+                value: value.0.clone(),
             }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
@@ -66,14 +69,14 @@ impl TryFrom<RespArray> for HMget {
                 let fields = fields
                     .iter()
                     .map(|v| match v {
-                        RespFrame::BulkString(v) => Ok(String::from_utf8_lossy(v).to_string()),
+                        RespFrame::BulkString(v) => Ok(v.0.clone()),
                         _ => Err(CommandError::InvalidArgument(
                             "Invalid argument".to_string(),
                         )),
                     })
-                    .collect::<Result<Vec<String>, CommandError>>()?;
+                    .collect::<Result<Vec<Bytes>, CommandError>>()?;
                 Ok(HMget {
-                    key: String::from_utf8_lossy(key).to_string(),
+                    key: key.0.clone(),
                     fields,
                 })
             }
@@ -88,8 +91,9 @@ impl CommandExcetor for HMget {
         let mut result = Vec::new();
         for field in self.fields.iter() {
             match backend.hget(&self.key, field) {
-                Some(v) => result.push(v),
-                None => result.push(RespFrame::SimpleString(SimpleString::new("(nil)"))),
+                Ok(Some(v)) => result.push(BulkString::from(v).into()),
+                Ok(None) => result.push(RespFrame::Null(RespNull)),
+                Err(e) => return e.into(),
             }
         }
         RespArray::new(result).into()
@@ -98,40 +102,32 @@ impl CommandExcetor for HMget {
 impl CommandExcetor for HGet {
     fn execute(&self, backend: &Backend) -> RespFrame {
         match backend.hget(&self.key, &self.field) {
-            Some(v) => v,
-            None => RespFrame::Null(RespNull),
+            Ok(Some(v)) => BulkString::from(v).into(),
+            Ok(None) => RespFrame::Null(RespNull),
+            Err(e) => e.into(),
         }
     }
 }
 impl CommandExcetor for HGetAll {
     fn execute(&self, backend: &Backend) -> RespFrame {
-        let hmap = backend.hgetall(&self.key);
-        match hmap {
-            Some(v) => {
-                let mut result = Vec::new();
-                for v in v.iter() {
-                    result.push(RespFrame::BulkString(BulkString::new(v.key().to_owned())));
-                    result.push(v.value().clone());
+        match backend.hgetall(&self.key) {
+            Ok(fields) => {
+                let mut result = RespMap::new();
+                for (field, value) in fields {
+                    result.insert(field, BulkString::from(value).into());
                 }
-                // if self.sort {
-                //     result.sort_by(|a, b| a.0.cmp(&b.0));
-                // }
-                // let ret = result
-                //     .into_iter()
-                //     .flat_map(|(k, v)| {
-                //         vec![RespFrame::BulkString(BulkString::new(k.clone())), v.clone()]
-                //     })
-                //     .collect::<Vec<RespFrame>>();
-                RespArray::new(result).into()
+                result.into()
             }
-            None => RespFrame::Null(RespNull),
+            Err(e) => e.into(),
         }
     }
 }
 impl CommandExcetor for HSet {
     fn execute(&self, backend: &Backend) -> RespFrame {
-        backend.hset(self.key.clone(), self.field.clone(), self.value.clone());
-        RESP_OK.clone()
+        match backend.hset(self.key.clone(), self.field.clone(), self.value.clone()) {
+            Ok(()) => RESP_OK.clone(),
+            Err(e) => e.into(),
+        }
     }
 }
 
@@ -139,7 +135,7 @@ impl CommandExcetor for HSet {
 mod tests {
     use bytes::BytesMut;
 
-    use crate::RespDecoder;
+    use crate::{RespDecoder, RespEncoder};
 
     use super::*;
 
@@ -168,6 +164,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_hgetall_execute() {
+        let backend = Backend::new();
+        let cmd = HGetAll { key: "user".into() };
+        assert_eq!(cmd.execute(&backend), RespMap::new().into());
+        backend
+            .hset("user".into(), "name".into(), "alice".into())
+            .unwrap();
+        let mut expected = RespMap::new();
+        expected.insert("name", b"alice".into());
+        assert_eq!(cmd.execute(&backend), expected.into());
+    }
+
+    #[test]
+    fn test_hash_binary_fields() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut buf = BytesMut::from(
+            &b"*4\r\n$4\r\nhset\r\n$3\r\n\xffk\x00\r\n$2\r\n\x00\xfe\r\n$1\r\nv\r\n"[..],
+        );
+        let cmd: HSet = RespArray::decode(&mut buf)?.try_into()?;
+        cmd.execute(&backend);
+
+        let cmd = HGetAll {
+            key: (&b"\xffk\x00"[..]).into(),
+        };
+        assert_eq!(
+            cmd.execute(&backend).encode(),
+            b"%1\r\n$2\r\n\x00\xfe\r\n$1\r\nv\r\n"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_hset_command() -> Result<(), CommandError> {
         let mut buf = BytesMut::new();
@@ -178,7 +206,7 @@ mod tests {
 
         assert_eq!(result.key, "key");
         assert_eq!(result.field, "field");
-        assert_eq!(result.value, RespFrame::BulkString(b"value".into()));
+        assert_eq!(result.value, "value");
         Ok(())
     }
     #[test]
@@ -193,4 +221,17 @@ mod tests {
         assert_eq!(result.fields, vec!["hello", "hello1"]);
         Ok(())
     }
+
+    #[test]
+    fn test_hmget_empty_and_missing() {
+        let backend = Backend::new();
+        backend
+            .hset("user".into(), "nick".into(), "".into())
+            .unwrap();
+        let cmd = HMget {
+            key: "user".into(),
+            fields: vec!["nick".into(), "age".into()],
+        };
+        assert_eq!(cmd.execute(&backend).encode(), b"*2\r\n$0\r\n\r\n_\r\n");
+    }
 }
@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use crate::{Backend, RespArray, RespFrame, VerbatimString};
+
+use super::{extract_args, extract_string, CommandError, CommandExcetor, Info};
+
+// the sections printed when none or `default`, `all` or `everything` is asked for
+const SECTIONS: [&str; 4] = ["memory", "persistence", "stats", "keyspace"];
+
+impl CommandExcetor for Info {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let all = self.sections.is_empty()
+            || self
+                .sections
+                .iter()
+                .any(|s| matches!(s.as_str(), "default" | "all" | "everything"));
+        let mut info = String::new();
+        for section in SECTIONS {
+            if !all && !self.sections.iter().any(|s| s == section) {
+                continue;
+            }
+            if !info.is_empty() {
+                info.push_str("\r\n");
+            }
+            match section {
+                "memory" => memory_section(backend, &mut info),
+                "persistence" => persistence_section(backend, &mut info),
+                "stats" => stats_section(backend, &mut info),
+                _ => keyspace_section(backend, &mut info),
+            }
+        }
+        VerbatimString::new(*b"txt", info).into()
+    }
+}
+
+fn memory_section(backend: &Backend, info: &mut String) {
+    let config = backend.memory_config();
+    let used = backend.used_memory();
+    let peak = backend.peak_memory();
+    let _ = write!(
+        info,
+        "# Memory\r\nused_memory:{}\r\nused_memory_human:{}\r\nused_memory_peak:{}\r\nused_memory_peak_human:{}\r\nmaxmemory:{}\r\nmaxmemory_human:{}\r\nmaxmemory_policy:{}\r\n",
+        used,
+        bytes_to_human(used),
+        peak,
+        bytes_to_human(peak),
+        config.maxmemory(),
+        bytes_to_human(config.maxmemory()),
+        config.policy().name()
+    );
+}
+
+fn persistence_section(backend: &Backend, info: &mut String) {
+    let status = if backend.last_bgsave_ok() {
+        "ok"
+    } else {
+        "err"
+    };
+    let _ = write!(
+        info,
+        "# Persistence\r\nrdb_changes_since_last_save:{}\r\nrdb_bgsave_in_progress:{}\r\nrdb_last_save_time:{}\r\nrdb_last_bgsave_status:{}\r\naof_enabled:{}\r\naof_rewrite_in_progress:{}\r\n",
+        backend.dirty(),
+        backend.save_in_progress() as u8,
+        backend.lastsave(),
+        status,
+        backend.aof().is_enabled() as u8,
+        backend.aof().rewrite_in_progress() as u8
+    );
+}
+
+fn stats_section(backend: &Backend, info: &mut String) {
+    let _ = write!(
+        info,
+        "# Stats\r\nevicted_keys:{}\r\n",
+        backend.evicted_keys()
+    );
+}
+
+fn keyspace_section(backend: &Backend, info: &mut String) {
+    info.push_str("# Keyspace\r\n");
+    let (keys, expires) = backend.key_count();
+    if keys > 0 {
+        let _ = write!(info, "db0:keys={},expires={},avg_ttl=0\r\n", keys, expires);
+    }
+}
+
+// the way redis prints memory amounts, such as `1.50M`
+fn bytes_to_human(bytes: usize) -> String {
+    const UNITS: [(f64, &str); 5] = [
+        ((1u64 << 50) as f64, "P"),
+        ((1u64 << 40) as f64, "T"),
+        ((1u64 << 30) as f64, "G"),
+        ((1u64 << 20) as f64, "M"),
+        ((1u64 << 10) as f64, "K"),
+    ];
+    let bytes = bytes as f64;
+    match UNITS.iter().find(|(unit, _)| bytes >= *unit) {
+        Some((unit, suffix)) => format!("{:.2}{}", bytes / unit, suffix),
+        None => format!("{}B", bytes),
+    }
+}
+
+//INFO [section [section ...]]
+impl TryFrom<RespArray> for Info {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"info") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let sections = extract_args(&value, 1)?
+            .into_iter()
+            .map(|frame| extract_string(frame).map(|s| s.to_ascii_lowercase()))
+            .collect::<Result<Vec<String>, CommandError>>()?;
+        Ok(Info { sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::{BulkString, MaxMemoryPolicy};
+
+    use super::*;
+
+    fn info(backend: &Backend, args: &[&str]) -> Result<String> {
+        let frame = RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        );
+        let cmd: Info = frame.try_into()?;
+        match cmd.execute(backend) {
+            RespFrame::VerbatimString(s) => Ok(String::from_utf8(s.data().to_vec())?),
+            frame => anyhow::bail!("unexpected reply {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_info_sections() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("a".into(), "1".into());
+        backend.set("b".into(), "2".into());
+        backend.memory_config().set_maxmemory(1);
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::AllKeysRandom);
+        let before = info(&backend, &["info", "keyspace"])?;
+        assert_eq!(
+            before,
+            "# Keyspace\r\ndb0:keys=2,expires=0,avg_ttl=0\r\n".to_string()
+        );
+
+        assert!(backend.perform_evictions());
+        let stats = info(&backend, &["info", "STATS"])?;
+        assert_eq!(stats, "# Stats\r\nevicted_keys:2\r\n");
+
+        let all = info(&backend, &["info"])?;
+        assert!(all.starts_with("# Memory\r\nused_memory:0\r\nused_memory_human:0B\r\n"));
+        assert!(all.contains("used_memory_peak:"));
+        assert!(all.contains("maxmemory:1\r\n"));
+        assert!(all.contains("maxmemory_policy:allkeys-random\r\n"));
+        assert!(all.contains("\r\n# Persistence\r\nrdb_changes_since_last_save:"));
+        assert!(all.contains("rdb_bgsave_in_progress:0\r\n"));
+        assert!(all.contains("\r\n# Stats\r\n"));
+        assert!(all.ends_with("# Keyspace\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes_to_human() {
+        assert_eq!(bytes_to_human(1023), "1023B");
+        assert_eq!(bytes_to_human(1536), "1.50K");
+        assert_eq!(bytes_to_human(100 * 1024 * 1024), "100.00M");
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{Backend, RespArray, RespFrame};
+
+use super::{
+    extract_args, extract_bytes, extract_string, CommandError, CommandExcetor, Del, Flush, RESP_OK,
+};
+
+impl CommandExcetor for Del {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let deleted = self.keys.iter().filter(|key| backend.del(key)).count();
+        RespFrame::Integer(deleted as i64)
+    }
+}
+
+//DEL key [key ...]
+impl TryFrom<RespArray> for Del {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"del") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let keys = extract_args(&value, 1)?
+            .into_iter()
+            .map(extract_bytes)
+            .collect::<Result<Vec<_>, CommandError>>()?;
+        if keys.is_empty() {
+            return Err(CommandError::InvalidArgument(
+                "wrong number of arguments for 'del' command".to_string(),
+            ));
+        }
+        Ok(Del { keys })
+    }
+}
+
+impl CommandExcetor for Flush {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        backend.flush();
+        RESP_OK.clone()
+    }
+}
+
+//FLUSHDB [ASYNC | SYNC], FLUSHALL [ASYNC | SYNC]
+impl TryFrom<RespArray> for Flush {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name))
+                if matches!(
+                    name.to_ascii_lowercase().as_slice(),
+                    b"flushdb" | b"flushall"
+                ) => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        match value.len() {
+            1 => Ok(Flush),
+            2 => match extract_string(&value[1])?.to_ascii_lowercase().as_str() {
+                "async" | "sync" => Ok(Flush),
+                _ => Err(CommandError::InvalidArgument("syntax error".to_string())),
+            },
+            _ => Err(CommandError::InvalidArgument("syntax error".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BulkString;
+
+    use super::*;
+
+    #[test]
+    fn test_del_command() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        backend.set("a".into(), "1".into());
+        backend.set("b".into(), "2".into());
+        let frame = RespArray::new(vec![
+            BulkString::new("DEL").into(),
+            BulkString::new("a").into(),
+            BulkString::new("b").into(),
+            BulkString::new("missing").into(),
+        ]);
+        let cmd: Del = frame.try_into()?;
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(2));
+        assert!(!backend.exists(b"a"));
+        assert!(Del::try_from(RespArray::new(vec![BulkString::new("del").into()])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_command() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        backend.set("hello".into(), "world".into());
+        backend.sadd("set".into(), &["a".into()]).unwrap();
+        let frame = RespArray::new(vec![
+            BulkString::new("flushall").into(),
+            BulkString::new("ASYNC").into(),
+        ]);
+        let cmd: Flush = frame.try_into()?;
+        assert_eq!(cmd.execute(&backend), RESP_OK.clone());
+        assert!(!backend.exists(b"hello"));
+        assert!(!backend.exists(b"set"));
+        Ok(())
+    }
+}
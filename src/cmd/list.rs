@@ -0,0 +1,112 @@
+use bytes::Bytes;
+
+use crate::{Backend, BulkString, RespArray, RespFrame};
+
+use super::{
+    extract_args, extract_bytes, extract_integer, validate_command, CommandError, CommandExcetor,
+    LPush, LRange, RPush,
+};
+
+impl CommandExcetor for LPush {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match backend.lpush(self.key.clone(), &self.values) {
+            Ok(len) => RespFrame::Integer(len),
+            Err(e) => e.into(),
+        }
+    }
+}
+impl CommandExcetor for RPush {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match backend.rpush(self.key.clone(), &self.values) {
+            Ok(len) => RespFrame::Integer(len),
+            Err(e) => e.into(),
+        }
+    }
+}
+impl CommandExcetor for LRange {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match backend.lrange(&self.key, self.start, self.stop) {
+            Ok(values) => RespArray::new(
+                values
+                    .into_iter()
+                    .map(|v| BulkString::from(v).into())
+                    .collect::<Vec<RespFrame>>(),
+            )
+            .into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+fn extract_push_args(
+    value: &RespArray,
+    name: &'static str,
+) -> Result<(Bytes, Vec<Bytes>), CommandError> {
+    let n_args = if value.len() > 3 { value.len() - 1 } else { 2 };
+    validate_command(value, &[name], n_args)?;
+    let args = extract_args(value, 1)?;
+    let key = extract_bytes(args[0])?;
+    let values = args[1..]
+        .iter()
+        .map(|v| extract_bytes(v))
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+    Ok((key, values))
+}
+
+impl TryFrom<RespArray> for LPush {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let (key, values) = extract_push_args(&value, "lpush")?;
+        Ok(LPush { key, values })
+    }
+}
+impl TryFrom<RespArray> for RPush {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let (key, values) = extract_push_args(&value, "rpush")?;
+        Ok(RPush { key, values })
+    }
+}
+impl TryFrom<RespArray> for LRange {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["lrange"], 3)?;
+        let args = extract_args(&value, 1)?;
+        Ok(LRange {
+            key: extract_bytes(args[0])?,
+            start: extract_integer(args[1])?,
+            stop: extract_integer(args[2])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::RespDecoder;
+
+    use super::*;
+
+    #[test]
+    fn test_push_lrange() -> Result<()> {
+        let backend = Backend::new();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"*4\r\n$5\r\nrpush\r\n$4\r\nlist\r\n$1\r\nb\r\n$1\r\nc\r\n");
+        let cmd: RPush = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(2));
+
+        buf.extend_from_slice(b"*3\r\n$5\r\nlpush\r\n$4\r\nlist\r\n$1\r\na\r\n");
+        let cmd: LPush = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(3));
+
+        buf.extend_from_slice(b"*4\r\n$6\r\nlrange\r\n$4\r\nlist\r\n$1\r\n1\r\n$2\r\n-1\r\n");
+        let cmd: LRange = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(
+            cmd.execute(&backend),
+            RespArray::new([b"b".into(), b"c".into()]).into()
+        );
+        Ok(())
+    }
+}
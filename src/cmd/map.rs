@@ -1,12 +1,13 @@
-use crate::{Backend, RespArray, RespFrame, RespNull};
+use crate::{Backend, BulkString, RespArray, RespFrame, RespNull};
 
 use super::{extract_args, validate_command, CommandError, CommandExcetor, Get, Set, RESP_OK};
 
 impl CommandExcetor for Get {
     fn execute(&self, backend: &Backend) -> RespFrame {
         match backend.get(&self.key) {
-            Some(v) => v,
-            None => RespFrame::Null(RespNull),
+            Ok(Some(v)) => BulkString::from(v).into(),
+            Ok(None) => RespFrame::Null(RespNull),
+            Err(e) => e.into(),
         }
     }
 }
@@ -23,9 +24,7 @@ impl TryFrom<RespArray> for Get {
         validate_command(&value, &["get"], 1)?;
         let args = extract_args(&value, 1)?;
         match args[0] {
-            RespFrame::BulkString(key) => Ok(Get {
-                key: String::from_utf8_lossy(key).to_string(),
-            }),
+            RespFrame::BulkString(key) => Ok(Get { key: key.0.clone() }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
             )),
@@ -39,9 +38,9 @@ impl TryFrom<RespArray> for Set {
         validate_command(&value, &["set"], 2)?;
         let args = extract_args(&value, 1)?;
         match (args[0], args[1]) {
-            (RespFrame::BulkString(key), value) => Ok(Set {
-                key: String::from_utf8_lossy(key).to_string(),
-                value: value.clone(),
+            (RespFrame::BulkString(key), RespFrame::BulkString(value)) => Ok(Set {
+                key: key.0.clone(),
+                value: value.0.clone(),
             }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
@@ -54,7 +53,7 @@ mod tests {
     use anyhow::Result;
     use bytes::BytesMut;
 
-    use crate::RespDecoder;
+    use crate::{RespDecoder, RespEncoder, WrongType};
 
     use super::*;
 
@@ -77,7 +76,7 @@ mod tests {
         let frame = RespArray::decode(&mut buf)?;
         let result: Set = frame.try_into()?;
         assert_eq!(result.key, "hello");
-        assert_eq!(result.value, RespFrame::BulkString(b"world".into()));
+        assert_eq!(result.value, "world");
         Ok(())
     }
     #[test]
@@ -89,7 +88,7 @@ mod tests {
 
         let backend = Backend::new();
         // let cmd = Set {
-        //     key: "hello".to_string(),
+        //     key: "hello".into(),
         //     value: RespFrame::BulkString(b"world".into()),
         // };
         cmd.execute(&backend);
@@ -100,10 +99,45 @@ mod tests {
         let get_cmd: Get = frame.try_into()?;
 
         // let get_cmd = Get {
-        //     key: "hello".to_string(),
+        //     key: "hello".into(),
         // };
         let result = get_cmd.execute(&backend);
         assert_eq!(result, RespFrame::BulkString(b"world".into()));
         Ok(())
     }
+
+    #[test]
+    fn test_set_get_empty_string() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let mut buf = BytesMut::from(&b"*3\r\n$3\r\nset\r\n$1\r\nk\r\n$0\r\n\r\n"[..]);
+        let cmd: Set = RespArray::decode(&mut buf)?.try_into()?;
+        cmd.execute(&backend);
+
+        let get = Get { key: "k".into() };
+        assert_eq!(get.execute(&backend).encode(), b"$0\r\n\r\n");
+        let get = Get {
+            key: "missing".into(),
+        };
+        assert_eq!(get.execute(&backend), RespFrame::Null(RespNull));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_stores_strings_only() -> anyhow::Result<()> {
+        let mut buf = BytesMut::from(&b"*3\r\n$3\r\nset\r\n$1\r\nk\r\n,1.5\r\n"[..]);
+        assert!(Set::try_from(RespArray::decode(&mut buf)?).is_err());
+
+        let backend = Backend::new();
+        backend.hset("user".into(), "name".into(), "alice".into())?;
+        let get = Get { key: "user".into() };
+        assert_eq!(get.execute(&backend), WrongType.into());
+        // SET replaces a value of any type
+        let set = Set {
+            key: "user".into(),
+            value: "42".into(),
+        };
+        set.execute(&backend);
+        assert_eq!(get.execute(&backend), b"42".into());
+        Ok(())
+    }
 }
@@ -0,0 +1,237 @@
+use crate::{
+    Backend, RespArray, RespFrame, RespMap, RespNull, SimpleString, VerbatimString, DEFAULT_SAMPLES,
+};
+
+use super::{
+    extract_args, extract_bytes, extract_integer, extract_string, CommandError, CommandExcetor,
+    Memory, MemorySubcommand,
+};
+
+const HELP: &[&str] = &[
+    "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "DOCTOR",
+    "    Return memory problems reports.",
+    "STATS",
+    "    Return information about the memory usage of the server.",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 5, 0 means sample all).",
+    "HELP",
+    "    Print this help.",
+];
+
+// below this, there is not enough data for the doctor to tell anything
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
+
+impl CommandExcetor for Memory {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match &self.subcommand {
+            MemorySubcommand::Usage { key, samples } => match backend.memory_usage(key, *samples) {
+                Some(bytes) => RespFrame::Integer(bytes as i64),
+                None => RespFrame::Null(RespNull),
+            },
+            MemorySubcommand::Stats => stats(backend).into(),
+            MemorySubcommand::Doctor => VerbatimString::new(*b"txt", doctor(backend)).into(),
+            MemorySubcommand::Help => {
+                let lines = HELP.iter().map(|line| SimpleString::new(*line).into());
+                RespArray::new(lines.collect::<Vec<RespFrame>>()).into()
+            }
+        }
+    }
+}
+
+fn stats(backend: &Backend) -> RespMap {
+    let used = backend.used_memory();
+    let peak = backend.peak_memory();
+    let (keys, _) = backend.key_count();
+    let mut map = RespMap::new();
+    map.insert("peak.allocated", RespFrame::Integer(peak as i64));
+    map.insert("total.allocated", RespFrame::Integer(used as i64));
+    // nothing is allocated before the first key, the dataset is all there is
+    map.insert("startup.allocated", RespFrame::Integer(0));
+    map.insert("dataset.bytes", RespFrame::Integer(used as i64));
+    map.insert("keys.count", RespFrame::Integer(keys as i64));
+    map.insert(
+        "keys.bytes-per-key",
+        RespFrame::Integer(used.checked_div(keys).unwrap_or(0) as i64),
+    );
+    let percentage = match peak {
+        0 => 0.0,
+        peak => used as f64 * 100.0 / peak as f64,
+    };
+    map.insert("peak.percentage", RespFrame::Double(percentage));
+    map
+}
+
+fn doctor(backend: &Backend) -> String {
+    let used = backend.used_memory();
+    if used < DOCTOR_MIN_MEMORY {
+        return "Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions. Please, leave for your mission on Earth and fill it with some data. The new Sam and I will be back to our programming as soon as I finished rebooting.".to_string();
+    }
+    let mut issues = Vec::new();
+    if backend.peak_memory() as f64 > used as f64 * 1.5 {
+        issues.push(" * Peak memory: In the past this instance used more than 150% the memory that is currently using. The allocator is normally not able to release memory after a peak, so the process may hold more memory than the dataset needs until it is filled again.");
+    }
+    let maxmemory = backend.memory_config().maxmemory();
+    if maxmemory > 0 && used as f64 > maxmemory as f64 * 0.9 {
+        issues.push(" * Max memory: The dataset takes more than 90% of maxmemory. Writes will soon evict keys, or be refused with OOM errors under the noeviction policy, consider raising maxmemory.");
+    }
+    if issues.is_empty() {
+        return "Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base.".to_string();
+    }
+    format!(
+        "Sam, I detected a few issues in this Redis instance memory implants:\n\n{}\n\nI'm here to keep you safe, Sam. I want to help you.\n",
+        issues.join("\n\n")
+    )
+}
+
+//MEMORY USAGE key [SAMPLES count] | STATS | DOCTOR | HELP
+impl TryFrom<RespArray> for Memory {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"memory") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let args = extract_args(&value, 1)?;
+        let Some((subcommand, args)) = args.split_first() else {
+            return Err(CommandError::InvalidArgument(
+                "wrong number of arguments for 'memory' command".to_string(),
+            ));
+        };
+        let subcommand = extract_string(subcommand)?;
+        let subcommand = match (subcommand.to_ascii_lowercase().as_str(), args) {
+            ("usage", [key]) => MemorySubcommand::Usage {
+                key: extract_bytes(key)?,
+                samples: DEFAULT_SAMPLES,
+            },
+            ("usage", [key, option, count])
+                if extract_string(option)?.eq_ignore_ascii_case("samples") =>
+            {
+                MemorySubcommand::Usage {
+                    key: extract_bytes(key)?,
+                    samples: extract_integer(count)?,
+                }
+            }
+            ("usage", [_, ..]) => {
+                return Err(CommandError::InvalidArgument("syntax error".to_string()))
+            }
+            ("stats", []) => MemorySubcommand::Stats,
+            ("doctor", []) => MemorySubcommand::Doctor,
+            ("help", []) => MemorySubcommand::Help,
+            _ => {
+                return Err(CommandError::InvalidArgument(format!(
+                    "unknown subcommand or wrong number of arguments for '{}'. Try MEMORY HELP.",
+                    subcommand
+                )))
+            }
+        };
+        Ok(Memory { subcommand })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::Bytes;
+
+    use crate::BulkString;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    fn integer(frame: RespFrame) -> i64 {
+        match frame {
+            RespFrame::Integer(v) => v,
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_memory_usage() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("short".into(), "hello".into());
+        backend.set("long".into(), vec![b'x'; 1000].into());
+        let values = (0..1000)
+            .map(|i| Bytes::from(vec![b'x'; i]))
+            .collect::<Vec<_>>();
+        backend.rpush("list".into(), &values)?;
+
+        let short: Memory = command(&["memory", "usage", "short"]).try_into()?;
+        let long: Memory = command(&["MEMORY", "USAGE", "long"]).try_into()?;
+        let short = integer(short.execute(&backend));
+        assert!(short > 0);
+        assert!(integer(long.execute(&backend)) > 1000);
+
+        let all: Memory = command(&["memory", "usage", "list", "SAMPLES", "0"]).try_into()?;
+        let all = integer(all.execute(&backend));
+        assert_eq!(backend.memory_usage(b"list", 0), Some(all as usize));
+        // the first few values are the shortest, sampling them underestimates the list
+        let sampled: Memory = command(&["memory", "usage", "list"]).try_into()?;
+        assert!(integer(sampled.execute(&backend)) < all);
+
+        let missing: Memory = command(&["memory", "usage", "missing"]).try_into()?;
+        assert_eq!(missing.execute(&backend), RespFrame::Null(RespNull));
+
+        assert!(Memory::try_from(command(&["memory", "usage", "k", "samples"])).is_err());
+        assert!(Memory::try_from(command(&["memory", "usage", "k", "count", "1"])).is_err());
+        assert!(Memory::try_from(command(&["memory", "usage", "k", "samples", "-1"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_stats() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("a".into(), "hello".into());
+        backend.set("b".into(), "world".into());
+        let cmd: Memory = command(&["memory", "stats"]).try_into()?;
+        let RespFrame::Map(map) = cmd.execute(&backend) else {
+            panic!("expected a map");
+        };
+        let used = backend.used_memory() as i64;
+        assert_eq!(
+            map.0[b"total.allocated".as_slice()],
+            RespFrame::Integer(used)
+        );
+        assert_eq!(map.0[b"keys.count".as_slice()], RespFrame::Integer(2));
+        assert_eq!(
+            map.0[b"keys.bytes-per-key".as_slice()],
+            RespFrame::Integer(used / 2)
+        );
+        assert_eq!(
+            map.0[b"peak.percentage".as_slice()],
+            RespFrame::Double(100.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_doctor() -> Result<()> {
+        let backend = Backend::new();
+        let cmd: Memory = command(&["memory", "doctor"]).try_into()?;
+        let report = |backend: &Backend| match cmd.execute(backend) {
+            RespFrame::VerbatimString(s) => String::from_utf8_lossy(s.data()).to_string(),
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+        assert!(report(&backend).contains("empty"));
+
+        for i in 0..10 {
+            backend.set(format!("key:{}", i).into(), vec![b'x'; 1024 * 1024].into());
+        }
+        assert!(report(&backend).contains("can't find any memory issue"));
+        backend.memory_config().set_maxmemory(backend.used_memory());
+        assert!(report(&backend).contains("Max memory"));
+
+        let cmd: Memory = command(&["memory", "help"]).try_into()?;
+        assert!(matches!(cmd.execute(&backend), RespFrame::Array(_)));
+        assert!(Memory::try_from(command(&["memory", "stats", "x"])).is_err());
+        Ok(())
+    }
+}
@@ -1,16 +1,39 @@
+mod config;
+mod dump;
 mod echo;
+mod function;
+mod hello;
 mod hmap;
+mod info;
+mod keyspace;
+mod list;
 mod map;
+mod memory;
+mod object;
+mod pubsub;
+mod save;
+mod script;
 mod sismember;
+mod sort;
+mod transaction;
+mod zset;
 
-use crate::{Backend, RespArray, RespError, RespFrame, SimpleString};
+pub use config::configure;
+pub(crate) use hello::REDIS_VERSION;
+
+use std::sync::Mutex;
+
+use crate::{
+    now_ms, Backend, BulkString, RespArray, RespError, RespFrame, RestorePolicy, SimpleString,
+};
 use anyhow::Result;
+use bytes::Bytes;
 use enum_dispatch::enum_dispatch;
 use lazy_static::lazy_static;
 use thiserror::Error;
 
 lazy_static! {
-    static ref RESP_OK: RespFrame = SimpleString::new("OK".to_string()).into();
+    pub(crate) static ref RESP_OK: RespFrame = SimpleString::new("OK".to_string()).into();
 }
 #[derive(Error, Debug)]
 pub enum CommandError {
@@ -37,57 +60,440 @@ pub enum Command {
     HgetAll(HGetAll),
     Unrecognized(Unrecognized),
     Echo(Echo),
+    Ping(Ping),
+    Quit(Quit),
+    Reset(Reset),
     HMget(HMget),
     Sadd(Sadd),
     Sismember(Sismember),
+    Dump(Dump),
+    Restore(Restore),
+    Migrate(Migrate),
+    LPush(LPush),
+    RPush(RPush),
+    LRange(LRange),
+    ZAdd(ZAdd),
+    ZRange(ZRange),
+    Sort(Sort),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Flush(Flush),
+    Del(Del),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    Function(Function),
+    FCall(FCall),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    PSubscribe(PSubscribe),
+    PUnsubscribe(PUnsubscribe),
+    Publish(Publish),
+    PubSub(PubSub),
+    SSubscribe(SSubscribe),
+    SUnsubscribe(SUnsubscribe),
+    SPublish(SPublish),
+    Config(Config),
+    Hello(Hello),
+    Object(Object),
+    Info(Info),
+    Memory(Memory),
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
 }
 
 #[derive(Debug)]
 pub struct Sismember {
-    key: String,
-    member: String,
+    key: Bytes,
+    member: Bytes,
 }
 #[derive(Debug)]
 pub struct Sadd {
-    key: String,
-    members: Vec<String>,
+    key: Bytes,
+    members: Vec<Bytes>,
 }
 #[derive(Debug)]
 pub struct HMget {
-    key: String,
-    fields: Vec<String>,
+    key: Bytes,
+    fields: Vec<Bytes>,
 }
 #[derive(Debug)]
 pub struct Get {
-    key: String,
+    key: Bytes,
 }
 #[derive(Debug)]
 pub struct Unrecognized;
 
 #[derive(Debug)]
 pub struct Set {
-    key: String,
-    value: RespFrame,
+    key: Bytes,
+    value: Bytes,
 }
 #[derive(Debug)]
 pub struct HGet {
-    key: String,
-    field: String,
+    key: Bytes,
+    field: Bytes,
 }
 #[derive(Debug)]
 pub struct HSet {
-    key: String,
-    field: String,
-    value: RespFrame,
+    key: Bytes,
+    field: Bytes,
+    value: Bytes,
 }
 #[derive(Debug)]
 pub struct HGetAll {
-    key: String,
+    key: Bytes,
 }
 
 #[derive(Debug)]
 pub struct Echo {
-    message: String,
+    message: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Ping {
+    message: Option<Bytes>,
+}
+#[derive(Debug)]
+pub struct Quit;
+#[derive(Debug)]
+pub struct Reset;
+
+#[derive(Debug)]
+pub struct Dump {
+    key: Bytes,
+}
+#[derive(Debug)]
+pub struct Restore {
+    key: Bytes,
+    ttl: u64,
+    payload: Vec<u8>,
+    replace: bool,
+    absttl: bool,
+    idletime: Option<u64>,
+    freq: Option<u8>,
+}
+#[derive(Debug)]
+pub struct Migrate {
+    host: String,
+    port: u16,
+    keys: Vec<Bytes>,
+    db: u64,
+    timeout: u64,
+    copy: bool,
+    replace: bool,
+    auth: Option<(Option<String>, String)>,
+    // the keys removed so far, as the target acknowledged them
+    deleted: Mutex<Vec<Bytes>>,
+}
+#[derive(Debug)]
+pub struct LPush {
+    key: Bytes,
+    values: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct RPush {
+    key: Bytes,
+    values: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct LRange {
+    key: Bytes,
+    start: i64,
+    stop: i64,
+}
+#[derive(Debug)]
+pub struct ZAdd {
+    key: Bytes,
+    members: Vec<(Bytes, f64)>,
+}
+#[derive(Debug)]
+pub struct ZRange {
+    key: Bytes,
+    start: i64,
+    stop: i64,
+    withscores: bool,
+}
+#[derive(Debug)]
+pub struct Sort {
+    key: Bytes,
+    by: Option<Bytes>,
+    limit: Option<(i64, i64)>,
+    get: Vec<Bytes>,
+    desc: bool,
+    alpha: bool,
+    store: Option<Bytes>,
+}
+#[derive(Debug)]
+pub struct Multi;
+#[derive(Debug)]
+pub struct Exec;
+#[derive(Debug)]
+pub struct Discard;
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct Unwatch;
+#[derive(Debug)]
+pub struct Flush;
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct Eval {
+    script: String,
+    keys: Vec<Bytes>,
+    args: Vec<Vec<u8>>,
+}
+#[derive(Debug)]
+pub struct EvalSha {
+    sha: String,
+    keys: Vec<Bytes>,
+    args: Vec<Vec<u8>>,
+}
+#[derive(Debug)]
+pub struct Script {
+    subcommand: ScriptSubcommand,
+}
+#[derive(Debug)]
+enum ScriptSubcommand {
+    Load(String),
+    Exists(Vec<String>),
+    Flush,
+    Kill,
+}
+#[derive(Debug)]
+pub struct Function {
+    subcommand: FunctionSubcommand,
+}
+#[derive(Debug)]
+enum FunctionSubcommand {
+    Load {
+        code: String,
+        replace: bool,
+    },
+    List {
+        pattern: Option<String>,
+        with_code: bool,
+    },
+    Delete(String),
+    Dump,
+    Restore {
+        payload: Vec<u8>,
+        policy: RestorePolicy,
+    },
+    Flush,
+    Kill,
+}
+#[derive(Debug)]
+pub struct FCall {
+    function: String,
+    keys: Vec<Bytes>,
+    args: Vec<Vec<u8>>,
+    read_only: bool,
+}
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct PSubscribe {
+    patterns: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct PUnsubscribe {
+    patterns: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct Publish {
+    channel: Bytes,
+    message: Vec<u8>,
+}
+#[derive(Debug)]
+pub struct SSubscribe {
+    channels: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct SUnsubscribe {
+    channels: Vec<Bytes>,
+}
+#[derive(Debug)]
+pub struct SPublish {
+    channel: Bytes,
+    message: Vec<u8>,
+}
+#[derive(Debug)]
+pub struct Hello {
+    protover: Option<i64>,
+    auth: Option<(String, String)>,
+    setname: Option<String>,
+}
+#[derive(Debug)]
+pub struct Config {
+    subcommand: ConfigSubcommand,
+}
+#[derive(Debug)]
+pub enum ConfigSubcommand {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+#[derive(Debug)]
+pub struct Object {
+    subcommand: ObjectSubcommand,
+}
+#[derive(Debug)]
+enum ObjectSubcommand {
+    Encoding(Bytes),
+    RefCount(Bytes),
+    IdleTime(Bytes),
+    Freq(Bytes),
+    Help,
+}
+#[derive(Debug)]
+pub struct Memory {
+    subcommand: MemorySubcommand,
+}
+#[derive(Debug)]
+enum MemorySubcommand {
+    Usage { key: Bytes, samples: usize },
+    Stats,
+    Doctor,
+    Help,
+}
+#[derive(Debug)]
+pub struct Save;
+#[derive(Debug)]
+pub struct BgSave;
+#[derive(Debug)]
+pub struct LastSave;
+#[derive(Debug)]
+pub struct Info {
+    sections: Vec<String>,
+}
+#[derive(Debug)]
+pub struct PubSub {
+    subcommand: PubSubSubcommand,
+}
+#[derive(Debug)]
+enum PubSubSubcommand {
+    Channels(Option<Bytes>),
+    NumSub(Vec<Bytes>),
+    NumPat,
+    ShardChannels(Option<Bytes>),
+    ShardNumSub(Vec<Bytes>),
+}
+
+impl Command {
+    /// Whether the command may modify the dataset.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Command::Set(_)
+            | Command::Hset(_)
+            | Command::Sadd(_)
+            | Command::Restore(_)
+            | Command::Migrate(_)
+            | Command::LPush(_)
+            | Command::RPush(_)
+            | Command::ZAdd(_)
+            | Command::Flush(_)
+            | Command::Del(_)
+            | Command::Eval(_)
+            | Command::EvalSha(_) => true,
+            Command::Sort(sort) => sort.store.is_some(),
+            Command::FCall(fcall) => !fcall.read_only,
+            Command::Function(function) => function.is_write(),
+            _ => false,
+        }
+    }
+    /// Whether the command may grow the dataset, and so is refused once over `maxmemory`.
+    pub fn is_denyoom(&self) -> bool {
+        match self {
+            Command::Set(_)
+            | Command::Hset(_)
+            | Command::Sadd(_)
+            | Command::Restore(_)
+            | Command::LPush(_)
+            | Command::RPush(_)
+            | Command::ZAdd(_)
+            | Command::Eval(_)
+            | Command::EvalSha(_) => true,
+            Command::Sort(sort) => sort.store.is_some(),
+            Command::FCall(fcall) => !fcall.read_only,
+            Command::Function(function) => matches!(
+                function.subcommand,
+                FunctionSubcommand::Load { .. } | FunctionSubcommand::Restore { .. }
+            ),
+            _ => false,
+        }
+    }
+    /// SCRIPT KILL and FUNCTION KILL have to get through while another client runs a script.
+    pub fn is_script_kill(&self) -> bool {
+        matches!(
+            self,
+            Command::Script(Script {
+                subcommand: ScriptSubcommand::Kill
+            }) | Command::Function(Function {
+                subcommand: FunctionSubcommand::Kill
+            })
+        )
+    }
+    /// The requests that redo what `request` did to the dataset once replayed, for the append
+    /// only file. Scripts are replaced by the writes they made, like redis 7, and those and
+    /// MIGRATE are kept even when they fail, as they may have written before.
+    pub fn propagated(
+        &self,
+        request: RespFrame,
+        reply: &RespFrame,
+        backend: &Backend,
+    ) -> Vec<RespFrame> {
+        let failed = matches!(reply, RespFrame::Error(_) | RespFrame::BulkError(_));
+        let partial = matches!(
+            self,
+            Command::Eval(_) | Command::EvalSha(_) | Command::FCall(_) | Command::Migrate(_)
+        );
+        if !self.is_write() || (failed && !partial) {
+            return Vec::new();
+        }
+        match (self, request) {
+            // the keys left for the other instance
+            (Command::Migrate(migrate), _) => {
+                let deleted = migrate.deleted();
+                if deleted.is_empty() {
+                    return Vec::new();
+                }
+                let mut args = vec![RespFrame::from(b"DEL")];
+                args.extend(deleted.into_iter().map(bulk));
+                vec![RespArray::new(args).into()]
+            }
+            // a relative ttl would start over on every replay
+            (Command::Restore(restore), RespFrame::Array(mut args))
+                if restore.ttl > 0 && !restore.absttl =>
+            {
+                args.0[2] = bulk((now_ms() + restore.ttl).to_string());
+                args.0.push(b"ABSTTL".into());
+                vec![args.into()]
+            }
+            (Command::Eval(_) | Command::EvalSha(_) | Command::FCall(_), _) => {
+                backend.scripts().take_effects()
+            }
+            (_, request) => vec![request],
+        }
+    }
+}
+
+fn bulk(value: impl Into<Vec<u8>>) -> RespFrame {
+    BulkString::new(value).into()
 }
 
 impl TryFrom<RespFrame> for Command {
@@ -111,9 +517,50 @@ impl TryFrom<RespArray> for Command {
                     b"hset" => Ok(HSet::try_from(frame)?.into()),
                     b"hgetall" => Ok(HGetAll::try_from(frame)?.into()),
                     b"echo" => Ok(Echo::try_from(frame)?.into()),
+                    b"ping" => Ok(Ping::try_from(frame)?.into()),
+                    b"quit" => Ok(Quit::try_from(frame)?.into()),
+                    b"reset" => Ok(Reset::try_from(frame)?.into()),
                     b"hmget" => Ok(HMget::try_from(frame)?.into()),
                     b"sadd" => Ok(Sadd::try_from(frame)?.into()),
                     b"sismember" => Ok(Sismember::try_from(frame)?.into()),
+                    b"dump" => Ok(Dump::try_from(frame)?.into()),
+                    b"restore" => Ok(Restore::try_from(frame)?.into()),
+                    b"migrate" => Ok(Migrate::try_from(frame)?.into()),
+                    b"lpush" => Ok(LPush::try_from(frame)?.into()),
+                    b"rpush" => Ok(RPush::try_from(frame)?.into()),
+                    b"lrange" => Ok(LRange::try_from(frame)?.into()),
+                    b"zadd" => Ok(ZAdd::try_from(frame)?.into()),
+                    b"zrange" => Ok(ZRange::try_from(frame)?.into()),
+                    b"sort" | b"sort_ro" => Ok(Sort::try_from(frame)?.into()),
+                    b"multi" => Ok(Multi::try_from(frame)?.into()),
+                    b"exec" => Ok(Exec::try_from(frame)?.into()),
+                    b"discard" => Ok(Discard::try_from(frame)?.into()),
+                    b"watch" => Ok(Watch::try_from(frame)?.into()),
+                    b"unwatch" => Ok(Unwatch::try_from(frame)?.into()),
+                    b"flushdb" | b"flushall" => Ok(Flush::try_from(frame)?.into()),
+                    b"del" => Ok(Del::try_from(frame)?.into()),
+                    b"eval" => Ok(Eval::try_from(frame)?.into()),
+                    b"evalsha" => Ok(EvalSha::try_from(frame)?.into()),
+                    b"script" => Ok(Script::try_from(frame)?.into()),
+                    b"function" => Ok(Function::try_from(frame)?.into()),
+                    b"fcall" | b"fcall_ro" => Ok(FCall::try_from(frame)?.into()),
+                    b"subscribe" => Ok(Subscribe::try_from(frame)?.into()),
+                    b"unsubscribe" => Ok(Unsubscribe::try_from(frame)?.into()),
+                    b"psubscribe" => Ok(PSubscribe::try_from(frame)?.into()),
+                    b"punsubscribe" => Ok(PUnsubscribe::try_from(frame)?.into()),
+                    b"publish" => Ok(Publish::try_from(frame)?.into()),
+                    b"pubsub" => Ok(PubSub::try_from(frame)?.into()),
+                    b"ssubscribe" => Ok(SSubscribe::try_from(frame)?.into()),
+                    b"sunsubscribe" => Ok(SUnsubscribe::try_from(frame)?.into()),
+                    b"spublish" => Ok(SPublish::try_from(frame)?.into()),
+                    b"config" => Ok(Config::try_from(frame)?.into()),
+                    b"hello" => Ok(Hello::try_from(frame)?.into()),
+                    b"object" => Ok(Object::try_from(frame)?.into()),
+                    b"info" => Ok(Info::try_from(frame)?.into()),
+                    b"memory" => Ok(Memory::try_from(frame)?.into()),
+                    b"save" => Ok(Save::try_from(frame)?.into()),
+                    b"bgsave" => Ok(BgSave::try_from(frame)?.into()),
+                    b"lastsave" => Ok(LastSave::try_from(frame)?.into()),
                     _ => Ok(Unrecognized.into()),
                 }
             }
@@ -170,3 +617,26 @@ fn extract_args_hmget(value: &RespArray) -> Result<(&RespFrame, Vec<&RespFrame>)
     let fields = value.iter().skip(2).collect::<Vec<&RespFrame>>();
     Ok((key, fields))
 }
+
+fn extract_string(frame: &RespFrame) -> Result<String, CommandError> {
+    match frame {
+        RespFrame::BulkString(v) => Ok(String::from_utf8_lossy(v).to_string()),
+        _ => Err(CommandError::InvalidArgument(
+            "Invalid argument".to_string(),
+        )),
+    }
+}
+/// The raw bytes of a key, field, member or value, sharing the request buffer.
+fn extract_bytes(frame: &RespFrame) -> Result<Bytes, CommandError> {
+    match frame {
+        RespFrame::BulkString(v) => Ok(v.0.clone()),
+        _ => Err(CommandError::InvalidArgument(
+            "Invalid argument".to_string(),
+        )),
+    }
+}
+fn extract_integer<T: std::str::FromStr>(frame: &RespFrame) -> Result<T, CommandError> {
+    extract_string(frame)?.parse().map_err(|_| {
+        CommandError::InvalidArgument("value is not an integer or out of range".to_string())
+    })
+}
@@ -0,0 +1,189 @@
+use crate::{
+    Backend, BulkString, MaxMemoryPolicy, RespArray, RespFrame, RespNull, SimpleError, SimpleString,
+};
+
+use super::{
+    extract_args, extract_bytes, extract_string, CommandError, CommandExcetor, Object,
+    ObjectSubcommand,
+};
+
+const HELP: &[&str] = &[
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+impl CommandExcetor for Object {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let lfu = matches!(
+            backend.memory_config().policy(),
+            MaxMemoryPolicy::AllKeysLfu | MaxMemoryPolicy::VolatileLfu
+        );
+        let reply = match &self.subcommand {
+            ObjectSubcommand::Encoding(key) => backend
+                .encoding(key)
+                .map(|encoding| BulkString::new(encoding).into()),
+            ObjectSubcommand::RefCount(key) => backend.refcount(key).map(RespFrame::Integer),
+            // redis only keeps the one the policy needs, so the other one is not reliable
+            ObjectSubcommand::IdleTime(_) if lfu => {
+                return SimpleError::new("ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.").into()
+            }
+            ObjectSubcommand::IdleTime(key) => backend
+                .idle_time(key)
+                .map(|ms| RespFrame::Integer((ms / 1000) as i64)),
+            ObjectSubcommand::Freq(_) if !lfu => {
+                return SimpleError::new("ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.").into()
+            }
+            ObjectSubcommand::Freq(key) => backend
+                .access_freq(key)
+                .map(|freq| RespFrame::Integer(freq as i64)),
+            ObjectSubcommand::Help => {
+                let lines = HELP.iter().map(|line| SimpleString::new(*line).into());
+                return RespArray::new(lines.collect::<Vec<RespFrame>>()).into();
+            }
+        };
+        reply.unwrap_or(RespFrame::Null(RespNull))
+    }
+}
+
+//OBJECT ENCODING|REFCOUNT|IDLETIME|FREQ key, OBJECT HELP
+impl TryFrom<RespArray> for Object {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"object") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let args = extract_args(&value, 1)?;
+        let Some((subcommand, args)) = args.split_first() else {
+            return Err(CommandError::InvalidArgument(
+                "wrong number of arguments for 'object' command".to_string(),
+            ));
+        };
+        let subcommand = extract_string(subcommand)?;
+        let subcommand = match (subcommand.to_ascii_lowercase().as_str(), args) {
+            ("encoding", [key]) => ObjectSubcommand::Encoding(extract_bytes(key)?),
+            ("refcount", [key]) => ObjectSubcommand::RefCount(extract_bytes(key)?),
+            ("idletime", [key]) => ObjectSubcommand::IdleTime(extract_bytes(key)?),
+            ("freq", [key]) => ObjectSubcommand::Freq(extract_bytes(key)?),
+            ("help", []) => ObjectSubcommand::Help,
+            _ => {
+                return Err(CommandError::InvalidArgument(format!(
+                    "unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+                    subcommand
+                )))
+            }
+        };
+        Ok(Object { subcommand })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_object_encoding() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("n".into(), "12".into());
+        backend.set("s".into(), "hello".into());
+        backend.sadd("ints".into(), &["1".into(), "2".into()])?;
+        backend.sadd("names".into(), &["a".into()])?;
+        backend.rpush("list".into(), &["a".into()])?;
+        backend.zadd("zset".into(), &[("a".into(), 1.0)])?;
+        backend.hset("hash".into(), "f".into(), "v".into())?;
+        backend.update_encoding_limits(|limits| limits.hash_max_listpack_entries = 1);
+        backend.hset("hash".into(), "g".into(), "v".into())?;
+
+        for (key, encoding) in [
+            ("n", "int"),
+            ("s", "embstr"),
+            ("ints", "intset"),
+            ("names", "listpack"),
+            ("list", "listpack"),
+            ("zset", "listpack"),
+            ("hash", "hashtable"),
+        ] {
+            let cmd: Object = command(&["object", "encoding", key]).try_into()?;
+            assert_eq!(cmd.execute(&backend), BulkString::new(encoding).into());
+        }
+        let cmd: Object = command(&["OBJECT", "ENCODING", "missing"]).try_into()?;
+        assert_eq!(cmd.execute(&backend), RespFrame::Null(RespNull));
+
+        assert!(Object::try_from(command(&["object", "encoding"])).is_err());
+        assert!(Object::try_from(command(&["object", "nope", "n"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_refcount() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("shared".into(), "100".into());
+        backend.set("big".into(), "100000".into());
+        backend.set("s".into(), "hello".into());
+        for (key, refcount) in [("shared", i32::MAX as i64), ("big", 1), ("s", 1)] {
+            let cmd: Object = command(&["object", "refcount", key]).try_into()?;
+            assert_eq!(cmd.execute(&backend), RespFrame::Integer(refcount));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_idletime_freq() -> Result<()> {
+        let backend = Backend::new();
+        backend.set("key".into(), "value".into());
+        backend.set_access(b"key", Some(5_000), None);
+
+        let idletime: Object = command(&["object", "idletime", "key"]).try_into()?;
+        let freq: Object = command(&["object", "freq", "key"]).try_into()?;
+        assert_eq!(idletime.execute(&backend), RespFrame::Integer(5));
+        // looking at a key doesn't count as an access
+        assert_eq!(idletime.execute(&backend), RespFrame::Integer(5));
+        assert!(matches!(freq.execute(&backend), RespFrame::Error(_)));
+
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::AllKeysLfu);
+        backend.set_access(b"key", None, Some(42));
+        assert_eq!(freq.execute(&backend), RespFrame::Integer(42));
+        assert!(matches!(idletime.execute(&backend), RespFrame::Error(_)));
+
+        let cmd: Object = command(&["object", "freq", "missing"]).try_into()?;
+        assert_eq!(cmd.execute(&backend), RespFrame::Null(RespNull));
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_help() -> Result<()> {
+        let backend = Backend::new();
+        let cmd: Object = command(&["object", "help"]).try_into()?;
+        match cmd.execute(&backend) {
+            RespFrame::Array(lines) => assert_eq!(lines.len(), HELP.len()),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        assert!(Object::try_from(command(&["object", "help", "x"])).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+use bytes::Bytes;
+
+use crate::{Backend, BulkString, RespArray, RespFrame, SimpleError};
+
+use super::{
+    extract_args, extract_bytes, CommandError, CommandExcetor, PSubscribe, PUnsubscribe, PubSub,
+    PubSubSubcommand, Publish, SPublish, SSubscribe, SUnsubscribe, Subscribe, Unsubscribe,
+};
+
+impl Subscribe {
+    pub fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+}
+impl Unsubscribe {
+    pub fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+}
+impl PSubscribe {
+    pub fn patterns(&self) -> &[Bytes] {
+        &self.patterns
+    }
+}
+impl PUnsubscribe {
+    pub fn patterns(&self) -> &[Bytes] {
+        &self.patterns
+    }
+}
+
+impl SSubscribe {
+    pub fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+}
+impl SUnsubscribe {
+    pub fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+}
+
+// (P|S)SUBSCRIBE and (P|S)UNSUBSCRIBE change the connection state and are handled by the connection
+// session, they can't be executed anywhere else.
+fn subscribe_not_allowed(name: &str) -> RespFrame {
+    SimpleError::new(format!(
+        "ERR {} is only allowed outside of MULTI and scripts",
+        name
+    ))
+    .into()
+}
+impl CommandExcetor for Subscribe {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        subscribe_not_allowed("SUBSCRIBE")
+    }
+}
+impl CommandExcetor for Unsubscribe {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        subscribe_not_allowed("UNSUBSCRIBE")
+    }
+}
+impl CommandExcetor for PSubscribe {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        subscribe_not_allowed("PSUBSCRIBE")
+    }
+}
+impl CommandExcetor for PUnsubscribe {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        subscribe_not_allowed("PUNSUBSCRIBE")
+    }
+}
+
+impl CommandExcetor for SSubscribe {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        subscribe_not_allowed("SSUBSCRIBE")
+    }
+}
+impl CommandExcetor for SUnsubscribe {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        subscribe_not_allowed("SUNSUBSCRIBE")
+    }
+}
+
+impl CommandExcetor for Publish {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        RespFrame::Integer(backend.pubsub().publish(&self.channel, &self.message))
+    }
+}
+
+impl CommandExcetor for SPublish {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        RespFrame::Integer(
+            backend
+                .shard_pubsub()
+                .spublish(&self.channel, &self.message),
+        )
+    }
+}
+
+impl CommandExcetor for PubSub {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let registry = backend.pubsub();
+        match &self.subcommand {
+            PubSubSubcommand::Channels(pattern) => {
+                channels_reply(registry.channels(pattern.as_deref()))
+            }
+            PubSubSubcommand::NumSub(channels) => {
+                numsub_reply(channels, |channel| registry.numsub(channel))
+            }
+            PubSubSubcommand::NumPat => RespFrame::Integer(registry.numpat()),
+            PubSubSubcommand::ShardChannels(pattern) => {
+                channels_reply(backend.shard_pubsub().channels(pattern.as_deref()))
+            }
+            PubSubSubcommand::ShardNumSub(channels) => {
+                numsub_reply(channels, |channel| backend.shard_pubsub().numsub(channel))
+            }
+        }
+    }
+}
+
+fn channels_reply(channels: Vec<Bytes>) -> RespFrame {
+    let channels = channels
+        .into_iter()
+        .map(|channel| BulkString::from(channel).into())
+        .collect::<Vec<RespFrame>>();
+    RespArray::new(channels).into()
+}
+
+// `[channel, subscribers, ...]`
+fn numsub_reply(channels: &[Bytes], numsub: impl Fn(&[u8]) -> i64) -> RespFrame {
+    let mut frames = Vec::with_capacity(channels.len() * 2);
+    for channel in channels {
+        frames.push(BulkString::from(channel.clone()).into());
+        frames.push(RespFrame::Integer(numsub(channel)));
+    }
+    RespArray::new(frames).into()
+}
+
+//SUBSCRIBE channel [channel ...]
+impl TryFrom<RespArray> for Subscribe {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let channels = extract_names(&value, "subscribe", false)?;
+        Ok(Subscribe { channels })
+    }
+}
+//UNSUBSCRIBE [channel [channel ...]]
+impl TryFrom<RespArray> for Unsubscribe {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let channels = extract_names(&value, "unsubscribe", true)?;
+        Ok(Unsubscribe { channels })
+    }
+}
+//PSUBSCRIBE pattern [pattern ...]
+impl TryFrom<RespArray> for PSubscribe {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let patterns = extract_names(&value, "psubscribe", false)?;
+        Ok(PSubscribe { patterns })
+    }
+}
+//PUNSUBSCRIBE [pattern [pattern ...]]
+impl TryFrom<RespArray> for PUnsubscribe {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let patterns = extract_names(&value, "punsubscribe", true)?;
+        Ok(PUnsubscribe { patterns })
+    }
+}
+
+//SSUBSCRIBE shardchannel [shardchannel ...]
+impl TryFrom<RespArray> for SSubscribe {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let channels = extract_names(&value, "ssubscribe", false)?;
+        Ok(SSubscribe { channels })
+    }
+}
+//SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+impl TryFrom<RespArray> for SUnsubscribe {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let channels = extract_names(&value, "sunsubscribe", true)?;
+        Ok(SUnsubscribe { channels })
+    }
+}
+
+//PUBLISH channel message
+impl TryFrom<RespArray> for Publish {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let (channel, message) = extract_message(&value, "publish")?;
+        Ok(Publish { channel, message })
+    }
+}
+//SPUBLISH shardchannel message
+impl TryFrom<RespArray> for SPublish {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let (channel, message) = extract_message(&value, "spublish")?;
+        Ok(SPublish { channel, message })
+    }
+}
+
+//PUBSUB CHANNELS [pattern] | NUMSUB [channel [channel ...]] | NUMPAT
+//    | SHARDCHANNELS [pattern] | SHARDNUMSUB [shardchannel [shardchannel ...]]
+impl TryFrom<RespArray> for PubSub {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let args = extract_names(&value, "pubsub", false)?;
+        let syntax_err = || CommandError::InvalidArgument("syntax error".to_string());
+        let subcommand = match (args[0].to_ascii_lowercase().as_slice(), &args[1..]) {
+            (b"channels", []) => PubSubSubcommand::Channels(None),
+            (b"channels", [pattern]) => PubSubSubcommand::Channels(Some(pattern.clone())),
+            (b"numsub", channels) => PubSubSubcommand::NumSub(channels.to_vec()),
+            (b"numpat", []) => PubSubSubcommand::NumPat,
+            (b"shardchannels", []) => PubSubSubcommand::ShardChannels(None),
+            (b"shardchannels", [pattern]) => PubSubSubcommand::ShardChannels(Some(pattern.clone())),
+            (b"shardnumsub", channels) => PubSubSubcommand::ShardNumSub(channels.to_vec()),
+            _ => return Err(syntax_err()),
+        };
+        Ok(PubSub { subcommand })
+    }
+}
+
+fn extract_message(value: &RespArray, name: &str) -> Result<(Bytes, Vec<u8>), CommandError> {
+    extract_names(value, name, false)?;
+    if value.len() != 3 {
+        return Err(CommandError::InvalidArgument(format!(
+            "{} command must have 2 argument",
+            name
+        )));
+    }
+    match &value[2] {
+        RespFrame::BulkString(message) => Ok((extract_bytes(&value[1])?, message.to_vec())),
+        _ => Err(CommandError::InvalidArgument(
+            "Invalid argument".to_string(),
+        )),
+    }
+}
+
+// the arguments following the command name, which may only be missing if `optional`
+fn extract_names(
+    value: &RespArray,
+    name: &str,
+    optional: bool,
+) -> Result<Vec<Bytes>, CommandError> {
+    match value.first() {
+        Some(RespFrame::BulkString(command)) if command.eq_ignore_ascii_case(name.as_bytes()) => {}
+        _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+    }
+    if value.len() < 2 && !optional {
+        return Err(CommandError::InvalidArgument(format!(
+            "{} command must have at least 1 argument",
+            name
+        )));
+    }
+    extract_args(value, 1)?
+        .into_iter()
+        .map(extract_bytes)
+        .collect::<Result<Vec<Bytes>, CommandError>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::Subscriber;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_pubsub_commands() -> Result<()> {
+        let cmd: Subscribe = command(&["subscribe", "a", "b"]).try_into()?;
+        assert_eq!(cmd.channels(), [Bytes::from("a"), Bytes::from("b")]);
+        assert!(Subscribe::try_from(command(&["subscribe"])).is_err());
+        let cmd: Unsubscribe = command(&["unsubscribe"]).try_into()?;
+        assert!(cmd.channels().is_empty());
+        let cmd: PSubscribe = command(&["psubscribe", "a*"]).try_into()?;
+        assert_eq!(cmd.patterns(), [Bytes::from("a*")]);
+        assert!(Publish::try_from(command(&["publish", "a"])).is_err());
+        assert!(PubSub::try_from(command(&["pubsub", "numpat", "x"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_publish_and_introspection() -> Result<()> {
+        let backend = Backend::new();
+        let (tx, _rx) = Subscriber::channel();
+        backend.pubsub().subscribe(b"news", 1, &tx);
+        backend.pubsub().psubscribe(b"n*", 1, &tx);
+
+        let publish: Publish = command(&["publish", "news", "hello"]).try_into()?;
+        assert_eq!(publish.execute(&backend), RespFrame::Integer(2));
+
+        let pubsub: PubSub = command(&["pubsub", "channels", "n*"]).try_into()?;
+        assert_eq!(
+            pubsub.execute(&backend),
+            RespArray::new([BulkString::new("news").into()]).into()
+        );
+        let pubsub: PubSub = command(&["pubsub", "numsub", "news", "other"]).try_into()?;
+        assert_eq!(
+            pubsub.execute(&backend),
+            RespArray::new([
+                BulkString::new("news").into(),
+                RespFrame::Integer(1),
+                BulkString::new("other").into(),
+                RespFrame::Integer(0),
+            ])
+            .into()
+        );
+        let pubsub: PubSub = command(&["pubsub", "numpat"]).try_into()?;
+        assert_eq!(pubsub.execute(&backend), RespFrame::Integer(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_spublish_and_introspection() -> Result<()> {
+        let backend = Backend::new();
+        let (tx, _rx) = Subscriber::channel();
+        backend.shard_pubsub().ssubscribe(b"orders", 1, &tx);
+        backend.pubsub().subscribe(b"orders", 1, &tx);
+
+        let spublish: SPublish = command(&["spublish", "orders", "hello"]).try_into()?;
+        assert_eq!(spublish.execute(&backend), RespFrame::Integer(1));
+        let spublish: SPublish = command(&["spublish", "payments", "hello"]).try_into()?;
+        assert_eq!(spublish.execute(&backend), RespFrame::Integer(0));
+
+        let pubsub: PubSub = command(&["pubsub", "shardchannels"]).try_into()?;
+        assert_eq!(
+            pubsub.execute(&backend),
+            RespArray::new([BulkString::new("orders").into()]).into()
+        );
+        let pubsub: PubSub = command(&["pubsub", "shardnumsub", "orders"]).try_into()?;
+        assert_eq!(
+            pubsub.execute(&backend),
+            RespArray::new([BulkString::new("orders").into(), RespFrame::Integer(1)]).into()
+        );
+        Ok(())
+    }
+}
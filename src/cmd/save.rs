@@ -0,0 +1,108 @@
+use crate::{rdb, Backend, RespArray, RespFrame, SimpleError, SimpleString};
+
+use super::{validate_command, BgSave, CommandError, CommandExcetor, LastSave, Save, RESP_OK};
+
+impl CommandExcetor for Save {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match rdb::save(backend) {
+            Ok(_) => RESP_OK.clone(),
+            Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+        }
+    }
+}
+
+impl CommandExcetor for BgSave {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match rdb::bgsave(backend) {
+            Ok(_) => SimpleString::new("Background saving started").into(),
+            Err(e) => SimpleError::new(format!("ERR {}", e)).into(),
+        }
+    }
+}
+
+impl CommandExcetor for LastSave {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        RespFrame::Integer(backend.lastsave() as i64)
+    }
+}
+
+//SAVE
+impl TryFrom<RespArray> for Save {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["save"], 0)?;
+        Ok(Save)
+    }
+}
+
+//BGSAVE
+impl TryFrom<RespArray> for BgSave {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["bgsave"], 0)?;
+        Ok(BgSave)
+    }
+}
+
+//LASTSAVE
+impl TryFrom<RespArray> for LastSave {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["lastsave"], 0)?;
+        Ok(LastSave)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use anyhow::Result;
+
+    use crate::BulkString;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_save_commands() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-{}-cmd-save", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.set("key".into(), "value".into());
+
+        let save: Save = command(&["save"]).try_into()?;
+        assert_eq!(save.execute(&backend), RESP_OK.clone());
+        assert!(dir.join("dump.rdb").exists());
+        let lastsave: LastSave = command(&["LASTSAVE"]).try_into()?;
+        assert_eq!(
+            lastsave.execute(&backend),
+            RespFrame::Integer(backend.lastsave() as i64)
+        );
+
+        let bgsave: BgSave = command(&["bgsave"]).try_into()?;
+        assert_eq!(
+            bgsave.execute(&backend),
+            SimpleString::new("Background saving started").into()
+        );
+        while backend.save_in_progress() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(backend.last_bgsave_ok());
+
+        backend.save_config().set_dir(dir.join("missing"));
+        assert!(matches!(save.execute(&backend), RespFrame::Error(_)));
+        assert!(Save::try_from(command(&["save", "now"])).is_err());
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use bytes::Bytes;
+
+use crate::{Backend, BulkString, RespArray, RespFrame};
+
+use super::{
+    extract_args, extract_bytes, extract_integer, extract_string, CommandError, CommandExcetor,
+    Eval, EvalSha, Script, ScriptSubcommand, RESP_OK,
+};
+
+impl CommandExcetor for Eval {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match backend.scripts().load(&self.script) {
+            Ok(sha) => backend
+                .scripts()
+                .eval(backend, &sha, &self.keys, &self.args),
+            Err(e) => e.into(),
+        }
+    }
+}
+impl CommandExcetor for EvalSha {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        backend
+            .scripts()
+            .eval(backend, &self.sha, &self.keys, &self.args)
+    }
+}
+impl CommandExcetor for Script {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let scripts = backend.scripts();
+        match &self.subcommand {
+            ScriptSubcommand::Load(script) => match scripts.load(script) {
+                Ok(sha) => BulkString::new(sha).into(),
+                Err(e) => e.into(),
+            },
+            ScriptSubcommand::Exists(shas) => {
+                let exists = shas
+                    .iter()
+                    .map(|sha| RespFrame::Integer(scripts.exists(sha) as i64))
+                    .collect::<Vec<RespFrame>>();
+                RespArray::new(exists).into()
+            }
+            ScriptSubcommand::Flush => {
+                scripts.flush();
+                RESP_OK.clone()
+            }
+            ScriptSubcommand::Kill => scripts.kill(),
+        }
+    }
+}
+
+//EVAL script numkeys [key [key ...]] [arg [arg ...]]
+impl TryFrom<RespArray> for Eval {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let (script, keys, args) = extract_script_args(&value, "eval")?;
+        Ok(Eval { script, keys, args })
+    }
+}
+//EVALSHA sha1 numkeys [key [key ...]] [arg [arg ...]]
+impl TryFrom<RespArray> for EvalSha {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let (sha, keys, args) = extract_script_args(&value, "evalsha")?;
+        Ok(EvalSha { sha, keys, args })
+    }
+}
+
+//SCRIPT LOAD script | EXISTS sha1 [sha1 ...] | FLUSH [ASYNC | SYNC] | KILL
+impl TryFrom<RespArray> for Script {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(RespFrame::BulkString(name)) if name.eq_ignore_ascii_case(b"script") => {}
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        }
+        let args = extract_args(&value, 1)?;
+        let subcommand = match args.first() {
+            Some(frame) => extract_string(frame)?.to_ascii_lowercase(),
+            None => {
+                return Err(CommandError::InvalidArgument(
+                    "script command must have a subcommand".to_string(),
+                ))
+            }
+        };
+        let syntax_err = || CommandError::InvalidArgument("syntax error".to_string());
+        let subcommand = match (subcommand.as_str(), &args[1..]) {
+            ("load", [script]) => ScriptSubcommand::Load(extract_string(script)?),
+            ("exists", shas) if !shas.is_empty() => ScriptSubcommand::Exists(
+                shas.iter()
+                    .map(|sha| extract_string(sha))
+                    .collect::<Result<Vec<String>, CommandError>>()?,
+            ),
+            ("flush", []) => ScriptSubcommand::Flush,
+            ("flush", [mode]) => match extract_string(mode)?.to_ascii_lowercase().as_str() {
+                "async" | "sync" => ScriptSubcommand::Flush,
+                _ => return Err(syntax_err()),
+            },
+            ("kill", []) => ScriptSubcommand::Kill,
+            _ => return Err(syntax_err()),
+        };
+        Ok(Script { subcommand })
+    }
+}
+
+// the script or its sha1, the keys and the remaining arguments
+pub(super) type ScriptArgs = (String, Vec<Bytes>, Vec<Vec<u8>>);
+
+pub(super) fn extract_script_args(
+    value: &RespArray,
+    name: &str,
+) -> Result<ScriptArgs, CommandError> {
+    match value.first() {
+        Some(RespFrame::BulkString(command)) if command.eq_ignore_ascii_case(name.as_bytes()) => {}
+        _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+    }
+    if value.len() < 3 {
+        return Err(CommandError::InvalidArgument(format!(
+            "{} command must have at least 2 argument",
+            name
+        )));
+    }
+    let script = extract_string(&value[1])?;
+    let numkeys: i64 = extract_integer(&value[2])?;
+    if numkeys < 0 {
+        return Err(CommandError::InvalidArgument(
+            "Number of keys can't be negative".to_string(),
+        ));
+    }
+    let rest = extract_args(value, 3)?;
+    if numkeys as usize > rest.len() {
+        return Err(CommandError::InvalidArgument(
+            "Number of keys can't be greater than number of args".to_string(),
+        ));
+    }
+    let (keys, args) = rest.split_at(numkeys as usize);
+    let keys = keys
+        .iter()
+        .map(|key| extract_bytes(key))
+        .collect::<Result<Vec<Bytes>, CommandError>>()?;
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            RespFrame::BulkString(arg) => Ok(arg.to_vec()),
+            _ => Err(CommandError::InvalidArgument(
+                "Invalid argument".to_string(),
+            )),
+        })
+        .collect::<Result<Vec<Vec<u8>>, CommandError>>()?;
+    Ok((script, keys, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::SimpleError;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    #[test]
+    fn test_eval_command() -> Result<()> {
+        let eval: Eval = command(&["eval", "return 1", "1", "key", "arg"]).try_into()?;
+        assert_eq!(eval.keys, vec!["key"]);
+        assert_eq!(eval.args, vec![b"arg".to_vec()]);
+        assert!(Eval::try_from(command(&["eval", "return 1", "2", "key"])).is_err());
+        assert!(Eval::try_from(command(&["eval", "return 1", "-1"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_and_evalsha() -> Result<()> {
+        let backend = Backend::new();
+        let script = "return redis.call('incr_missing') or ARGV[1]";
+        let eval: Eval = command(&["eval", script, "0", "hello"]).try_into()?;
+        assert_eq!(
+            eval.execute(&backend),
+            SimpleError::new("ERR Unknown Redis command called from script").into()
+        );
+
+        let script = "redis.call('set', KEYS[1], ARGV[1]) return redis.call('get', KEYS[1])";
+        let eval: Eval = command(&["eval", script, "1", "hello", "world"]).try_into()?;
+        assert_eq!(eval.execute(&backend), b"world".into());
+
+        let sha = crate::sha1_hex(script.as_bytes());
+        let evalsha: EvalSha =
+            command(&["evalsha", &sha.to_uppercase(), "1", "other", "value"]).try_into()?;
+        assert_eq!(evalsha.execute(&backend), b"value".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_command() -> Result<()> {
+        let backend = Backend::new();
+        let load: Script = command(&["script", "load", "return 'ok'"]).try_into()?;
+        let sha = match load.execute(&backend) {
+            RespFrame::BulkString(sha) => String::from_utf8(sha.to_vec())?,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        let exists: Script = command(&["script", "exists", &sha, "missing"]).try_into()?;
+        assert_eq!(
+            exists.execute(&backend),
+            RespArray::new([RespFrame::Integer(1), RespFrame::Integer(0)]).into()
+        );
+
+        let flush: Script = command(&["script", "flush"]).try_into()?;
+        assert_eq!(flush.execute(&backend), RESP_OK.clone());
+        assert_eq!(
+            exists.execute(&backend),
+            RespArray::new([RespFrame::Integer(0), RespFrame::Integer(0)]).into()
+        );
+
+        let kill: Script = command(&["script", "kill"]).try_into()?;
+        assert!(matches!(kill.subcommand, ScriptSubcommand::Kill));
+        assert!(matches!(kill.execute(&backend), RespFrame::Error(_)));
+        assert!(Script::try_from(command(&["script", "load"])).is_err());
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::{Backend, RespArray, RespFrame};
 
 use super::{
@@ -16,17 +18,15 @@ impl TryFrom<RespArray> for Sadd {
                 let members = members
                     .iter()
                     .map(|m| match m {
-                        RespFrame::BulkString(member) => {
-                            Ok(String::from_utf8_lossy(member).to_string())
-                        }
+                        RespFrame::BulkString(member) => Ok(member.0.clone()),
 
                         _ => Err(CommandError::InvalidArgument(
                             "Invalid argument".to_string(),
                         )),
                     })
-                    .collect::<Result<Vec<String>, CommandError>>()?;
+                    .collect::<Result<Vec<Bytes>, CommandError>>()?;
                 Ok(Sadd {
-                    key: String::from_utf8_lossy(key).to_string(),
+                    key: key.0.clone(),
                     members,
                 })
             }
@@ -43,8 +43,8 @@ impl TryFrom<RespArray> for Sismember {
         let args = extract_args(&value, 1)?;
         match (args[0], args[1]) {
             (RespFrame::BulkString(key), RespFrame::BulkString(member)) => Ok(Sismember {
-                key: String::from_utf8_lossy(key).to_string(),
-                member: String::from_utf8_lossy(member).to_string(),
+                key: key.0.clone(),
+                member: member.0.clone(),
             }),
             _ => Err(CommandError::InvalidArgument(
                 "Invalid argument".to_string(),
@@ -54,15 +54,19 @@ impl TryFrom<RespArray> for Sismember {
 }
 impl CommandExcetor for Sismember {
     fn execute(&self, backend: &Backend) -> RespFrame {
-        let is_member = backend.sismember(self.key.clone(), self.member.clone());
-        RespFrame::Integer(is_member)
+        match backend.sismember(&self.key, &self.member) {
+            Ok(is_member) => RespFrame::Integer(is_member),
+            Err(e) => e.into(),
+        }
     }
 }
 
 impl CommandExcetor for Sadd {
     fn execute(&self, backend: &Backend) -> RespFrame {
-        let added = backend.sadd(self.key.clone(), &self.members);
-        RespFrame::Integer(added)
+        match backend.sadd(self.key.clone(), &self.members) {
+            Ok(added) => RespFrame::Integer(added),
+            Err(e) => e.into(),
+        }
     }
 }
 
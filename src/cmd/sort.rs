@@ -0,0 +1,355 @@
+use bytes::Bytes;
+
+use crate::{
+    keyspace_event, Backend, BulkString, ListValue, RespArray, RespFrame, RespNull, SimpleError,
+    StoredValue, WrongType,
+};
+
+use super::{
+    extract_args, extract_bytes, extract_integer, extract_string, CommandError, CommandExcetor,
+    Sort,
+};
+
+impl CommandExcetor for Sort {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let elements = match backend.get_value(&self.key) {
+            None => vec![],
+            Some(StoredValue::List(values)) => values.iter().cloned().collect(),
+            Some(StoredValue::Set(members)) => members.members(),
+            Some(StoredValue::ZSet(members)) => members.iter().map(|(m, _)| m.clone()).collect(),
+            Some(_) => return WrongType.into(),
+        };
+
+        // a BY pattern without `*` can never match a key, so the input order is kept
+        let dontsort = self.by.as_ref().is_some_and(|by| !by.contains(&b'*'));
+        let mut items = Vec::with_capacity(elements.len());
+        for element in elements {
+            let weight = match &self.by {
+                Some(by) if !dontsort => lookup_by_pattern(backend, by, &element),
+                _ => Some(element.to_vec()),
+            };
+            let score = match &weight {
+                Some(w) if !dontsort && !self.alpha => {
+                    match std::str::from_utf8(w).ok().and_then(|w| w.parse().ok()) {
+                        Some(score) => score,
+                        None => {
+                            return SimpleError::new(
+                                "ERR One or more scores can't be converted into double",
+                            )
+                            .into()
+                        }
+                    }
+                }
+                _ => 0f64,
+            };
+            items.push((element, weight, score));
+        }
+
+        if !dontsort {
+            items.sort_by(|a, b| {
+                let ordering = if self.alpha {
+                    a.1.cmp(&b.1)
+                } else {
+                    a.2.total_cmp(&b.2)
+                };
+                let ordering = ordering.then_with(|| a.0.cmp(&b.0));
+                if self.desc {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+
+        let (offset, count) = self.limit.unwrap_or((0, -1));
+        let offset = offset.max(0) as usize;
+        let count = if count < 0 {
+            usize::MAX
+        } else {
+            count as usize
+        };
+        let elements = items
+            .into_iter()
+            .skip(offset)
+            .take(count)
+            .map(|(element, _, _)| element);
+
+        let mut result: Vec<Option<Vec<u8>>> = Vec::new();
+        for element in elements {
+            if self.get.is_empty() {
+                result.push(Some(element.to_vec()));
+                continue;
+            }
+            for pattern in &self.get {
+                result.push(lookup_by_pattern(backend, pattern, &element));
+            }
+        }
+
+        match &self.store {
+            Some(dest) => {
+                let values = result
+                    .into_iter()
+                    .map(|v| Bytes::from(v.unwrap_or_default()))
+                    .collect::<Vec<Bytes>>();
+                let len = values.len() as i64;
+                if values.is_empty() {
+                    backend.del(dest);
+                } else {
+                    let list = ListValue::from_values(values, &backend.encoding_limits());
+                    backend.set_value(dest.clone(), StoredValue::List(list), None);
+                    backend.notify(keyspace_event::LIST, "sortstore", dest);
+                }
+                RespFrame::Integer(len)
+            }
+            None => {
+                let frames = result
+                    .into_iter()
+                    .map(|v| match v {
+                        Some(v) => BulkString::new(v).into(),
+                        None => RespFrame::Null(RespNull),
+                    })
+                    .collect::<Vec<RespFrame>>();
+                RespArray::new(frames).into()
+            }
+        }
+    }
+}
+
+// `#` is the element itself, otherwise the first `*` is replaced by the element and a
+// `->field` suffix looks the field up in the resulting hash instead of a string key
+fn lookup_by_pattern(backend: &Backend, pattern: &[u8], element: &[u8]) -> Option<Vec<u8>> {
+    if pattern == b"#" {
+        return Some(element.to_vec());
+    }
+    let star = pattern.iter().position(|&c| c == b'*')?;
+    // like lookupKeyByPattern, only an arrow after the star names a hash field
+    let arrow = pattern[star + 1..]
+        .windows(2)
+        .position(|w| w == b"->")
+        .map(|pos| star + 1 + pos);
+    let (key_pattern, field) = match arrow {
+        Some(pos) if pos + 2 < pattern.len() => (&pattern[..pos], Some(&pattern[pos + 2..])),
+        _ => (pattern, None),
+    };
+    let key = [&key_pattern[..star], element, &key_pattern[star + 1..]].concat();
+    // a key of the wrong type reads as missing
+    let value = match field {
+        Some(field) => backend.hget(&key, field),
+        None => backend.get(&key),
+    };
+    value.ok().flatten().map(|v| v.to_vec())
+}
+
+//SORT key [BY pattern] [LIMIT offset count] [GET pattern [GET pattern ...]]
+//    [ASC|DESC] [ALPHA] [STORE destination]
+impl TryFrom<RespArray> for Sort {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let name = match value.first() {
+            Some(RespFrame::BulkString(name)) => name.to_ascii_lowercase(),
+            _ => return Err(CommandError::InvalidCommand("Invalid command".to_string())),
+        };
+        let read_only = match name.as_slice() {
+            b"sort" => false,
+            b"sort_ro" => true,
+            _ => {
+                return Err(CommandError::InvalidCommand(format!(
+                    "{} is not a valid command,expected sort",
+                    String::from_utf8_lossy(&name)
+                )))
+            }
+        };
+        if value.len() < 2 {
+            return Err(CommandError::InvalidArgument(
+                "sort command must have at least 1 argument".to_string(),
+            ));
+        }
+        let args = extract_args(&value, 1)?;
+        let mut sort = Sort {
+            key: extract_bytes(args[0])?,
+            by: None,
+            limit: None,
+            get: vec![],
+            desc: false,
+            alpha: false,
+            store: None,
+        };
+        let syntax_err = || CommandError::InvalidArgument("syntax error".to_string());
+        let mut options = args[1..].iter();
+        while let Some(option) = options.next() {
+            match extract_string(option)?.to_ascii_lowercase().as_str() {
+                "asc" => sort.desc = false,
+                "desc" => sort.desc = true,
+                "alpha" => sort.alpha = true,
+                "by" => sort.by = Some(extract_bytes(options.next().ok_or_else(syntax_err)?)?),
+                "get" => sort
+                    .get
+                    .push(extract_bytes(options.next().ok_or_else(syntax_err)?)?),
+                "limit" => {
+                    let offset = extract_integer(options.next().ok_or_else(syntax_err)?)?;
+                    let count = extract_integer(options.next().ok_or_else(syntax_err)?)?;
+                    sort.limit = Some((offset, count));
+                }
+                "store" if !read_only => {
+                    sort.store = Some(extract_bytes(options.next().ok_or_else(syntax_err)?)?)
+                }
+                _ => return Err(syntax_err()),
+            }
+        }
+        Ok(sort)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+    }
+
+    fn bulk_array(values: &[&str]) -> RespFrame {
+        RespArray::new(
+            values
+                .iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+        .into()
+    }
+
+    fn setup() -> Backend {
+        let backend = Backend::new();
+        let ids = ["3", "1", "2"].map(Bytes::from);
+        backend.rpush("ids".into(), &ids).unwrap();
+        backend.set("weight_1".into(), "30".into());
+        backend.set("weight_2".into(), "10".into());
+        backend.set("weight_3".into(), "20".into());
+        for (id, name) in [("1", "one"), ("2", "two"), ("3", "three")] {
+            backend
+                .hset(format!("object_{}", id).into(), "name".into(), name.into())
+                .unwrap();
+        }
+        backend
+    }
+
+    #[test]
+    fn test_sort_command() -> Result<()> {
+        let result: Sort = command(&[
+            "sort",
+            "ids",
+            "BY",
+            "weight_*",
+            "LIMIT",
+            "0",
+            "2",
+            "GET",
+            "#",
+            "GET",
+            "object_*->name",
+            "DESC",
+            "ALPHA",
+            "STORE",
+            "dest",
+        ])
+        .try_into()?;
+        assert_eq!(result.key, "ids");
+        assert_eq!(result.by.as_deref(), Some(&b"weight_*"[..]));
+        assert_eq!(result.limit, Some((0, 2)));
+        assert_eq!(result.get, vec!["#", "object_*->name"]);
+        assert!(result.desc && result.alpha);
+        assert_eq!(result.store.as_deref(), Some(&b"dest"[..]));
+
+        assert!(Sort::try_from(command(&["sort_ro", "ids", "STORE", "dest"])).is_err());
+        assert!(Sort::try_from(command(&["sort", "ids", "LIMIT", "0"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_numeric_and_alpha() -> Result<()> {
+        let backend = setup();
+        let sort: Sort = command(&["sort", "ids"]).try_into()?;
+        assert_eq!(sort.execute(&backend), bulk_array(&["1", "2", "3"]));
+
+        let sort: Sort = command(&["sort", "ids", "DESC", "LIMIT", "1", "5"]).try_into()?;
+        assert_eq!(sort.execute(&backend), bulk_array(&["2", "1"]));
+
+        backend
+            .rpush("names".into(), &["b".into(), "a".into()])
+            .unwrap();
+        let sort: Sort = command(&["sort_ro", "names", "ALPHA"]).try_into()?;
+        assert_eq!(sort.execute(&backend), bulk_array(&["a", "b"]));
+
+        let sort: Sort = command(&["sort", "names"]).try_into()?;
+        assert!(matches!(sort.execute(&backend), RespFrame::Error(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_by_and_get_patterns() -> Result<()> {
+        let backend = setup();
+        let sort: Sort = command(&[
+            "sort",
+            "ids",
+            "BY",
+            "weight_*",
+            "GET",
+            "#",
+            "GET",
+            "object_*->name",
+        ])
+        .try_into()?;
+        assert_eq!(
+            sort.execute(&backend),
+            bulk_array(&["2", "two", "3", "three", "1", "one"])
+        );
+
+        let sort: Sort =
+            command(&["sort", "ids", "BY", "nosort", "GET", "missing_*"]).try_into()?;
+        assert_eq!(
+            sort.execute(&backend),
+            RespArray::new(vec![RespFrame::Null(RespNull); 3]).into()
+        );
+
+        // an arrow before the star is part of the key name
+        for (id, label) in [("1", "a"), ("2", "b"), ("3", "c")] {
+            backend
+                .hset(format!("a->{}", id).into(), "label".into(), label.into())
+                .unwrap();
+            backend.set(format!("b->{}", id).into(), label.into());
+        }
+        let sort: Sort =
+            command(&["sort", "ids", "GET", "a->*->label", "GET", "b->*"]).try_into()?;
+        assert_eq!(
+            sort.execute(&backend),
+            bulk_array(&["a", "a", "b", "b", "c", "c"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_store() -> Result<()> {
+        let backend = setup();
+        backend
+            .sadd("set".into(), &["2".into(), "1".into()])
+            .unwrap();
+        let sort: Sort =
+            command(&["sort", "set", "GET", "object_*->name", "STORE", "dest"]).try_into()?;
+        assert_eq!(sort.execute(&backend), RespFrame::Integer(2));
+        assert_eq!(
+            backend.lrange(b"dest", 0, -1),
+            Ok(vec!["one".into(), "two".into()])
+        );
+
+        let sort: Sort = command(&["sort", "missing", "STORE", "dest"]).try_into()?;
+        assert_eq!(sort.execute(&backend), RespFrame::Integer(0));
+        assert!(!backend.exists(b"dest"));
+        Ok(())
+    }
+}
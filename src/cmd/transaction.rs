@@ -0,0 +1,85 @@
+use bytes::Bytes;
+
+use crate::{Backend, RespArray, RespFrame, SimpleError};
+
+use super::{
+    extract_args, extract_bytes, validate_command, CommandError, CommandExcetor, Discard, Exec,
+    Multi, Unwatch, Watch, RESP_OK,
+};
+
+impl Watch {
+    pub fn keys(&self) -> &[Bytes] {
+        &self.keys
+    }
+}
+
+// MULTI/EXEC/DISCARD/WATCH/UNWATCH change the connection state and are handled by the connection
+// session, executing them directly means there is no transaction in progress.
+impl CommandExcetor for Multi {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        RESP_OK.clone()
+    }
+}
+impl CommandExcetor for Exec {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        SimpleError::new("ERR EXEC without MULTI").into()
+    }
+}
+impl CommandExcetor for Discard {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        SimpleError::new("ERR DISCARD without MULTI").into()
+    }
+}
+
+impl CommandExcetor for Watch {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        RESP_OK.clone()
+    }
+}
+impl CommandExcetor for Unwatch {
+    fn execute(&self, _backend: &Backend) -> RespFrame {
+        RESP_OK.clone()
+    }
+}
+
+impl TryFrom<RespArray> for Multi {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["multi"], 0)?;
+        Ok(Multi)
+    }
+}
+impl TryFrom<RespArray> for Exec {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["exec"], 0)?;
+        Ok(Exec)
+    }
+}
+impl TryFrom<RespArray> for Discard {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["discard"], 0)?;
+        Ok(Discard)
+    }
+}
+//WATCH key [key ...]
+impl TryFrom<RespArray> for Watch {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let n_args = if value.len() > 2 { value.len() - 1 } else { 1 };
+        validate_command(&value, &["watch"], n_args)?;
+        let keys = extract_args(&value, 1)?
+            .into_iter()
+            .map(extract_bytes)
+            .collect::<Result<Vec<Bytes>, CommandError>>()?;
+        Ok(Watch { keys })
+    }
+}
+impl TryFrom<RespArray> for Unwatch {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, &["unwatch"], 0)?;
+        Ok(Unwatch)
+    }
+}
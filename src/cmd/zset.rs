@@ -0,0 +1,115 @@
+use bytes::Bytes;
+
+use crate::{Backend, BulkString, RespArray, RespFrame};
+
+use super::{
+    extract_args, extract_bytes, extract_integer, extract_string, validate_command, CommandError,
+    CommandExcetor, ZAdd, ZRange,
+};
+
+impl CommandExcetor for ZAdd {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        match backend.zadd(self.key.clone(), &self.members) {
+            Ok(added) => RespFrame::Integer(added),
+            Err(e) => e.into(),
+        }
+    }
+}
+impl CommandExcetor for ZRange {
+    fn execute(&self, backend: &Backend) -> RespFrame {
+        let members = match backend.zrange(&self.key, self.start, self.stop) {
+            Ok(members) => members,
+            Err(e) => return e.into(),
+        };
+        let mut result = Vec::new();
+        for (member, score) in members {
+            result.push(BulkString::from(member).into());
+            if self.withscores {
+                result.push(BulkString::new(score.to_string()).into());
+            }
+        }
+        RespArray::new(result).into()
+    }
+}
+
+//ZADD key score member [score member ...]
+impl TryFrom<RespArray> for ZAdd {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let n_args = if value.len() > 4 { value.len() - 1 } else { 3 };
+        validate_command(&value, &["zadd"], n_args)?;
+        let args = extract_args(&value, 1)?;
+        if args.len() % 2 == 0 {
+            return Err(CommandError::InvalidArgument("syntax error".to_string()));
+        }
+        let members = args[1..]
+            .chunks(2)
+            .map(|pair| {
+                let score = extract_string(pair[0])?.parse::<f64>().map_err(|_| {
+                    CommandError::InvalidArgument("value is not a valid float".to_string())
+                })?;
+                Ok((extract_bytes(pair[1])?, score))
+            })
+            .collect::<Result<Vec<(Bytes, f64)>, CommandError>>()?;
+        Ok(ZAdd {
+            key: extract_bytes(args[0])?,
+            members,
+        })
+    }
+}
+
+//ZRANGE key start stop [WITHSCORES]
+impl TryFrom<RespArray> for ZRange {
+    type Error = CommandError;
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let n_args = if value.len() > 4 { 4 } else { 3 };
+        validate_command(&value, &["zrange"], n_args)?;
+        let args = extract_args(&value, 1)?;
+        let withscores = match args.get(3) {
+            Some(v) if extract_string(v)?.eq_ignore_ascii_case("withscores") => true,
+            Some(_) => return Err(CommandError::InvalidArgument("syntax error".to_string())),
+            None => false,
+        };
+        Ok(ZRange {
+            key: extract_bytes(args[0])?,
+            start: extract_integer(args[1])?,
+            stop: extract_integer(args[2])?,
+            withscores,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    use crate::RespDecoder;
+
+    use super::*;
+
+    #[test]
+    fn test_zadd_zrange() -> Result<()> {
+        let backend = Backend::new();
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(
+            b"*6\r\n$4\r\nzadd\r\n$1\r\nz\r\n$1\r\n2\r\n$1\r\nb\r\n$3\r\n1.5\r\n$1\r\na\r\n",
+        );
+        let cmd: ZAdd = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(
+            cmd.members,
+            vec![(Bytes::from("b"), 2.0), (Bytes::from("a"), 1.5)]
+        );
+        assert_eq!(cmd.execute(&backend), RespFrame::Integer(2));
+
+        buf.extend_from_slice(
+            b"*5\r\n$6\r\nzrange\r\n$1\r\nz\r\n$1\r\n0\r\n$2\r\n-1\r\n$10\r\nwithscores\r\n",
+        );
+        let cmd: ZRange = RespArray::decode(&mut buf)?.try_into()?;
+        assert_eq!(
+            cmd.execute(&backend),
+            RespArray::new([b"a".into(), b"1.5".into(), b"b".into(), b"2".into()]).into()
+        );
+        Ok(())
+    }
+}
@@ -5,4 +5,8 @@ pub use cmd::*;
 
 mod backend;
 pub use backend::*;
+pub mod aof;
 pub mod network;
+pub mod rdb;
+mod script;
+pub use script::*;
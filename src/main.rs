@@ -1,11 +1,43 @@
-use simple_redis::{network::stream_handler, Backend};
+use std::time::Duration;
+
+use simple_redis::{aof, configure, network::stream_handler, rdb, Backend};
 use tracing::{info, warn};
+
+// how often the save rules and the append only file fsync are checked, and expired keys
+// removed
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
     let addr = "0.0.0.0:6379";
     info!("Simple Redis Server started at {}", addr);
     let backend = Backend::new();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    configure(&backend, &args).map_err(|e| anyhow::anyhow!("Bad command line: {}", e))?;
+    // the append only file is more recent than the snapshot when there is one
+    if let Some(stats) = aof::load(&backend)? {
+        info!(
+            "DB loaded from append only file: {} keys loaded, {} commands replayed",
+            stats.keys, stats.commands
+        );
+    } else {
+        load_snapshot(&backend)?;
+    }
+    let cron_backend = backend.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CRON_INTERVAL);
+        loop {
+            interval.tick().await;
+            rdb::save_if_needed(&cron_backend);
+            // keys are left alone while EXEC or a script runs, as it must see them unchanged
+            if let Some(_guard) = cron_backend.try_lock(false) {
+                cron_backend.active_expire_cycle();
+            }
+            // fsync may take a while, keep it off the other connections
+            tokio::task::block_in_place(|| cron_backend.aof().fsync_if_needed());
+        }
+    });
     let listener = tokio::net::TcpListener::bind(addr).await?;
     loop {
         let (stream, raddr) = listener.accept().await?;
@@ -22,3 +54,18 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 }
+
+fn load_snapshot(backend: &Backend) -> anyhow::Result<()> {
+    match std::fs::read(backend.save_config().path()) {
+        Ok(data) => {
+            let stats = rdb::load(&data, backend)?;
+            info!(
+                "DB loaded from disk: {} keys loaded, {} expired keys skipped",
+                stats.keys, stats.expired
+            );
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
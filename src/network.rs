@@ -1,30 +1,106 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
 use anyhow::Result;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use futures::SinkExt;
-use tokio::net::TcpStream;
+use lazy_static::lazy_static;
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc::Receiver, RwLockReadGuard},
+};
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Decoder, Encoder, Framed};
 use tracing::info;
 
 use crate::{
-    cmd::Command, Backend, CommandExcetor, RespDecoder, RespEncoder, RespError, RespFrame,
+    cmd::{Command, Hello, Migrate, RESP_OK},
+    key_slot, Backend, BulkString, CommandExcetor, RespArray, RespEncoder, RespError, RespFrame,
+    RespNullArray, RespNullBulkString, RespParser, RespPush, SimpleError, SimpleString, Subscriber,
 };
 
-struct RespFrameCodec;
+lazy_static! {
+    static ref RESP_QUEUED: RespFrame = SimpleString::new("QUEUED").into();
+    static ref RESP_BUSY: RespFrame = SimpleError::new(
+        "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE."
+    )
+    .into();
+    static ref RESP_OOM: RespFrame =
+        SimpleError::new("OOM command not allowed when used memory > 'maxmemory'.").into();
+}
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+// how often a command waiting for the backend lock checks whether a script keeps it too long
+const BUSY_CHECK_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug)]
+struct RespFrameCodec {
+    parser: RespParser,
+    backend: Backend,
+}
+
+impl RespFrameCodec {
+    fn new(backend: Backend) -> Self {
+        Self {
+            parser: RespParser::with_limits(backend.proto_limits().parser_limits()),
+            backend,
+        }
+    }
+}
 
 struct RedisRequest {
     frame: RespFrame,
     backend: Backend,
 }
 struct RedisResponse {
-    frame: RespFrame,
+    frames: Vec<RespFrame>,
+    // hang up once the frames are sent, after QUIT
+    close: bool,
+}
+
+/// Per connection state that outlives a single request.
+#[derive(Debug)]
+struct Session {
+    id: u64,
+    // commands queued since MULTI, `None` outside of a transaction
+    queued: Option<Vec<(Command, RespFrame)>>,
+    // a command failed to queue, so EXEC has to abort the transaction
+    dirty: bool,
+    // keys watched since WATCH, and the flag the backend raises when one is modified
+    watched_keys: Vec<Bytes>,
+    watch_dirty: Arc<AtomicBool>,
+    // channels, patterns and shard channels subscribed to, and where the messages published to
+    // them arrive
+    channels: Vec<Bytes>,
+    patterns: Vec<Bytes>,
+    shard_channels: Vec<Bytes>,
+    subscriber: Subscriber,
+    messages: Receiver<RespFrame>,
+    // RESP version spoken by the client, 2 until it says otherwise with HELLO
+    protocol: i64,
+    name: Option<String>,
+}
+
+/// What a connection subscribes to, either a channel, every channel matching a pattern or a
+/// shard channel.
+#[derive(Debug, Clone, Copy)]
+enum Subscription {
+    Channel,
+    Pattern,
+    Shard,
 }
 
 impl Encoder<RespFrame> for RespFrameCodec {
     type Error = anyhow::Error;
     fn encode(&mut self, item: RespFrame, dst: &mut BytesMut) -> Result<()> {
-        let encoded = item.encode();
-        dst.extend_from_slice(&encoded);
+        item.encode_to(dst);
         Ok(())
     }
 }
@@ -32,30 +108,66 @@ impl Decoder for RespFrameCodec {
     type Item = RespFrame;
     type Error = anyhow::Error;
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RespFrame>> {
-        match RespFrame::decode(src) {
-            Ok(frame) => Ok(Some(frame)),
-            Err(RespError::NotComplete) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+        // CONFIG SET may have changed the limits since the last read
+        self.parser
+            .set_limits(self.backend.proto_limits().parser_limits());
+        Ok(self.parser.parse(src)?)
     }
 }
 pub async fn stream_handler(_stream: TcpStream, backend: Backend) -> Result<()> {
-    let mut framed = Framed::new(_stream, RespFrameCodec);
+    let mut framed = Framed::new(_stream, RespFrameCodec::new(backend.clone()));
+    let mut session = Session::default();
+    let ret = serve(&mut framed, &backend, &mut session).await;
+    session.close(&backend);
+    ret
+}
+
+async fn serve(
+    framed: &mut Framed<TcpStream, RespFrameCodec>,
+    backend: &Backend,
+    session: &mut Session,
+) -> Result<()> {
     loop {
-        match framed.next().await {
+        let frame = tokio::select! {
+            frame = framed.next() => frame,
+            Some(message) = session.messages.recv() => {
+                // like redis past its pubsub output buffer limit, drop a client too slow to
+                // keep up with its messages rather than queueing them without bound
+                if session.subscriber.overflowed() {
+                    info!("Closing subscriber {} over the message limit", session.id);
+                    return Ok(());
+                }
+                framed.send(session.encode_for_client(message)).await?;
+                continue;
+            }
+        };
+        match frame {
             Some(Ok(frame)) => {
                 info!("Received frame: {:?}", frame);
                 let request = RedisRequest {
                     frame,
                     backend: backend.clone(),
                 };
-                let response = request_handler(request).await?;
-                info!("Sending response: {:?}", response.frame);
-                framed.send(response.frame).await?;
+                let response = request_handler(request, session).await?;
+                for frame in response.frames {
+                    info!("Sending response: {:?}", frame);
+                    framed.send(session.encode_for_client(frame)).await?;
+                }
+                if response.close {
+                    return Ok(());
+                }
             }
             Some(Err(e)) => {
                 info!("Error decoding frame: {:?}", e);
-                return Err(e);
+                // like redis, tell the client what was wrong with its request before hanging up,
+                // the rest of the stream can't be trusted to be framed correctly
+                let reply = match e.downcast_ref::<RespError>() {
+                    Some(RespError::Protocol(_)) => format!("ERR {}", e),
+                    Some(_) => format!("ERR Protocol error: {}", e),
+                    None => return Err(e),
+                };
+                framed.send(SimpleError::new(reply).into()).await?;
+                return Ok(());
             }
             None => {
                 return Ok(());
@@ -64,10 +176,1120 @@ pub async fn stream_handler(_stream: TcpStream, backend: Backend) -> Result<()>
     }
 }
 
-async fn request_handler(_request: RedisRequest) -> Result<RedisResponse> {
+async fn request_handler(_request: RedisRequest, session: &mut Session) -> Result<RedisResponse> {
     let (frame, backend) = (_request.frame, _request.backend);
-    let cmd = Command::try_from(frame)?;
+    let name = match &frame {
+        RespFrame::Array(args) => match args.first() {
+            Some(RespFrame::BulkString(name)) => String::from_utf8_lossy(name).to_lowercase(),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    };
+    // kept to be written to the append only file as it came
+    let request = frame.clone();
+    let cmd = match Command::try_from(frame) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            info!("Invalid command: {:?}", e);
+            if session.queued.is_some() {
+                session.dirty = true;
+            }
+            let frame = SimpleError::new(format!("ERR {}", e)).into();
+            return Ok(RedisResponse {
+                frames: vec![frame],
+                close: false,
+            });
+        }
+    };
     info!("Executing command: {:?}", cmd);
-    let ret = cmd.execute(&backend);
-    Ok(RedisResponse { frame: ret })
+    let in_multi = session.queued.is_some();
+    let frames = match cmd {
+        Command::Subscribe(cmd) if !in_multi => {
+            session.subscribe(Subscription::Channel, cmd.channels(), &backend)
+        }
+        Command::Unsubscribe(cmd) if !in_multi => {
+            session.unsubscribe(Subscription::Channel, cmd.channels(), &backend)
+        }
+        Command::PSubscribe(cmd) if !in_multi => {
+            session.subscribe(Subscription::Pattern, cmd.patterns(), &backend)
+        }
+        Command::PUnsubscribe(cmd) if !in_multi => {
+            session.unsubscribe(Subscription::Pattern, cmd.patterns(), &backend)
+        }
+        Command::SSubscribe(cmd) if !in_multi => match same_slot(cmd.channels()) {
+            Ok(()) => session.subscribe(Subscription::Shard, cmd.channels(), &backend),
+            Err(e) => vec![e],
+        },
+        Command::SUnsubscribe(cmd) if !in_multi => match same_slot(cmd.channels()) {
+            Ok(()) => session.unsubscribe(Subscription::Shard, cmd.channels(), &backend),
+            Err(e) => vec![e],
+        },
+        Command::Quit(_) => {
+            return Ok(RedisResponse {
+                frames: vec![RESP_OK.clone()],
+                close: true,
+            })
+        }
+        Command::Reset(_) => vec![session.reset_connection(&backend)],
+        // a RESP2 client in subscribed mode only expects arrays
+        Command::Ping(cmd) if session.subscription_count() > 0 && session.protocol < 3 => {
+            vec![cmd.subscribed_reply()]
+        }
+        // RESP3 clients get their messages as out of band data and may keep issuing commands
+        _ if session.subscription_count() > 0 && session.protocol < 3 => {
+            let frame = SimpleError::new(format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                name
+            ));
+            vec![frame.into()]
+        }
+        Command::Hello(cmd) if !in_multi => vec![session.hello(&cmd)],
+        cmd => vec![session.execute(cmd, request, &backend).await],
+    };
+    Ok(RedisResponse {
+        frames,
+        close: false,
+    })
+}
+
+// Shard channels of a single command must all belong to the same slot, as a cluster node would
+// only own some of them.
+fn same_slot(channels: &[Bytes]) -> Result<(), RespFrame> {
+    let mut slots = channels.iter().map(|channel| key_slot(channel));
+    match slots.next() {
+        Some(first) if slots.any(|slot| slot != first) => {
+            Err(SimpleError::new("CROSSSLOT Keys in request don't hash to the same slot").into())
+        }
+        _ => Ok(()),
+    }
+}
+
+// Wait in line for a side of the backend lock, replying BUSY once a script has been running
+// for too long. The place in the queue is kept while checking, so that a transaction waiting
+// for the exclusive side holds back the commands which come after it.
+async fn wait_for_lock<T>(
+    backend: &Backend,
+    lock: impl Future<Output = T>,
+) -> Result<T, RespFrame> {
+    tokio::pin!(lock);
+    loop {
+        tokio::select! {
+            guard = &mut lock => return Ok(guard),
+            _ = tokio::time::sleep(BUSY_CHECK_INTERVAL) => {
+                if backend.scripts().is_busy() {
+                    return Err(RESP_BUSY.clone());
+                }
+            }
+        }
+    }
+}
+
+async fn lock_shared(backend: &Backend) -> Result<RwLockReadGuard<'_, ()>, RespFrame> {
+    wait_for_lock(backend, backend.lock_shared_async()).await
+}
+
+// Evict what `maxmemory-policy` allows before running a command, refusing the ones that may
+// grow the dataset while it is still over `maxmemory`.
+fn reserve_memory(backend: &Backend, denyoom: bool) -> Result<(), RespFrame> {
+    if !backend.perform_evictions() && denyoom {
+        return Err(RESP_OOM.clone());
+    }
+    Ok(())
+}
+
+// Scripts and transactions hold the exclusive side for as long as they run, which may be a
+// while, so they run off the runtime threads to keep serving the other connections meanwhile.
+async fn execute_exclusive<F>(backend: &Backend, f: F) -> RespFrame
+where
+    F: FnOnce(&Backend) -> RespFrame + Send + 'static,
+{
+    let guard = match wait_for_lock(backend, backend.lock_exclusive_owned()).await {
+        Ok(guard) => guard,
+        Err(busy) => return busy,
+    };
+    let backend = backend.clone();
+    let ret = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        f(&backend)
+    })
+    .await;
+    ret.unwrap_or_else(|e| SimpleError::new(format!("ERR {}", e)).into())
+}
+
+// MIGRATE waits on the target instance for up to its timeout on every key, so the transfer
+// runs off the runtime threads and without holding the backend lock or the append only file,
+// only reading the keys and removing the migrated ones do.
+async fn execute_migrate(backend: &Backend, migrate: Migrate, request: RespFrame) -> RespFrame {
+    let values = match lock_shared(backend).await {
+        Ok(_guard) => migrate.values(backend),
+        Err(busy) => return busy,
+    };
+    if values.is_empty() {
+        return SimpleString::new("NOKEY").into();
+    }
+    let ret = tokio::task::spawn_blocking(move || {
+        let ret = migrate.transfer(&values);
+        (migrate, values, ret)
+    })
+    .await;
+    let (migrate, values, (acked, ret)) = match ret {
+        Ok(ret) => ret,
+        Err(e) => return SimpleError::new(format!("ERR {}", e)).into(),
+    };
+    // the keys now on the target are removed even while a script keeps the server busy
+    let _guard = backend.lock_shared_async().await;
+    let mut aof = backend.aof().is_enabled().then(|| backend.aof().lock());
+    let reply = migrate.finish(backend, &values, acked, ret);
+    if let Some(aof) = aof.as_mut() {
+        let cmd = Command::Migrate(migrate);
+        aof.append(&cmd.propagated(request, &reply, backend));
+    }
+    reply
+}
+
+// Run a command and append what it changed to the append only file. While appendonly is on,
+// writes hold the file while they run, so that it sees them in the order they were made. A
+// write that finds it off is still running when it is turned on, and so makes it into the
+// snapshot the new file starts with, as that waits for the exclusive side of the backend lock.
+fn execute_logged(backend: &Backend, cmd: Command, request: RespFrame) -> RespFrame {
+    if !cmd.is_write() || !backend.aof().is_enabled() {
+        return cmd.execute(backend);
+    }
+    let mut aof = backend.aof().lock();
+    let reply = cmd.execute(backend);
+    aof.append(&cmd.propagated(request, &reply, backend));
+    reply
+}
+
+impl Session {
+    async fn execute(&mut self, cmd: Command, request: RespFrame, backend: &Backend) -> RespFrame {
+        match (cmd, self.queued.as_mut()) {
+            (Command::Multi(_), Some(_)) => {
+                SimpleError::new("ERR MULTI calls can not be nested").into()
+            }
+            (Command::Multi(_), None) => {
+                self.queued = Some(Vec::new());
+                RESP_OK.clone()
+            }
+            (Command::Discard(_), Some(_)) => {
+                self.reset();
+                self.unwatch(backend);
+                RESP_OK.clone()
+            }
+            (Command::Watch(_), Some(_)) => {
+                SimpleError::new("ERR WATCH inside MULTI is not allowed").into()
+            }
+            (Command::Watch(watch), None) => {
+                for key in watch.keys() {
+                    backend.watch(key, &self.watch_dirty);
+                    if !self.watched_keys.contains(key) {
+                        self.watched_keys.push(key.clone());
+                    }
+                }
+                RESP_OK.clone()
+            }
+            (Command::Unwatch(_), None) => {
+                self.unwatch(backend);
+                RESP_OK.clone()
+            }
+            (Command::Exec(_), Some(_)) => self.exec(backend).await,
+            (Command::Unrecognized(_), Some(_)) => {
+                self.dirty = true;
+                SimpleError::new("ERR unknown command").into()
+            }
+            (cmd, Some(queued)) => {
+                queued.push((cmd, request));
+                RESP_QUEUED.clone()
+            }
+            (cmd, None) if cmd.is_script_kill() => cmd.execute(backend),
+            (cmd @ (Command::Eval(_) | Command::EvalSha(_) | Command::FCall(_)), None) => {
+                execute_exclusive(backend, move |backend| {
+                    // nothing else runs meanwhile, and scripts may change the file through
+                    // CONFIG SET, so it is only held to append
+                    match reserve_memory(backend, cmd.is_denyoom()) {
+                        Ok(()) => {
+                            let reply = cmd.execute(backend);
+                            let mut requests = cmd.propagated(request, &reply, backend);
+                            // the writes of a script are replayed all or none like it ran
+                            if requests.len() > 1 {
+                                requests.insert(0, RespArray::new([b"MULTI".into()]).into());
+                                requests.push(RespArray::new([b"EXEC".into()]).into());
+                            }
+                            backend.aof().lock().append(&requests);
+                            reply
+                        }
+                        Err(oom) => oom,
+                    }
+                })
+                .await
+            }
+            (Command::Migrate(migrate), None) => execute_migrate(backend, migrate, request).await,
+            // like redis, SAVE blocks the server, which writes the dataset of a single moment
+            (cmd @ Command::Save(_), None) => {
+                execute_exclusive(backend, move |backend| cmd.execute(backend)).await
+            }
+            (cmd, None) => match lock_shared(backend).await {
+                Ok(_guard) => match reserve_memory(backend, cmd.is_denyoom()) {
+                    Ok(()) => execute_logged(backend, cmd, request),
+                    Err(oom) => oom,
+                },
+                Err(busy) => busy,
+            },
+        }
+    }
+
+    async fn exec(&mut self, backend: &Backend) -> RespFrame {
+        let queued = self.queued.take().unwrap_or_default();
+        let dirty = std::mem::take(&mut self.dirty);
+        if dirty {
+            self.unwatch(backend);
+            return SimpleError::new("EXECABORT Transaction discarded because of previous errors.")
+                .into();
+        }
+        let keys = self.watched_keys.clone();
+        let watch_dirty = self.watch_dirty.clone();
+        let ret = execute_exclusive(backend, move |backend| {
+            // watched keys which expired meanwhile are only removed, and so flagged, on access
+            for key in &keys {
+                backend.exists(key);
+            }
+            if watch_dirty.load(Ordering::Acquire) {
+                return RespNullArray.into();
+            }
+            if let Err(oom) =
+                reserve_memory(backend, queued.iter().any(|(cmd, _)| cmd.is_denyoom()))
+            {
+                return oom;
+            }
+            let mut requests = Vec::new();
+            let results = queued
+                .into_iter()
+                .map(|(cmd, request)| {
+                    let reply = cmd.execute(backend);
+                    // the writes made before appendonly was turned on are in its snapshot
+                    if backend.aof().is_enabled() {
+                        requests.extend(cmd.propagated(request, &reply, backend));
+                    }
+                    reply
+                })
+                .collect::<Vec<RespFrame>>();
+            if !requests.is_empty() {
+                requests.insert(0, RespArray::new([b"MULTI".into()]).into());
+                requests.push(RespArray::new([b"EXEC".into()]).into());
+                backend.aof().lock().append(&requests);
+            }
+            RespArray::new(results).into()
+        })
+        .await;
+        self.unwatch(backend);
+        ret
+    }
+
+    fn subscribe(
+        &mut self,
+        kind: Subscription,
+        names: &[Bytes],
+        backend: &Backend,
+    ) -> Vec<RespFrame> {
+        let mut replies = Vec::with_capacity(names.len());
+        for name in names {
+            if !self.subscriptions(kind).contains(name) {
+                let registry = backend.pubsub();
+                match kind {
+                    Subscription::Channel => registry.subscribe(name, self.id, &self.subscriber),
+                    Subscription::Pattern => registry.psubscribe(name, self.id, &self.subscriber),
+                    Subscription::Shard => {
+                        backend
+                            .shard_pubsub()
+                            .ssubscribe(name, self.id, &self.subscriber)
+                    }
+                }
+                self.subscriptions(kind).push(name.clone());
+            }
+            replies.push(self.subscription_reply(kind, kind.subscribe(), Some(name)));
+        }
+        replies
+    }
+
+    // without names, unsubscribes from everything subscribed with the same kind
+    fn unsubscribe(
+        &mut self,
+        kind: Subscription,
+        names: &[Bytes],
+        backend: &Backend,
+    ) -> Vec<RespFrame> {
+        let names = match names {
+            [] => self.subscriptions(kind).clone(),
+            names => names.to_vec(),
+        };
+        if names.is_empty() {
+            return vec![self.subscription_reply(kind, kind.unsubscribe(), None)];
+        }
+        let mut replies = Vec::with_capacity(names.len());
+        for name in &names {
+            let subscriptions = self.subscriptions(kind);
+            if let Some(pos) = subscriptions.iter().position(|n| n == name) {
+                subscriptions.remove(pos);
+                let registry = backend.pubsub();
+                match kind {
+                    Subscription::Channel => registry.unsubscribe(name, self.id),
+                    Subscription::Pattern => registry.punsubscribe(name, self.id),
+                    Subscription::Shard => backend.shard_pubsub().sunsubscribe(name, self.id),
+                }
+            }
+            replies.push(self.subscription_reply(kind, kind.unsubscribe(), Some(name)));
+        }
+        replies
+    }
+
+    fn subscriptions(&mut self, kind: Subscription) -> &mut Vec<Bytes> {
+        match kind {
+            Subscription::Channel => &mut self.channels,
+            Subscription::Pattern => &mut self.patterns,
+            Subscription::Shard => &mut self.shard_channels,
+        }
+    }
+
+    fn subscription_count(&self) -> usize {
+        self.channels.len() + self.patterns.len() + self.shard_channels.len()
+    }
+
+    // `[action, name, number of subscriptions left]`, shard channels are counted apart from
+    // channels and patterns
+    fn subscription_reply(
+        &self,
+        kind: Subscription,
+        action: &str,
+        name: Option<&Bytes>,
+    ) -> RespFrame {
+        let count = match kind {
+            Subscription::Channel | Subscription::Pattern => {
+                self.channels.len() + self.patterns.len()
+            }
+            Subscription::Shard => self.shard_channels.len(),
+        };
+        let name = match name {
+            Some(name) => BulkString::from(name.clone()).into(),
+            None => RespNullBulkString.into(),
+        };
+        RespPush::new([
+            BulkString::new(action).into(),
+            name,
+            RespFrame::Integer(count as i64),
+        ])
+        .into()
+    }
+
+    // the connection is gone, drop everything it left behind in the backend
+    fn close(&mut self, backend: &Backend) {
+        self.unwatch(backend);
+        self.unsubscribe(Subscription::Channel, &[], backend);
+        self.unsubscribe(Subscription::Pattern, &[], backend);
+        self.unsubscribe(Subscription::Shard, &[], backend);
+    }
+
+    fn hello(&mut self, cmd: &Hello) -> RespFrame {
+        if let Err(e) = cmd.check() {
+            return e.into();
+        }
+        if let Some(protocol) = cmd.protocol() {
+            self.protocol = protocol;
+        }
+        if let Some(name) = cmd.client_name() {
+            self.name = Some(name.to_string());
+        }
+        Hello::reply(self.id, self.protocol)
+    }
+
+    // RESP2 clients don't understand the RESP3 only types
+    fn encode_for_client(&self, frame: RespFrame) -> RespFrame {
+        if self.protocol < 3 {
+            frame.into_resp2()
+        } else {
+            frame
+        }
+    }
+
+    fn reset(&mut self) {
+        self.queued = None;
+        self.dirty = false;
+    }
+
+    // RESET: back to the state of a fresh connection, keeping only its id
+    fn reset_connection(&mut self, backend: &Backend) -> RespFrame {
+        self.reset();
+        self.close(backend);
+        self.protocol = 2;
+        self.name = None;
+        SimpleString::new("RESET").into()
+    }
+
+    fn unwatch(&mut self, backend: &Backend) {
+        for key in self.watched_keys.drain(..) {
+            backend.unwatch(&key, &self.watch_dirty);
+        }
+        self.watch_dirty.store(false, Ordering::Release);
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        let (subscriber, messages) = Subscriber::channel();
+        Session {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            queued: None,
+            dirty: false,
+            watched_keys: Vec::new(),
+            watch_dirty: Arc::new(AtomicBool::new(false)),
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            shard_channels: Vec::new(),
+            subscriber,
+            messages,
+            protocol: 2,
+            name: None,
+        }
+    }
+}
+
+impl Subscription {
+    fn subscribe(self) -> &'static str {
+        match self {
+            Subscription::Channel => "subscribe",
+            Subscription::Pattern => "psubscribe",
+            Subscription::Shard => "ssubscribe",
+        }
+    }
+    fn unsubscribe(self) -> &'static str {
+        match self {
+            Subscription::Channel => "unsubscribe",
+            Subscription::Pattern => "punsubscribe",
+            Subscription::Shard => "sunsubscribe",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BulkString;
+
+    use super::*;
+
+    fn command(args: &[&str]) -> RespFrame {
+        RespArray::new(
+            args.iter()
+                .map(|v| BulkString::new(*v).into())
+                .collect::<Vec<RespFrame>>(),
+        )
+        .into()
+    }
+
+    async fn call(session: &mut Session, backend: &Backend, args: &[&str]) -> RespFrame {
+        let request = RedisRequest {
+            frame: command(args),
+            backend: backend.clone(),
+        };
+        let mut frames = request_handler(request, session).await.unwrap().frames;
+        assert_eq!(frames.len(), 1);
+        frames.pop().unwrap()
+    }
+
+    async fn call_all(session: &mut Session, backend: &Backend, args: &[&str]) -> Vec<RespFrame> {
+        let request = RedisRequest {
+            frame: command(args),
+            backend: backend.clone(),
+        };
+        request_handler(request, session).await.unwrap().frames
+    }
+
+    async fn wait_for_rewrite(backend: &Backend) {
+        for _ in 0..500 {
+            if !backend.aof().rewrite_in_progress() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("append only file rewrite didn't finish");
+    }
+
+    fn push(values: &[RespFrame]) -> RespFrame {
+        RespPush::new(values.to_vec()).into()
+    }
+
+    fn bulk(value: &str) -> RespFrame {
+        BulkString::new(value).into()
+    }
+
+    #[tokio::test]
+    async fn test_multi_exec() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        assert_eq!(call(&mut session, &backend, &["multi"]).await, *RESP_OK);
+        assert_eq!(
+            call(&mut session, &backend, &["set", "hello", "world"]).await,
+            *RESP_QUEUED
+        );
+        assert_eq!(
+            call(&mut session, &backend, &["get", "hello"]).await,
+            *RESP_QUEUED
+        );
+        assert_eq!(backend.get(b"hello"), Ok(None));
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            RespArray::new([RESP_OK.clone(), b"world".into()]).into()
+        );
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            SimpleError::new("ERR EXEC without MULTI").into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maxmemory_oom() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        call(&mut session, &backend, &["set", "hello", "world"]).await;
+        backend.memory_config().set_maxmemory(1);
+        assert_eq!(
+            call(&mut session, &backend, &["set", "other", "value"]).await,
+            *RESP_OOM
+        );
+        // reads still go through
+        assert_eq!(
+            call(&mut session, &backend, &["get", "hello"]).await,
+            b"world".into()
+        );
+        call(&mut session, &backend, &["multi"]).await;
+        call(&mut session, &backend, &["sadd", "set", "a"]).await;
+        assert_eq!(call(&mut session, &backend, &["exec"]).await, *RESP_OOM);
+
+        // evicting makes room for the write
+        call(
+            &mut session,
+            &backend,
+            &["config", "set", "maxmemory-policy", "allkeys-lru"],
+        )
+        .await;
+        backend
+            .memory_config()
+            .set_maxmemory(backend.used_memory() - 1);
+        assert_eq!(
+            call(&mut session, &backend, &["set", "other", "value"]).await,
+            *RESP_OK
+        );
+        assert!(!backend.exists(b"hello"));
+        assert_eq!(backend.get(b"other"), Ok(Some("value".into())));
+        assert_eq!(backend.evicted_keys(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_exec_not_starved_by_commands() {
+        let backend = Backend::new();
+        let stop = Arc::new(AtomicBool::new(false));
+        // readers overlapping one another, so that the shared side is never free
+        let readers = (0..4)
+            .map(|_| {
+                let (backend, stop) = (backend.clone(), stop.clone());
+                tokio::spawn(async move {
+                    while !stop.load(Ordering::Relaxed) {
+                        let _guard = lock_shared(&backend).await.unwrap();
+                        tokio::time::sleep(Duration::from_millis(2)).await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let mut session = Session::default();
+        call(&mut session, &backend, &["multi"]).await;
+        call(&mut session, &backend, &["set", "hello", "world"]).await;
+        let exec = call(&mut session, &backend, &["exec"]);
+        let reply = tokio::time::timeout(Duration::from_secs(2), exec).await;
+        stop.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.await.unwrap();
+        }
+        assert_eq!(
+            reply.expect("EXEC waited behind the commands"),
+            RespArray::new([RESP_OK.clone()]).into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multi_discard() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        call(&mut session, &backend, &["multi"]).await;
+        assert!(matches!(
+            call(&mut session, &backend, &["multi"]).await,
+            RespFrame::Error(_)
+        ));
+        call(&mut session, &backend, &["set", "hello", "world"]).await;
+        assert_eq!(call(&mut session, &backend, &["discard"]).await, *RESP_OK);
+        assert_eq!(backend.get(b"hello"), Ok(None));
+        assert_eq!(
+            call(&mut session, &backend, &["discard"]).await,
+            SimpleError::new("ERR DISCARD without MULTI").into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multi_execabort() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        call(&mut session, &backend, &["multi"]).await;
+        call(&mut session, &backend, &["set", "hello", "world"]).await;
+        assert!(matches!(
+            call(&mut session, &backend, &["get"]).await,
+            RespFrame::Error(_)
+        ));
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            SimpleError::new("EXECABORT Transaction discarded because of previous errors.").into()
+        );
+        assert_eq!(backend.get(b"hello"), Ok(None));
+        assert!(session.queued.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_watch_exec() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        assert_eq!(
+            call(&mut session, &backend, &["watch", "stock"]).await,
+            *RESP_OK
+        );
+        call(&mut session, &backend, &["multi"]).await;
+        call(&mut session, &backend, &["set", "stock", "9"]).await;
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            RespArray::new([RESP_OK.clone()]).into()
+        );
+
+        call(&mut session, &backend, &["watch", "stock"]).await;
+        // another connection modifies the watched key
+        backend.set("stock".into(), "8".into());
+        call(&mut session, &backend, &["multi"]).await;
+        call(&mut session, &backend, &["set", "stock", "7"]).await;
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            RespNullArray.into()
+        );
+        assert_eq!(backend.get(b"stock"), Ok(Some("8".into())));
+        assert!(session.watched_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_flush_and_expire() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        backend.set("stock".into(), "9".into());
+        call(&mut session, &backend, &["watch", "stock", "other"]).await;
+        call(&mut session, &backend, &["flushall"]).await;
+        call(&mut session, &backend, &["multi"]).await;
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            RespNullArray.into()
+        );
+
+        backend.set("stock".into(), "9".into());
+        backend.pexpire_at(b"stock", crate::now_ms() + 20);
+        call(&mut session, &backend, &["watch", "stock"]).await;
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        call(&mut session, &backend, &["multi"]).await;
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            RespNullArray.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unwatch() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        call(&mut session, &backend, &["watch", "stock"]).await;
+        call(&mut session, &backend, &["unwatch"]).await;
+        backend.set("stock".into(), "8".into());
+        call(&mut session, &backend, &["multi"]).await;
+        assert!(matches!(
+            call(&mut session, &backend, &["watch", "stock"]).await,
+            RespFrame::Error(_)
+        ));
+        assert_eq!(
+            call(&mut session, &backend, &["exec"]).await,
+            RespArray::new(Vec::<RespFrame>::new()).into()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_busy_script_kill() {
+        let backend = Backend::new();
+        backend.scripts().set_time_limit(Duration::from_millis(10));
+        let handle = {
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                let mut session = Session::default();
+                call(&mut session, &backend, &["eval", "while true do end", "0"]).await
+            })
+        };
+        let mut session = Session::default();
+        while !backend.scripts().is_busy() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(
+            call(&mut session, &backend, &["get", "hello"]).await,
+            *RESP_BUSY
+        );
+        assert_eq!(
+            call(&mut session, &backend, &["script", "kill"]).await,
+            *RESP_OK
+        );
+        assert!(matches!(handle.await.unwrap(), RespFrame::Error(_)));
+        assert!(matches!(
+            call(&mut session, &backend, &["script", "kill"]).await,
+            RespFrame::Error(e) if e.starts_with("NOTBUSY")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_publish() {
+        let backend = Backend::new();
+        let mut subscriber = Session::default();
+        let mut publisher = Session::default();
+        assert_eq!(
+            call_all(&mut subscriber, &backend, &["subscribe", "news", "sport"]).await,
+            vec![
+                push(&[bulk("subscribe"), bulk("news"), RespFrame::Integer(1)]),
+                push(&[bulk("subscribe"), bulk("sport"), RespFrame::Integer(2)]),
+            ]
+        );
+        assert_eq!(
+            call(&mut subscriber, &backend, &["psubscribe", "n*"]).await,
+            push(&[bulk("psubscribe"), bulk("n*"), RespFrame::Integer(3)])
+        );
+        assert!(matches!(
+            call(&mut subscriber, &backend, &["get", "news"]).await,
+            RespFrame::Error(e) if e.starts_with("ERR Can't execute 'get'")
+        ));
+
+        assert_eq!(
+            call(&mut publisher, &backend, &["publish", "news", "hello"]).await,
+            RespFrame::Integer(2)
+        );
+        assert_eq!(
+            subscriber.messages.try_recv().unwrap(),
+            push(&[bulk("message"), bulk("news"), bulk("hello")])
+        );
+        assert_eq!(
+            subscriber.messages.try_recv().unwrap(),
+            push(&[bulk("pmessage"), bulk("n*"), bulk("news"), bulk("hello")])
+        );
+
+        assert_eq!(
+            call_all(&mut subscriber, &backend, &["unsubscribe"]).await,
+            vec![
+                push(&[bulk("unsubscribe"), bulk("news"), RespFrame::Integer(2)]),
+                push(&[bulk("unsubscribe"), bulk("sport"), RespFrame::Integer(1)]),
+            ]
+        );
+        assert_eq!(
+            call(&mut subscriber, &backend, &["punsubscribe", "n*"]).await,
+            push(&[bulk("punsubscribe"), bulk("n*"), RespFrame::Integer(0)])
+        );
+        assert_eq!(
+            call(&mut subscriber, &backend, &["unsubscribe"]).await,
+            push(&[
+                bulk("unsubscribe"),
+                RespNullBulkString.into(),
+                RespFrame::Integer(0)
+            ])
+        );
+        assert_eq!(
+            call(&mut publisher, &backend, &["publish", "news", "hello"]).await,
+            RespFrame::Integer(0)
+        );
+        assert_eq!(
+            call(&mut subscriber, &backend, &["get", "news"]).await,
+            RespFrame::Null(crate::RespNull)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_ping_quit_reset() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        assert_eq!(
+            call(&mut session, &backend, &["ping"]).await,
+            SimpleString::new("PONG").into()
+        );
+        assert_eq!(
+            call(&mut session, &backend, &["ping", "hi"]).await,
+            bulk("hi")
+        );
+        call(&mut session, &backend, &["subscribe", "news"]).await;
+        assert_eq!(
+            call(&mut session, &backend, &["ping"]).await,
+            RespArray::new([bulk("pong"), bulk("")]).into()
+        );
+        assert_eq!(
+            call(&mut session, &backend, &["ping", "hi"]).await,
+            RespArray::new([bulk("pong"), bulk("hi")]).into()
+        );
+
+        // RESET leaves subscribed mode and forgets what HELLO set
+        call(&mut session, &backend, &["hello", "3", "setname", "app"]).await;
+        assert_eq!(
+            call(&mut session, &backend, &["reset"]).await,
+            SimpleString::new("RESET").into()
+        );
+        assert_eq!(session.subscription_count(), 0);
+        assert_eq!((session.protocol, session.name.as_deref()), (2, None));
+        assert_eq!(
+            call(
+                &mut Session::default(),
+                &backend,
+                &["publish", "news", "hello"]
+            )
+            .await,
+            RespFrame::Integer(0)
+        );
+
+        call(&mut session, &backend, &["subscribe", "news"]).await;
+        let request = RedisRequest {
+            frame: command(&["quit"]),
+            backend: backend.clone(),
+        };
+        let response = request_handler(request, &mut session).await.unwrap();
+        assert_eq!(response.frames, vec![RESP_OK.clone()]);
+        assert!(response.close);
+    }
+
+    #[tokio::test]
+    async fn test_hello_protocol() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        backend
+            .hset("user".into(), "name".into(), "alice".into())
+            .unwrap();
+
+        let reply = call(&mut session, &backend, &["hgetall", "user"]).await;
+        assert_eq!(
+            session.encode_for_client(reply).encode(),
+            b"*2\r\n$4\r\nname\r\n$5\r\nalice\r\n"
+        );
+        let reply = call(&mut session, &backend, &["get", "missing"]).await;
+        assert_eq!(session.encode_for_client(reply).encode(), b"$-1\r\n");
+        let message = RespPush::new([bulk("message"), bulk("news"), bulk("hi")]).into();
+        assert!(session
+            .encode_for_client(message)
+            .encode()
+            .starts_with(b"*3\r\n"));
+
+        assert!(matches!(
+            call(&mut session, &backend, &["hello", "4"]).await,
+            RespFrame::Error(e) if e.starts_with("NOPROTO")
+        ));
+        let reply = call(&mut session, &backend, &["hello", "3", "setname", "worker"]).await;
+        match reply {
+            RespFrame::Map(map) => assert_eq!(map.get(&b"proto"[..]), Some(&RespFrame::Integer(3))),
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+        assert_eq!(session.name.as_deref(), Some("worker"));
+
+        let reply = call(&mut session, &backend, &["hgetall", "user"]).await;
+        assert_eq!(
+            session.encode_for_client(reply).encode(),
+            b"%1\r\n$4\r\nname\r\n$5\r\nalice\r\n"
+        );
+        let reply = call(&mut session, &backend, &["get", "missing"]).await;
+        assert_eq!(session.encode_for_client(reply).encode(), b"_\r\n");
+
+        // RESP3 connections keep running commands while subscribed
+        let reply = call(&mut session, &backend, &["subscribe", "news"]).await;
+        assert!(session
+            .encode_for_client(reply)
+            .encode()
+            .starts_with(b">3\r\n"));
+        assert_eq!(
+            call(&mut session, &backend, &["get", "missing"]).await,
+            RespFrame::Null(crate::RespNull)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ssubscribe_spublish() {
+        let backend = Backend::new();
+        let mut subscriber = Session::default();
+        let mut publisher = Session::default();
+        assert_eq!(
+            call(&mut subscriber, &backend, &["subscribe", "news"]).await,
+            push(&[bulk("subscribe"), bulk("news"), RespFrame::Integer(1)])
+        );
+        assert_eq!(
+            call_all(&mut subscriber, &backend, &["ssubscribe", "{u}.a", "{u}.b"]).await,
+            vec![
+                push(&[bulk("ssubscribe"), bulk("{u}.a"), RespFrame::Integer(1)]),
+                push(&[bulk("ssubscribe"), bulk("{u}.b"), RespFrame::Integer(2)]),
+            ]
+        );
+        assert!(matches!(
+            call(&mut subscriber, &backend, &["ssubscribe", "a", "b"]).await,
+            RespFrame::Error(e) if e.starts_with("CROSSSLOT")
+        ));
+
+        assert_eq!(
+            call(&mut publisher, &backend, &["spublish", "{u}.a", "hello"]).await,
+            RespFrame::Integer(1)
+        );
+        assert_eq!(
+            call(&mut publisher, &backend, &["publish", "{u}.a", "hello"]).await,
+            RespFrame::Integer(0)
+        );
+        assert_eq!(
+            subscriber.messages.try_recv().unwrap(),
+            push(&[bulk("smessage"), bulk("{u}.a"), bulk("hello")])
+        );
+        assert!(subscriber.messages.try_recv().is_err());
+
+        assert_eq!(
+            call_all(&mut subscriber, &backend, &["sunsubscribe"]).await,
+            vec![
+                push(&[bulk("sunsubscribe"), bulk("{u}.a"), RespFrame::Integer(1)]),
+                push(&[bulk("sunsubscribe"), bulk("{u}.b"), RespFrame::Integer(0)]),
+            ]
+        );
+        // still in subscribed mode through the plain channel
+        assert!(matches!(
+            call(&mut subscriber, &backend, &["get", "news"]).await,
+            RespFrame::Error(_)
+        ));
+        subscriber.close(&backend);
+        assert_eq!(backend.shard_pubsub().numsub(b"{u}.a"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_close_unsubscribes() {
+        let backend = Backend::new();
+        let mut session = Session::default();
+        call(&mut session, &backend, &["subscribe", "news"]).await;
+        call(&mut session, &backend, &["psubscribe", "n*"]).await;
+        session.close(&backend);
+        assert_eq!(backend.pubsub().numsub(b"news"), 0);
+        assert_eq!(backend.pubsub().numpat(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_migrate_logged_as_del() -> Result<()> {
+        let target = Backend::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port().to_string();
+        let target_clone = target.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(stream_handler(stream, target_clone.clone()));
+            }
+        });
+
+        let dir = std::env::temp_dir().join(format!(
+            "simple-redis-network-migrate-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.set("hello".into(), "world".into());
+        crate::aof::enable(&backend)?;
+        wait_for_rewrite(&backend).await;
+        let mut session = Session::default();
+        let reply = call(
+            &mut session,
+            &backend,
+            &["migrate", "127.0.0.1", &port, "hello", "0", "1000"],
+        )
+        .await;
+        assert_eq!(reply, *RESP_OK);
+        assert!(!backend.exists(b"hello"));
+        assert_eq!(target.get(b"hello"), Ok(Some("world".into())));
+
+        let data = std::fs::read(dir.join(crate::aof::AOF_FILENAME))?;
+        let other = Backend::new();
+        let (stats, _) = crate::aof::replay(&data, &other, false)?;
+        assert_eq!((stats.keys, stats.commands), (1, 1));
+        assert!(!other.exists(b"hello"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_aof_logging() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-network-aof-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let backend = Backend::new();
+        let mut session = Session::default();
+        let dir_arg = dir.to_str().unwrap();
+        call(&mut session, &backend, &["config", "set", "dir", dir_arg]).await;
+        call(&mut session, &backend, &["set", "before", "1"]).await;
+        assert_eq!(
+            call(
+                &mut session,
+                &backend,
+                &["config", "set", "appendonly", "yes"]
+            )
+            .await,
+            *RESP_OK
+        );
+        // the writes made before the snapshot is copied would be in it rather than replayed
+        wait_for_rewrite(&backend).await;
+
+        call(&mut session, &backend, &["set", "hello", "world"]).await;
+        // neither failed writes nor reads are logged
+        let reply = call(&mut session, &backend, &["lpush", "hello", "x"]).await;
+        assert!(matches!(reply, RespFrame::Error(_)));
+        call(&mut session, &backend, &["get", "hello"]).await;
+        call(&mut session, &backend, &["multi"]).await;
+        call(&mut session, &backend, &["rpush", "list", "a", "b"]).await;
+        call(&mut session, &backend, &["get", "list"]).await;
+        call(&mut session, &backend, &["exec"]).await;
+        // scripts are logged by the writes they made, all of them or none
+        let script = "redis.call('set', KEYS[1], ARGV[1])
+            redis.call('get', KEYS[1])
+            return redis.call('rpush', 'list', redis.call('get', KEYS[1]))";
+        let sha = match call(&mut session, &backend, &["script", "load", script]).await {
+            RespFrame::BulkString(sha) => String::from_utf8(sha.to_vec())?,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        call(
+            &mut session,
+            &backend,
+            &["evalsha", &sha, "1", "script", "ok"],
+        )
+        .await;
+
+        wait_for_rewrite(&backend).await;
+        let data = std::fs::read(dir.join(crate::aof::AOF_FILENAME))?;
+        let other = Backend::new();
+        let (stats, len) = crate::aof::replay(&data, &other, false)?;
+        assert_eq!(len, data.len());
+        assert_eq!((stats.keys, stats.commands), (1, 4));
+        assert_eq!(other.get(b"before"), Ok(Some("1".into())));
+        assert_eq!(other.get(b"hello"), Ok(Some("world".into())));
+        assert_eq!(other.get(b"script"), Ok(Some("ok".into())));
+        assert_eq!(
+            other.lrange(b"list", 0, -1),
+            Ok(vec!["a".into(), "b".into(), "ok".into()])
+        );
+        let logged = String::from_utf8_lossy(&data);
+        assert!(!logged.contains("evalsha") && !logged.contains("EVAL"));
+
+        call(
+            &mut session,
+            &backend,
+            &["config", "set", "appendonly", "no"],
+        )
+        .await;
+        assert!(!dir.join(crate::aof::AOF_FILENAME).exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,249 @@
+/*
+   The compact containers redis serializes small values as, each stored as a single RDB string.
+
+   - listpack: "<total-bytes: u32 le><count: u16 le><entry>...<0xff>"
+       - entry: "<encoding><data><backlen>", backlen being the size of encoding and data
+       - 0xxxxxxx: 7 bit uint, 10xxxxxx: 6 bit length string, 110xxxxx: 13 bit int,
+         1110xxxx: 12 bit length string, 0xf0: 32 bit length string,
+         0xf1 to 0xf4: 16, 24, 32 and 64 bit int
+   - ziplist: "<total-bytes: u32 le><tail-offset: u32 le><count: u16 le><entry>...<0xff>"
+       - entry: "<prevlen: 1 byte, or 0xfe and u32 le><encoding><data>"
+       - 00xxxxxx: 6 bit length string, 01xxxxxx: 14 bit length string,
+         0x80: u32 be length string, 0xc0, 0xd0, 0xe0, 0xf0, 0xfe: 16, 32, 64, 24, 8 bit int,
+         0xf1 to 0xfd: 0 to 12
+   - intset: "<width: u32 le><count: u32 le><int le>..."
+*/
+use bytes::Bytes;
+
+use super::RdbError;
+
+const LISTPACK_HEADER_SIZE: usize = 6;
+const ZIPLIST_HEADER_SIZE: usize = 10;
+const CONTAINER_END: u8 = 0xff;
+// a listpack too large to count its entries in the header
+const LISTPACK_UNKNOWN_COUNT: u16 = u16::MAX;
+
+/// The entries of a listpack, integers formatted as strings.
+pub(crate) fn listpack_entries(buf: &[u8]) -> Result<Vec<Bytes>, RdbError> {
+    if buf.len() < LISTPACK_HEADER_SIZE + 1 || read_u32_le(buf, 0)? as usize != buf.len() {
+        return Err(RdbError::BadFormat);
+    }
+    let count = u16::from_le_bytes([buf[4], buf[5]]);
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut pos = LISTPACK_HEADER_SIZE;
+    loop {
+        let encoding = byte(buf, pos)?;
+        if encoding == CONTAINER_END {
+            break;
+        }
+        let (entry, len) = match encoding {
+            0x00..=0x7f => (int_entry(encoding as i64), 1),
+            0x80..=0xbf => {
+                let len = (encoding & 0x3f) as usize;
+                (str_entry(buf, pos + 1, len)?, 1 + len)
+            }
+            0xc0..=0xdf => {
+                let v = ((encoding as i64 & 0x1f) << 8) | byte(buf, pos + 1)? as i64;
+                (int_entry(sign_extend(v, 13)), 2)
+            }
+            0xe0..=0xef => {
+                let len = ((encoding as usize & 0x0f) << 8) | byte(buf, pos + 1)? as usize;
+                (str_entry(buf, pos + 2, len)?, 2 + len)
+            }
+            0xf0 => {
+                let len = read_u32_le(buf, pos + 1)? as usize;
+                (str_entry(buf, pos + 5, len)?, 5 + len)
+            }
+            0xf1 => (int_entry(read_int_le(buf, pos + 1, 2)?), 3),
+            0xf2 => (int_entry(read_int_le(buf, pos + 1, 3)?), 4),
+            0xf3 => (int_entry(read_int_le(buf, pos + 1, 4)?), 5),
+            0xf4 => (int_entry(read_int_le(buf, pos + 1, 8)?), 9),
+            _ => return Err(RdbError::BadFormat),
+        };
+        entries.push(entry);
+        pos += len + backlen_size(len);
+    }
+    if pos + 1 != buf.len() || (count != LISTPACK_UNKNOWN_COUNT && count as usize != entries.len())
+    {
+        return Err(RdbError::BadFormat);
+    }
+    Ok(entries)
+}
+
+/// The entries of a ziplist, integers formatted as strings.
+pub(crate) fn ziplist_entries(buf: &[u8]) -> Result<Vec<Bytes>, RdbError> {
+    if buf.len() < ZIPLIST_HEADER_SIZE + 1 || read_u32_le(buf, 0)? as usize != buf.len() {
+        return Err(RdbError::BadFormat);
+    }
+    let mut entries = Vec::new();
+    let mut pos = ZIPLIST_HEADER_SIZE;
+    loop {
+        let prevlen = byte(buf, pos)?;
+        if prevlen == CONTAINER_END {
+            break;
+        }
+        pos += if prevlen < 0xfe { 1 } else { 5 };
+        let encoding = byte(buf, pos)?;
+        let (entry, len) = match encoding >> 6 {
+            0 => {
+                let len = (encoding & 0x3f) as usize;
+                (str_entry(buf, pos + 1, len)?, 1 + len)
+            }
+            1 => {
+                let len = ((encoding as usize & 0x3f) << 8) | byte(buf, pos + 1)? as usize;
+                (str_entry(buf, pos + 2, len)?, 2 + len)
+            }
+            2 => {
+                let data = slice(buf, pos + 1, 4)?;
+                let len = u32::from_be_bytes(data.try_into().unwrap()) as usize;
+                (str_entry(buf, pos + 5, len)?, 5 + len)
+            }
+            _ => match encoding {
+                0xc0 => (int_entry(read_int_le(buf, pos + 1, 2)?), 3),
+                0xd0 => (int_entry(read_int_le(buf, pos + 1, 4)?), 5),
+                0xe0 => (int_entry(read_int_le(buf, pos + 1, 8)?), 9),
+                0xf0 => (int_entry(read_int_le(buf, pos + 1, 3)?), 4),
+                0xfe => (int_entry(read_int_le(buf, pos + 1, 1)?), 2),
+                0xf1..=0xfd => (int_entry((encoding & 0x0f) as i64 - 1), 1),
+                _ => return Err(RdbError::BadFormat),
+            },
+        };
+        entries.push(entry);
+        pos += len;
+    }
+    if pos + 1 != buf.len() {
+        return Err(RdbError::BadFormat);
+    }
+    Ok(entries)
+}
+
+/// The members of an intset, in order.
+pub(crate) fn intset_members(buf: &[u8]) -> Result<Vec<i64>, RdbError> {
+    let width = read_u32_le(buf, 0)? as usize;
+    let count = read_u32_le(buf, 4)? as usize;
+    if !matches!(width, 2 | 4 | 8) || count.checked_mul(width) != Some(buf.len() - 8) {
+        return Err(RdbError::BadFormat);
+    }
+    (0..count)
+        .map(|i| read_int_le(buf, 8 + i * width, width))
+        .collect()
+}
+
+fn byte(buf: &[u8], pos: usize) -> Result<u8, RdbError> {
+    buf.get(pos).copied().ok_or(RdbError::BadFormat)
+}
+
+fn slice(buf: &[u8], pos: usize, len: usize) -> Result<&[u8], RdbError> {
+    let end = pos.checked_add(len).ok_or(RdbError::BadFormat)?;
+    buf.get(pos..end).ok_or(RdbError::BadFormat)
+}
+
+fn read_u32_le(buf: &[u8], pos: usize) -> Result<u32, RdbError> {
+    Ok(u32::from_le_bytes(slice(buf, pos, 4)?.try_into().unwrap()))
+}
+
+// a signed little endian integer of `width` bytes
+fn read_int_le(buf: &[u8], pos: usize, width: usize) -> Result<i64, RdbError> {
+    let data = slice(buf, pos, width)?;
+    let mut bytes = [0; 8];
+    bytes[..width].copy_from_slice(data);
+    Ok(sign_extend(i64::from_le_bytes(bytes), width as u32 * 8))
+}
+
+fn sign_extend(v: i64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    (v << shift) >> shift
+}
+
+fn str_entry(buf: &[u8], pos: usize, len: usize) -> Result<Bytes, RdbError> {
+    Ok(Bytes::copy_from_slice(slice(buf, pos, len)?))
+}
+
+fn int_entry(v: i64) -> Bytes {
+    Bytes::from(v.to_string())
+}
+
+// how many bytes the back length of an entry of `len` bytes takes
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listpack_entries() -> anyhow::Result<()> {
+        // the listpack of `SADD myset one two three four five` in the repository dump.rdb
+        let buf =
+            b"$\x00\x00\x00\x05\x00\x83one\x04\x83two\x04\x85three\x06\x84four\x05\x84five\x05\xff";
+        assert_eq!(
+            listpack_entries(buf)?,
+            vec!["one", "two", "three", "four", "five"]
+        );
+
+        // 7 bit, 13 bit negative, 16 bit and 64 bit integers
+        let mut buf = vec![0, 0, 0, 0, 4, 0];
+        buf.extend_from_slice(&[0x05, 0x01]);
+        buf.extend_from_slice(&[0xdf, 0xff, 0x02]);
+        buf.extend_from_slice(&[0xf1, 0x10, 0x27, 0x03]);
+        buf.extend_from_slice(&[0xf4]);
+        buf.extend_from_slice(&i64::MIN.to_le_bytes());
+        buf.extend_from_slice(&[0x09, 0xff]);
+        let len = buf.len() as u32;
+        buf[..4].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(
+            listpack_entries(&buf)?,
+            vec![
+                "5".to_string(),
+                "-1".to_string(),
+                "10000".to_string(),
+                i64::MIN.to_string()
+            ]
+        );
+
+        // a wrong count or a missing terminator
+        buf[4] = 3;
+        assert_eq!(listpack_entries(&buf), Err(RdbError::BadFormat));
+        assert_eq!(
+            listpack_entries(b"\x08\x00\x00\x00\x01\x00\x01\x01"),
+            Err(RdbError::BadFormat)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ziplist_entries() -> anyhow::Result<()> {
+        let mut buf = vec![0; ZIPLIST_HEADER_SIZE];
+        buf.extend_from_slice(b"\x00\x05hello");
+        buf.extend_from_slice(&[0x07, 0xf4]);
+        buf.extend_from_slice(&[0x02, 0xfe, 0x9c]);
+        buf.extend_from_slice(&[0x03, 0xc0, 0x10, 0x27]);
+        buf.push(0xff);
+        let len = buf.len() as u32;
+        buf[..4].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(ziplist_entries(&buf)?, vec!["hello", "3", "-100", "10000"]);
+
+        buf.truncate(buf.len() - 1);
+        assert_eq!(ziplist_entries(&buf), Err(RdbError::BadFormat));
+        Ok(())
+    }
+
+    #[test]
+    fn test_intset_members() -> anyhow::Result<()> {
+        let mut buf = vec![4, 0, 0, 0, 2, 0, 0, 0];
+        buf.extend_from_slice(&(-70000i32).to_le_bytes());
+        buf.extend_from_slice(&3i32.to_le_bytes());
+        assert_eq!(intset_members(&buf)?, vec![-70000, 3]);
+
+        buf[4] = 3;
+        assert_eq!(intset_members(&buf), Err(RdbError::BadFormat));
+        Ok(())
+    }
+}
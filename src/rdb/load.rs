@@ -0,0 +1,274 @@
+/*
+   RDB snapshot files, as written by SAVE and BGSAVE.
+
+   - file: "REDIS<version: 4 digits><opcode or key>...<0xff><crc64: u64 le>"
+   - 0xfa aux: "<name><value>", 0xfe selectdb: "<db>", 0xfb resizedb: "<keys><expires>"
+   - 0xfc expiretime ms: "<unix-ms: u64 le>", 0xfd expiretime: "<unix-secs: u32 le>"
+   - 0xf8 idle: "<seconds>", 0xf9 freq: "<u8>", both apply to the key that follows
+   - 0xf5 function: "<library-code>"
+   - key: "<type><key><value>"
+*/
+use bytes::Bytes;
+
+use crate::{now_ms, Backend};
+
+use super::{
+    crc64, RdbError, RdbReader, RDB_HEADER_SIZE, RDB_OPCODE_AUX, RDB_OPCODE_EOF,
+    RDB_OPCODE_EXPIRETIME, RDB_OPCODE_EXPIRETIME_MS, RDB_OPCODE_FREQ, RDB_OPCODE_FUNCTION2,
+    RDB_OPCODE_IDLE, RDB_OPCODE_MODULE_AUX, RDB_OPCODE_RESIZEDB, RDB_OPCODE_SELECTDB,
+    RDB_SIGNATURE, RDB_VERSION,
+};
+
+// files from before version 5 have no checksum
+const RDB_CHECKSUM_VERSION: u16 = 5;
+
+/// What was found in a snapshot.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    /// Keys loaded into the backend.
+    pub keys: usize,
+    /// Keys left out because they already expired.
+    pub expired: usize,
+    /// Keys left out because they belong to a database other than 0.
+    pub skipped: usize,
+    /// Function libraries loaded.
+    pub functions: usize,
+}
+
+/// Load every key and function library of an RDB file into `backend`, checking the checksum
+/// when the file has one.
+pub fn load(data: &[u8], backend: &Backend) -> Result<LoadStats, RdbError> {
+    let (stats, len) = load_prefix(data, backend)?;
+    if len != data.len() {
+        return Err(RdbError::BadFormat);
+    }
+    Ok(stats)
+}
+
+/// Load the RDB file `data` starts with, returning how many bytes it took, for files carrying
+/// more after it such as the append only file.
+///
+/// Nothing reaches `backend` before the checksum is verified: the decoded keys and libraries
+/// are held until the end of the file, then applied.
+pub fn load_prefix(data: &[u8], backend: &Backend) -> Result<(LoadStats, usize), RdbError> {
+    if data.len() < RDB_HEADER_SIZE || !data.starts_with(RDB_SIGNATURE) {
+        return Err(RdbError::BadSignature);
+    }
+    let version = std::str::from_utf8(&data[RDB_SIGNATURE.len()..RDB_HEADER_SIZE])
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .ok_or(RdbError::BadSignature)?;
+    if version == 0 || version > RDB_VERSION {
+        return Err(RdbError::UnsupportedVersion(version));
+    }
+    let limits = backend.encoding_limits();
+    let now = now_ms();
+    let mut reader = RdbReader::new(&data[RDB_HEADER_SIZE..]);
+    let mut stats = LoadStats::default();
+    let mut db = 0;
+    let mut expire_at = None;
+    let mut idle_secs = None;
+    let mut freq = None;
+    let mut keys = Vec::new();
+    let mut functions = Vec::new();
+    loop {
+        match reader.read_u8()? {
+            RDB_OPCODE_EOF => break,
+            RDB_OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            }
+            RDB_OPCODE_SELECTDB => db = reader.read_length()?,
+            RDB_OPCODE_RESIZEDB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            RDB_OPCODE_EXPIRETIME_MS => expire_at = Some(reader.read_u64_le()?),
+            RDB_OPCODE_EXPIRETIME => {
+                let secs = u32::from_le_bytes(reader.read_exact(4)?.try_into().unwrap());
+                expire_at = Some(secs as u64 * 1000);
+            }
+            RDB_OPCODE_IDLE => idle_secs = Some(reader.read_length()? as u64),
+            RDB_OPCODE_FREQ => freq = Some(reader.read_u8()?),
+            RDB_OPCODE_FUNCTION2 => {
+                let code =
+                    String::from_utf8(reader.read_string()?).map_err(|_| RdbError::BadFormat)?;
+                functions.push(code);
+            }
+            ty @ RDB_OPCODE_MODULE_AUX => return Err(RdbError::UnsupportedType(ty)),
+            ty => {
+                let key = Bytes::from(reader.read_string()?);
+                let value = reader.read_object(ty, &limits)?;
+                // the metadata opcodes only apply to the key right after them
+                let expire_at = expire_at.take();
+                let (idle_secs, freq) = (idle_secs.take(), freq.take());
+                if db != 0 {
+                    stats.skipped += 1;
+                } else if expire_at.is_some_and(|at| at <= now) {
+                    stats.expired += 1;
+                } else {
+                    keys.push((key, value, expire_at, idle_secs, freq));
+                }
+            }
+        }
+    }
+    if version >= RDB_CHECKSUM_VERSION {
+        let body = RDB_HEADER_SIZE + reader.pos;
+        let expected = reader.read_u64_le()?;
+        // a zero checksum means it was disabled when the file was written
+        if expected != 0 && expected != crc64(&data[..body]) {
+            return Err(RdbError::BadChecksum);
+        }
+    }
+    for code in functions {
+        backend
+            .scripts()
+            .function_load(&code, false)
+            .map_err(|e| RdbError::Function(e.to_string()))?;
+        stats.functions += 1;
+    }
+    for (key, value, expire_at, idle_secs, freq) in keys {
+        backend.set_value(key.clone(), value, expire_at);
+        backend.set_access(&key, idle_secs.map(|secs| secs * 1000), freq);
+        stats.keys += 1;
+    }
+    Ok((stats, RDB_HEADER_SIZE + reader.pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use crate::{EncodingLimits, SetValue, StoredValue};
+
+    use super::*;
+
+    // the snapshot shipped with the repository, written by redis 7.2.5
+    const DUMP_RDB: &[u8] = include_bytes!("../../dump.rdb");
+
+    #[test]
+    fn test_load_repository_dump() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let stats = load(DUMP_RDB, &backend)?;
+        assert_eq!(
+            stats,
+            LoadStats {
+                keys: 3,
+                ..Default::default()
+            }
+        );
+        assert_eq!(backend.get(b"kevin"), Ok(Some("yang".into())));
+        assert_eq!(backend.get(b"hello"), Ok(Some("world".into())));
+        assert_eq!(backend.encoding(b"myset"), Some("listpack"));
+        for member in ["one", "two", "three", "four", "five"] {
+            assert_eq!(backend.sismember(b"myset", member.as_bytes()), Ok(1));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_bad_files() {
+        let backend = Backend::new();
+        let mut data = DUMP_RDB.to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        assert_eq!(load(&data, &backend), Err(RdbError::BadChecksum));
+        // a corrupted file leaves the dataset alone
+        assert_eq!(backend.key_count(), (0, 0));
+        assert_eq!(
+            load(&DUMP_RDB[..DUMP_RDB.len() - 4], &backend),
+            Err(RdbError::BadFormat)
+        );
+        assert_eq!(load(b"REDIX0011", &backend), Err(RdbError::BadSignature));
+        assert_eq!(
+            load(b"REDIS0012\xff", &backend),
+            Err(RdbError::UnsupportedVersion(12))
+        );
+    }
+
+    // a version 11 file without checksum holding `entries` after the header
+    fn rdb_file(entries: &[u8]) -> Vec<u8> {
+        let mut data = b"REDIS0011".to_vec();
+        data.extend_from_slice(entries);
+        data.put_u8(RDB_OPCODE_EOF);
+        data.put_u64_le(0);
+        data
+    }
+
+    #[test]
+    fn test_load_expires_and_databases() -> anyhow::Result<()> {
+        let mut entries = vec![RDB_OPCODE_SELECTDB, 0];
+        // expired a second ago
+        entries.put_u8(RDB_OPCODE_EXPIRETIME_MS);
+        entries.put_u64_le(now_ms() - 1000);
+        entries.extend_from_slice(b"\x00\x04gone\x01x");
+        // expires in an hour, idle for a minute
+        entries.put_u8(RDB_OPCODE_EXPIRETIME);
+        entries.put_u32_le((now_ms() / 1000 + 3600) as u32);
+        entries.extend_from_slice(&[RDB_OPCODE_IDLE, 60]);
+        entries.extend_from_slice(b"\x00\x04kept\x01x");
+        // an intset in another database
+        entries.extend_from_slice(&[RDB_OPCODE_SELECTDB, 1, 11]);
+        entries.extend_from_slice(b"\x04db1s\x0c\x02\x00\x00\x00\x02\x00\x00\x00\x01\x00\x02\x00");
+
+        let backend = Backend::new();
+        let stats = load(&rdb_file(&entries), &backend)?;
+        assert_eq!(
+            stats,
+            LoadStats {
+                keys: 1,
+                expired: 1,
+                skipped: 1,
+                functions: 0
+            }
+        );
+        assert!(!backend.exists(b"gone"));
+        assert!(backend.pttl(b"kept").unwrap() > 3_500_000);
+        assert!(backend.idle_time(b"kept").unwrap() >= 60_000);
+        assert!(!backend.exists(b"db1s"));
+
+        // the same intset in database 0
+        let backend = Backend::new();
+        load(&rdb_file(&entries[entries.len() - 19..]), &backend)?;
+        assert_eq!(
+            backend.get_value(b"db1s"),
+            Some(StoredValue::Set(SetValue::from_members(
+                ["1".into(), "2".into()],
+                &EncodingLimits::default()
+            )))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_quicklist() -> anyhow::Result<()> {
+        // a quicklist of a packed listpack node and a plain node
+        let listpack = b"\x0d\x00\x00\x00\x02\x00\x81a\x02\x81b\x02\xff";
+        let mut entries = vec![18, 4];
+        entries.extend_from_slice(b"list\x02\x02");
+        entries.push(listpack.len() as u8);
+        entries.extend_from_slice(listpack);
+        entries.extend_from_slice(b"\x01\x05plain");
+
+        let backend = Backend::new();
+        load(&rdb_file(&entries), &backend)?;
+        assert_eq!(
+            backend.lrange(b"list", 0, -1),
+            Ok(vec!["a".into(), "b".into(), "plain".into()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_functions() -> anyhow::Result<()> {
+        let code = "#!lua name=mylib\nredis.register_function('one', function() return 1 end)";
+        let mut entries = vec![RDB_OPCODE_FUNCTION2];
+        super::super::write_string(&mut entries, code.as_bytes());
+
+        let backend = Backend::new();
+        let stats = load(&rdb_file(&entries), &backend)?;
+        assert_eq!(stats.functions, 1);
+        assert_eq!(backend.scripts().function_codes(), vec![code.to_string()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use super::RdbError;
+
+// the most a compressed byte expands to: a 3 byte back reference copies up to 264 bytes
+const MAX_EXPANSION: usize = 88;
+
+/// Decompress an LZF block into exactly `len` bytes.
+///
+/// Each control byte is either a literal run (`000LLLLL`, copy `L + 1` bytes) or a
+/// back reference (`LLLooooo oooooooo`, copy `L + 2` bytes from `offset + 1` bytes back,
+/// with an extra length byte when `L` is 7).
+///
+/// `len` comes from the payload, so it is checked against what `input` can expand to before
+/// anything is allocated, and the output never grows past it.
+pub(crate) fn decompress(input: &[u8], len: usize) -> Result<Vec<u8>, RdbError> {
+    if len > input.len().saturating_mul(MAX_EXPANSION) {
+        return Err(RdbError::BadFormat);
+    }
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 1 << 5 {
+            let run = ctrl + 1;
+            if out.len() + run > len {
+                return Err(RdbError::BadFormat);
+            }
+            let literal = input.get(i..i + run).ok_or(RdbError::BadFormat)?;
+            out.extend_from_slice(literal);
+            i += run;
+        } else {
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or(RdbError::BadFormat)? as usize;
+                i += 1;
+            }
+            let low = *input.get(i).ok_or(RdbError::BadFormat)? as usize;
+            i += 1;
+            let back = ((ctrl & 0x1f) << 8) + low + 1;
+            if back > out.len() || out.len() + run + 2 > len {
+                return Err(RdbError::BadFormat);
+            }
+            // the reference may overlap the bytes it produces, so copy one at a time
+            let start = out.len() - back;
+            for n in 0..run + 2 {
+                out.push(out[start + n]);
+            }
+        }
+    }
+    if out.len() != len {
+        return Err(RdbError::BadFormat);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzf_decompress() -> anyhow::Result<()> {
+        // literal "abc", then a back reference of 6 bytes starting 3 bytes back
+        let input = [2, b'a', b'b', b'c', 4 << 5, 2];
+        assert_eq!(decompress(&input, 9)?, b"abcabcabc");
+        assert_eq!(decompress(&input, 8), Err(RdbError::BadFormat));
+        assert_eq!(decompress(&input, 1 << 40), Err(RdbError::BadFormat));
+        Ok(())
+    }
+}
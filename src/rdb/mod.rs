@@ -0,0 +1,585 @@
+/*
+   RDB value encoding, shared by DUMP/RESTORE payloads and snapshot files.
+
+   - dump payload: "<type><value><rdb-version: u16 le><crc64: u64 le>"
+   - function dump payload: "<0xf5><library-code>...<rdb-version: u16 le><crc64: u64 le>"
+   - length: "00|len(6)" | "01|len(14)" | "0x80 len(u32 be)" | "0x81 len(u64 be)"
+   - special string encoding: "11|enc(6)"
+       - 0: int8, 1: int16 le, 2: int32 le
+       - 3: lzf "<compressed-len><uncompressed-len><data>"
+*/
+mod container;
+mod load;
+mod lzf;
+mod save;
+
+use bytes::{BufMut, Bytes};
+use crc::{Crc, CRC_64_REDIS};
+use thiserror::Error;
+
+use crate::{EncodingLimits, HashValue, ListValue, SetValue, StoredValue, StringValue, ZSetValue};
+
+pub use load::{load, load_prefix, LoadStats};
+pub use save::{bgsave, save, save_if_needed, snapshot_file};
+pub(crate) use save::{write_file, Snapshot};
+
+pub const RDB_VERSION: u16 = 11;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_LIST_ZIPLIST: u8 = 10;
+const RDB_TYPE_SET_INTSET: u8 = 11;
+const RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
+const RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+const RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+const RDB_TYPE_HASH_LISTPACK: u8 = 16;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+const RDB_TYPE_SET_LISTPACK: u8 = 20;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+// how a quicklist node is stored
+const QUICKLIST_NODE_PLAIN: usize = 1;
+const QUICKLIST_NODE_PACKED: usize = 2;
+
+pub const RDB_SIGNATURE: &[u8] = b"REDIS";
+// the signature and a four digit version
+const RDB_HEADER_SIZE: usize = 9;
+
+const RDB_OPCODE_FUNCTION2: u8 = 245;
+const RDB_OPCODE_MODULE_AUX: u8 = 247;
+const RDB_OPCODE_IDLE: u8 = 248;
+const RDB_OPCODE_FREQ: u8 = 249;
+const RDB_OPCODE_AUX: u8 = 250;
+const RDB_OPCODE_RESIZEDB: u8 = 251;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
+const RDB_OPCODE_EXPIRETIME: u8 = 253;
+const RDB_OPCODE_SELECTDB: u8 = 254;
+const RDB_OPCODE_EOF: u8 = 255;
+
+const RDB_6BITLEN: u8 = 0;
+const RDB_14BITLEN: u8 = 1;
+const RDB_32BITLEN: u8 = 0x80;
+const RDB_64BITLEN: u8 = 0x81;
+const RDB_ENCVAL: u8 = 3;
+
+const RDB_ENC_INT8: u8 = 0;
+const RDB_ENC_INT16: u8 = 1;
+const RDB_ENC_INT32: u8 = 2;
+const RDB_ENC_LZF: u8 = 3;
+
+const CRC64: Crc<u64> = Crc::<u64>::new(&CRC_64_REDIS);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RdbError {
+    #[error("DUMP payload version or checksum are wrong")]
+    BadPayload,
+    #[error("Bad data format")]
+    BadFormat,
+    #[error("Unsupported value type: {0}")]
+    UnsupportedType(u8),
+    #[error("Wrong signature trying to load DB from file")]
+    BadSignature,
+    #[error("Can't handle RDB format version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Wrong RDB checksum")]
+    BadChecksum,
+    #[error("Failed loading library: {0}")]
+    Function(String),
+    #[error("Background save already in progress")]
+    SaveInProgress,
+    #[error("Error saving DB on disk: {0}")]
+    Io(String),
+}
+
+pub fn crc64(data: &[u8]) -> u64 {
+    CRC64.checksum(data)
+}
+
+/// Serialize a value the way DUMP does: type, value, RDB version and CRC64 footer.
+pub fn dump_payload(value: &StoredValue) -> Result<Vec<u8>, RdbError> {
+    let mut buf = Vec::with_capacity(64);
+    write_object(&mut buf, value)?;
+    buf.put_u16_le(RDB_VERSION);
+    let crc = crc64(&buf);
+    buf.put_u64_le(crc);
+    Ok(buf)
+}
+
+/// Verify the footer of a DUMP payload and deserialize the value it carries, encoded as `limits`
+/// allow.
+pub fn restore_payload(payload: &[u8], limits: &EncodingLimits) -> Result<StoredValue, RdbError> {
+    let mut reader = RdbReader::new(verify_footer(payload)?);
+    let ty = reader.read_u8()?;
+    let value = reader.read_object(ty, limits)?;
+    if !reader.is_empty() {
+        return Err(RdbError::BadFormat);
+    }
+    Ok(value)
+}
+
+/// Serialize function libraries the way FUNCTION DUMP does, one code string per library.
+pub fn dump_functions(libraries: &[String]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for code in libraries {
+        buf.put_u8(RDB_OPCODE_FUNCTION2);
+        write_string(&mut buf, code.as_bytes());
+    }
+    buf.put_u16_le(RDB_VERSION);
+    let crc = crc64(&buf);
+    buf.put_u64_le(crc);
+    buf
+}
+
+/// Verify the footer of a FUNCTION DUMP payload and return the code of every library.
+pub fn restore_functions(payload: &[u8]) -> Result<Vec<String>, RdbError> {
+    let mut reader = RdbReader::new(verify_footer(payload)?);
+    let mut libraries = Vec::new();
+    while !reader.is_empty() {
+        if reader.read_u8()? != RDB_OPCODE_FUNCTION2 {
+            return Err(RdbError::BadFormat);
+        }
+        let code = reader.read_string()?;
+        libraries.push(String::from_utf8(code).map_err(|_| RdbError::BadFormat)?);
+    }
+    Ok(libraries)
+}
+
+// checks the version and checksum, returning the payload without its footer
+fn verify_footer(payload: &[u8]) -> Result<&[u8], RdbError> {
+    if payload.len() < 10 {
+        return Err(RdbError::BadPayload);
+    }
+    let (body, footer) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+    if version > RDB_VERSION {
+        return Err(RdbError::BadPayload);
+    }
+    let crc = u64::from_le_bytes(footer.try_into().map_err(|_| RdbError::BadPayload)?);
+    if crc != crc64(body) {
+        return Err(RdbError::BadPayload);
+    }
+    Ok(&body[..body.len() - 2])
+}
+
+fn write_object(buf: &mut Vec<u8>, value: &StoredValue) -> Result<(), RdbError> {
+    buf.put_u8(object_type(value)?);
+    write_value(buf, value);
+    Ok(())
+}
+
+fn object_type(value: &StoredValue) -> Result<u8, RdbError> {
+    match value {
+        StoredValue::String(_) => Ok(RDB_TYPE_STRING),
+        StoredValue::Set(_) => Ok(RDB_TYPE_SET),
+        StoredValue::Hash(_) => Ok(RDB_TYPE_HASH),
+        StoredValue::List(_) => Ok(RDB_TYPE_LIST),
+        StoredValue::ZSet(_) => Ok(RDB_TYPE_ZSET_2),
+        StoredValue::Stream(_) => Err(RdbError::UnsupportedType(RDB_TYPE_STREAM_LISTPACKS_3)),
+    }
+}
+
+// the value of the type `object_type` gave
+fn write_value(buf: &mut Vec<u8>, value: &StoredValue) {
+    match value {
+        StoredValue::String(v) => write_string(buf, &v.to_bytes()),
+        StoredValue::Set(members) => {
+            write_length(buf, members.len() as u64);
+            for member in members.members() {
+                write_string(buf, &member);
+            }
+        }
+        StoredValue::Hash(fields) => {
+            write_length(buf, fields.len() as u64);
+            for (field, value) in fields.iter() {
+                write_string(buf, field);
+                write_string(buf, value);
+            }
+        }
+        StoredValue::List(values) => {
+            write_length(buf, values.len() as u64);
+            for value in values.iter() {
+                write_string(buf, value);
+            }
+        }
+        StoredValue::ZSet(members) => {
+            write_length(buf, members.len() as u64);
+            for (member, score) in members.iter() {
+                write_string(buf, member);
+                buf.put_f64_le(score);
+            }
+        }
+        // refused by object_type
+        StoredValue::Stream(_) => {}
+    }
+}
+
+fn write_length(buf: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        buf.put_u8((RDB_6BITLEN << 6) | len as u8);
+    } else if len < 1 << 14 {
+        buf.put_u8((RDB_14BITLEN << 6) | (len >> 8) as u8);
+        buf.put_u8(len as u8);
+    } else if len <= u32::MAX as u64 {
+        buf.put_u8(RDB_32BITLEN);
+        buf.put_u32(len as u32);
+    } else {
+        buf.put_u8(RDB_64BITLEN);
+        buf.put_u64(len);
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &[u8]) {
+    if let Some(v) = as_encodable_int(s) {
+        let enc = RDB_ENCVAL << 6;
+        if let Ok(v) = i8::try_from(v) {
+            buf.put_u8(enc | RDB_ENC_INT8);
+            buf.put_i8(v);
+        } else if let Ok(v) = i16::try_from(v) {
+            buf.put_u8(enc | RDB_ENC_INT16);
+            buf.put_i16_le(v);
+        } else {
+            buf.put_u8(enc | RDB_ENC_INT32);
+            buf.put_i32_le(v);
+        }
+        return;
+    }
+    write_length(buf, s.len() as u64);
+    buf.put_slice(s);
+}
+
+// only strings that format back to exactly the same bytes can be stored as integers
+fn as_encodable_int(s: &[u8]) -> Option<i32> {
+    if s.is_empty() || s.len() > 11 {
+        return None;
+    }
+    let v: i32 = std::str::from_utf8(s).ok()?.parse().ok()?;
+    (v.to_string().as_bytes() == s).then_some(v)
+}
+
+struct RdbReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RdbReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        RdbReader { buf, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn read_exact(&mut self, n: usize) -> Result<&'a [u8], RdbError> {
+        let end = self.pos.checked_add(n).ok_or(RdbError::BadFormat)?;
+        if end > self.buf.len() {
+            return Err(RdbError::BadFormat);
+        }
+        let data = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(data)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, RdbError> {
+        Ok(self.read_exact(1)?[0])
+    }
+
+    // returns the length and whether it is a special string encoding instead
+    fn read_length_with_encoding(&mut self) -> Result<(u64, bool), RdbError> {
+        let first = self.read_u8()?;
+        match first >> 6 {
+            RDB_6BITLEN => Ok(((first & 0x3f) as u64, false)),
+            RDB_14BITLEN => {
+                let next = self.read_u8()?;
+                Ok(((((first & 0x3f) as u64) << 8) | next as u64, false))
+            }
+            RDB_ENCVAL => Ok(((first & 0x3f) as u64, true)),
+            _ => match first {
+                RDB_32BITLEN => {
+                    let data = self.read_exact(4)?;
+                    Ok((u32::from_be_bytes(data.try_into().unwrap()) as u64, false))
+                }
+                RDB_64BITLEN => {
+                    let data = self.read_exact(8)?;
+                    Ok((u64::from_be_bytes(data.try_into().unwrap()), false))
+                }
+                _ => Err(RdbError::BadFormat),
+            },
+        }
+    }
+
+    fn read_length(&mut self) -> Result<usize, RdbError> {
+        match self.read_length_with_encoding()? {
+            (len, false) => usize::try_from(len).map_err(|_| RdbError::BadFormat),
+            (_, true) => Err(RdbError::BadFormat),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<Vec<u8>, RdbError> {
+        let (len, encoded) = self.read_length_with_encoding()?;
+        if !encoded {
+            let len = usize::try_from(len).map_err(|_| RdbError::BadFormat)?;
+            return Ok(self.read_exact(len)?.to_vec());
+        }
+        match len as u8 {
+            RDB_ENC_INT8 => Ok((self.read_u8()? as i8).to_string().into_bytes()),
+            RDB_ENC_INT16 => {
+                let data = self.read_exact(2)?;
+                Ok(i16::from_le_bytes(data.try_into().unwrap())
+                    .to_string()
+                    .into_bytes())
+            }
+            RDB_ENC_INT32 => {
+                let data = self.read_exact(4)?;
+                Ok(i32::from_le_bytes(data.try_into().unwrap())
+                    .to_string()
+                    .into_bytes())
+            }
+            RDB_ENC_LZF => {
+                let compressed_len = self.read_length()?;
+                let len = self.read_length()?;
+                let data = self.read_exact(compressed_len)?;
+                lzf::decompress(data, len)
+            }
+            _ => Err(RdbError::BadFormat),
+        }
+    }
+
+    fn read_u64_le(&mut self) -> Result<u64, RdbError> {
+        Ok(u64::from_le_bytes(self.read_exact(8)?.try_into().unwrap()))
+    }
+
+    // a score stored as a string, prefixed by its length or one of 253, 254 and 255 for nan,
+    // +inf and -inf
+    fn read_string_score(&mut self) -> Result<f64, RdbError> {
+        match self.read_u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_score(self.read_exact(len as usize)?),
+        }
+    }
+
+    fn read_object(&mut self, ty: u8, limits: &EncodingLimits) -> Result<StoredValue, RdbError> {
+        match ty {
+            RDB_TYPE_STRING => {
+                let value = self.read_string()?;
+                Ok(StoredValue::String(StringValue::new(value)))
+            }
+            RDB_TYPE_SET => {
+                let len = self.read_length()?;
+                let mut members = SetValue::default();
+                for _ in 0..len {
+                    members.insert(Bytes::from(self.read_string()?), limits);
+                }
+                Ok(StoredValue::Set(members))
+            }
+            RDB_TYPE_HASH => {
+                let len = self.read_length()?;
+                let mut fields = HashValue::default();
+                for _ in 0..len {
+                    let field = Bytes::from(self.read_string()?);
+                    let value = Bytes::from(self.read_string()?);
+                    fields.insert(field, value, limits);
+                }
+                Ok(StoredValue::Hash(fields))
+            }
+            RDB_TYPE_LIST => {
+                let len = self.read_length()?;
+                let mut values = Vec::with_capacity(len.min(1024));
+                for _ in 0..len {
+                    values.push(Bytes::from(self.read_string()?));
+                }
+                Ok(StoredValue::List(ListValue::from_values(values, limits)))
+            }
+            RDB_TYPE_ZSET_2 => {
+                let len = self.read_length()?;
+                let mut members = ZSetValue::default();
+                for _ in 0..len {
+                    let member = Bytes::from(self.read_string()?);
+                    let score = f64::from_le_bytes(self.read_exact(8)?.try_into().unwrap());
+                    members.insert(member, score, limits);
+                }
+                Ok(StoredValue::ZSet(members))
+            }
+            RDB_TYPE_ZSET => {
+                let len = self.read_length()?;
+                let mut members = ZSetValue::default();
+                for _ in 0..len {
+                    let member = Bytes::from(self.read_string()?);
+                    let score = self.read_string_score()?;
+                    members.insert(member, score, limits);
+                }
+                Ok(StoredValue::ZSet(members))
+            }
+            RDB_TYPE_SET_INTSET => {
+                let members = container::intset_members(&self.read_string()?)?;
+                let members = members.into_iter().map(|v| Bytes::from(v.to_string()));
+                Ok(StoredValue::Set(SetValue::from_members(members, limits)))
+            }
+            RDB_TYPE_SET_LISTPACK => {
+                let members = container::listpack_entries(&self.read_string()?)?;
+                Ok(StoredValue::Set(SetValue::from_members(members, limits)))
+            }
+            RDB_TYPE_HASH_ZIPLIST | RDB_TYPE_HASH_LISTPACK => {
+                let pairs = pairs(self.read_container(ty)?)?;
+                Ok(StoredValue::Hash(HashValue::from_pairs(pairs, limits)))
+            }
+            RDB_TYPE_ZSET_ZIPLIST | RDB_TYPE_ZSET_LISTPACK => {
+                let members = pairs(self.read_container(ty)?)?
+                    .into_iter()
+                    .map(|(member, score)| Ok((member, parse_score(&score)?)))
+                    .collect::<Result<Vec<_>, RdbError>>()?;
+                Ok(StoredValue::ZSet(ZSetValue::from_members(members, limits)))
+            }
+            RDB_TYPE_LIST_ZIPLIST => {
+                let values = container::ziplist_entries(&self.read_string()?)?;
+                Ok(StoredValue::List(ListValue::from_values(values, limits)))
+            }
+            RDB_TYPE_LIST_QUICKLIST | RDB_TYPE_LIST_QUICKLIST_2 => {
+                let nodes = self.read_length()?;
+                let mut values = Vec::new();
+                for _ in 0..nodes {
+                    let container = match ty {
+                        RDB_TYPE_LIST_QUICKLIST => QUICKLIST_NODE_PACKED,
+                        _ => self.read_length()?,
+                    };
+                    let node = self.read_string()?;
+                    match (ty, container) {
+                        (_, QUICKLIST_NODE_PLAIN) => values.push(Bytes::from(node)),
+                        (RDB_TYPE_LIST_QUICKLIST, _) => {
+                            values.extend(container::ziplist_entries(&node)?)
+                        }
+                        (_, QUICKLIST_NODE_PACKED) => {
+                            values.extend(container::listpack_entries(&node)?)
+                        }
+                        _ => return Err(RdbError::BadFormat),
+                    }
+                }
+                Ok(StoredValue::List(ListValue::from_values(values, limits)))
+            }
+            ty => Err(RdbError::UnsupportedType(ty)),
+        }
+    }
+
+    // the entries of the ziplist or listpack a value of type `ty` is stored as
+    fn read_container(&mut self, ty: u8) -> Result<Vec<Bytes>, RdbError> {
+        let buf = self.read_string()?;
+        match ty {
+            RDB_TYPE_HASH_ZIPLIST | RDB_TYPE_ZSET_ZIPLIST => container::ziplist_entries(&buf),
+            _ => container::listpack_entries(&buf),
+        }
+    }
+}
+
+// field and value, or member and score, alternate in a container
+fn pairs(entries: Vec<Bytes>) -> Result<Vec<(Bytes, Bytes)>, RdbError> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(RdbError::BadFormat);
+    }
+    let mut entries = entries.into_iter();
+    let mut pairs = Vec::with_capacity(entries.len() / 2);
+    while let (Some(a), Some(b)) = (entries.next(), entries.next()) {
+        pairs.push((a, b));
+    }
+    Ok(pairs)
+}
+
+fn parse_score(s: &[u8]) -> Result<f64, RdbError> {
+    std::str::from_utf8(s)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(RdbError::BadFormat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn test_restore_redis_payload() -> anyhow::Result<()> {
+        // DUMP of `SET mykey 10` on a redis server with RDB version 9
+        let payload = b"\x00\xc0\n\t\x00\xbem\x06\x89Z(\x00\n";
+        let value = restore_payload(payload, &EncodingLimits::default())?;
+        assert_eq!(value, StoredValue::String(StringValue::Int(10)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_restore_roundtrip() -> anyhow::Result<()> {
+        let limits = EncodingLimits::default();
+        let many = (0..1000).map(|i| Bytes::from(format!("m{}", i)));
+        let values = vec![
+            StoredValue::String("hello".into()),
+            StoredValue::String("-40000".into()),
+            StoredValue::String("x".repeat(20000).as_str().into()),
+            StoredValue::Set(SetValue::from_members(
+                ["a".into(), b"\xff\x00b"[..].into()],
+                &limits,
+            )),
+            StoredValue::Set(SetValue::from_members(["2".into(), "1".into()], &limits)),
+            StoredValue::Set(SetValue::from_members(many.clone(), &limits)),
+            StoredValue::Hash(HashValue::from_pairs(
+                [("name".into(), "simple".into())],
+                &limits,
+            )),
+            StoredValue::Hash(HashValue::from_pairs(many.clone().zip(many), &limits)),
+            StoredValue::List(ListValue::from_values(
+                ["x".into(), "y".into(), "x".into()],
+                &limits,
+            )),
+            StoredValue::ZSet(ZSetValue::from_members(
+                [("a".into(), 1.5), ("b".into(), -2.0)],
+                &limits,
+            )),
+        ];
+        for value in values {
+            let payload = dump_payload(&value)?;
+            assert_eq!(restore_payload(&payload, &limits)?, value);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_bad_payload() {
+        let mut payload = dump_payload(&StoredValue::String("hello".into())).unwrap();
+        let last = payload.len() - 1;
+        payload[last] ^= 0xff;
+        assert_eq!(
+            restore_payload(&payload, &EncodingLimits::default()),
+            Err(RdbError::BadPayload)
+        );
+        assert_eq!(
+            restore_payload(b"\x00", &EncodingLimits::default()),
+            Err(RdbError::BadPayload)
+        );
+    }
+
+    #[test]
+    fn test_dump_restore_functions() -> anyhow::Result<()> {
+        let libraries = vec![
+            "#!lua name=lib1\nredis.register_function('f1', function() return 1 end)".to_string(),
+            "#!lua name=lib2\n".to_string(),
+        ];
+        let payload = dump_functions(&libraries);
+        assert_eq!(restore_functions(&payload)?, libraries);
+        assert_eq!(
+            restore_functions(&dump_functions(&[]))?,
+            Vec::<String>::new()
+        );
+
+        let payload = dump_payload(&StoredValue::String("hello".into()))?;
+        assert_eq!(restore_functions(&payload), Err(RdbError::BadFormat));
+        Ok(())
+    }
+}
@@ -0,0 +1,413 @@
+/*
+   Writing snapshot files, in the format load reads back.
+
+   - aux fields: redis-ver, redis-bits, ctime, used-mem
+   - every function library, then the keys of database 0 with their expire and, as the
+     maxmemory policy needs it, their idle time or access counter
+   - the file is written next to the previous one and renamed over it once complete
+*/
+use std::{fs, io::Write, path::Path};
+
+use bytes::BufMut;
+use tracing::{info, warn};
+
+use crate::{now_ms, Backend, KeySnapshot, MaxMemoryPolicy, REDIS_VERSION};
+
+use super::{
+    crc64, object_type, write_length, write_string, write_value, RdbError, RDB_OPCODE_AUX,
+    RDB_OPCODE_EOF, RDB_OPCODE_EXPIRETIME_MS, RDB_OPCODE_FREQ, RDB_OPCODE_FUNCTION2,
+    RDB_OPCODE_IDLE, RDB_OPCODE_RESIZEDB, RDB_OPCODE_SELECTDB, RDB_SIGNATURE, RDB_VERSION,
+};
+
+// seconds to wait before trying a background save that failed again
+const BGSAVE_RETRY_DELAY: u64 = 5;
+
+/// Everything a snapshot file holds, copied before it is written.
+pub(crate) struct Snapshot {
+    keys: Vec<KeySnapshot>,
+    functions: Vec<String>,
+    policy: MaxMemoryPolicy,
+    used_memory: usize,
+    // the changes the snapshot covers
+    dirty: u64,
+}
+
+impl Snapshot {
+    pub(crate) fn take(backend: &Backend) -> Self {
+        let dirty = backend.dirty();
+        Snapshot {
+            keys: backend.snapshot(),
+            functions: backend.scripts().function_codes(),
+            policy: backend.memory_config().policy(),
+            used_memory: backend.used_memory(),
+            dirty,
+        }
+    }
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.used_memory / 2 + 64);
+        buf.put_slice(RDB_SIGNATURE);
+        buf.put_slice(format!("{:04}", RDB_VERSION).as_bytes());
+        let aux = [
+            ("redis-ver", REDIS_VERSION.to_string()),
+            ("redis-bits", usize::BITS.to_string()),
+            ("ctime", (now_ms() / 1000).to_string()),
+            ("used-mem", self.used_memory.to_string()),
+        ];
+        for (name, value) in aux {
+            buf.put_u8(RDB_OPCODE_AUX);
+            write_string(&mut buf, name.as_bytes());
+            write_string(&mut buf, value.as_bytes());
+        }
+        for code in &self.functions {
+            buf.put_u8(RDB_OPCODE_FUNCTION2);
+            write_string(&mut buf, code.as_bytes());
+        }
+
+        // streams have no RDB encoding yet, they are left out rather than failing the save
+        let keys = self
+            .keys
+            .iter()
+            .filter_map(|key| object_type(&key.value).ok().map(|ty| (ty, key)))
+            .collect::<Vec<_>>();
+        let skipped = self.keys.len() - keys.len();
+        if skipped > 0 {
+            warn!("{} stream keys were left out of the snapshot", skipped);
+        }
+        buf.put_u8(RDB_OPCODE_SELECTDB);
+        write_length(&mut buf, 0);
+        buf.put_u8(RDB_OPCODE_RESIZEDB);
+        write_length(&mut buf, keys.len() as u64);
+        let expires = keys.iter().filter(|(_, key)| key.expire_at.is_some());
+        write_length(&mut buf, expires.count() as u64);
+        for (ty, key) in keys {
+            if let Some(at) = key.expire_at {
+                buf.put_u8(RDB_OPCODE_EXPIRETIME_MS);
+                buf.put_u64_le(at);
+            }
+            match self.policy {
+                MaxMemoryPolicy::AllKeysLru | MaxMemoryPolicy::VolatileLru => {
+                    buf.put_u8(RDB_OPCODE_IDLE);
+                    write_length(&mut buf, key.idle_ms / 1000);
+                }
+                MaxMemoryPolicy::AllKeysLfu | MaxMemoryPolicy::VolatileLfu => {
+                    buf.put_u8(RDB_OPCODE_FREQ);
+                    buf.put_u8(key.freq);
+                }
+                _ => {}
+            }
+            buf.put_u8(ty);
+            write_string(&mut buf, &key.key);
+            write_value(&mut buf, &key.value);
+        }
+        buf.put_u8(RDB_OPCODE_EOF);
+        let crc = crc64(&buf);
+        buf.put_u64_le(crc);
+        buf
+    }
+}
+
+/// The whole dataset and every function library as an RDB file.
+pub fn snapshot_file(backend: &Backend) -> Vec<u8> {
+    Snapshot::take(backend).serialize()
+}
+
+/// Write the snapshot file right away, returning once it is on disk.
+pub fn save(backend: &Backend) -> Result<(), RdbError> {
+    if !backend.begin_save(false) {
+        return Err(RdbError::SaveInProgress);
+    }
+    let snapshot = Snapshot::take(backend);
+    let ret = write_file(&backend.save_config().path(), &snapshot.serialize());
+    backend.end_save(false, snapshot.dirty, ret.is_ok());
+    match &ret {
+        Ok(_) => info!("DB saved on disk"),
+        Err(e) => warn!("{}", e),
+    }
+    ret
+}
+
+/// Copy the dataset and write it to the snapshot file from another thread, so commands keep
+/// running while it is copied, serialized and written.
+///
+/// Without a fork to share pages with, values are shared with the copy instead: it is made under
+/// the exclusive side of the execution lock, holding off every command only while the keyspace
+/// is walked, and a value written before the save is done is cloned by that write. The memory
+/// taken again is that of the values written while the save runs, up to the whole dataset.
+pub fn bgsave(backend: &Backend) -> Result<(), RdbError> {
+    if !backend.begin_save(true) {
+        return Err(RdbError::SaveInProgress);
+    }
+    let backend = backend.clone();
+    std::thread::spawn(move || {
+        let snapshot = {
+            let _guard = backend.lock_exclusive();
+            Snapshot::take(&backend)
+        };
+        let ret = write_file(&backend.save_config().path(), &snapshot.serialize());
+        backend.end_save(true, snapshot.dirty, ret.is_ok());
+        match ret {
+            Ok(_) => info!("Background saving terminated with success"),
+            Err(e) => warn!("Background saving error: {}", e),
+        }
+    });
+    info!("Background saving started");
+    Ok(())
+}
+
+/// Start a background save if any of the `save` rules is met, returning whether one started.
+pub fn save_if_needed(backend: &Backend) -> bool {
+    if backend.save_in_progress() {
+        return false;
+    }
+    let now = now_ms() / 1000;
+    let dirty = backend.dirty();
+    let elapsed = now.saturating_sub(backend.lastsave());
+    // a failed save is only tried again after a while, rather than on every check
+    let may_retry = backend.last_bgsave_ok()
+        || now.saturating_sub(backend.last_bgsave_try()) >= BGSAVE_RETRY_DELAY;
+    let rule = backend
+        .save_config()
+        .rules()
+        .into_iter()
+        .find(|rule| dirty >= rule.changes && elapsed >= rule.seconds);
+    match rule {
+        Some(rule) if may_retry => {
+            info!(
+                "{} changes in {} seconds. Saving...",
+                rule.changes, rule.seconds
+            );
+            bgsave(backend).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Write to a temporary file in the same directory first, so the previous file stays whole
+/// until the new one replaces it. The temporary file is named after the target, as a snapshot
+/// and the append only file may be written at the same time.
+pub(crate) fn write_file(path: &Path, data: &[u8]) -> Result<(), RdbError> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!("temp-{}-{}", std::process::id(), name));
+    let ret = fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = ret {
+        let _ = fs::remove_file(&temp);
+        return Err(RdbError::Io(e.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, thread, time::Duration};
+
+    use crate::{SaveRule, StoredValue, Stream};
+
+    use super::*;
+
+    // an empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn wait_for_bgsave(backend: &Backend) {
+        for _ in 0..500 {
+            if !backend.save_in_progress() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("background save didn't finish");
+    }
+
+    #[test]
+    fn test_snapshot_unchanged_by_later_writes() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        backend.rpush("list".into(), &["a".into()])?;
+        backend.set("string".into(), "v".into());
+        let snapshot = Snapshot::take(&backend);
+        backend.rpush("list".into(), &["b".into()])?;
+        backend.set("string".into(), "w".into());
+        backend.set("new".into(), "v".into());
+
+        let loaded = Backend::new();
+        super::super::load(&snapshot.serialize(), &loaded)?;
+        assert_eq!(loaded.lrange(b"list", 0, -1), Ok(vec!["a".into()]));
+        assert_eq!(loaded.get(b"string"), Ok(Some("v".into())));
+        assert!(!loaded.exists(b"new"));
+        assert_eq!(
+            backend.lrange(b"list", 0, -1),
+            Ok(vec!["a".into(), "b".into()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        backend.set("string".into(), "hello".into());
+        backend.set("int".into(), "-12345".into());
+        backend.hset("hash".into(), "field".into(), "value".into())?;
+        backend.sadd("set".into(), &["a".into(), "1".into()])?;
+        backend.rpush("list".into(), &["x".into(), "y".into()])?;
+        backend.zadd(
+            "zset".into(),
+            &[("m".into(), 1.5), ("n".into(), f64::INFINITY)],
+        )?;
+        backend.pexpire_at(b"string", now_ms() + 60_000);
+        backend.set_value(
+            "stream".into(),
+            StoredValue::Stream(Stream::default()),
+            None,
+        );
+        let code = "#!lua name=mylib\nredis.register_function('one', function() return 1 end)";
+        backend
+            .scripts()
+            .function_load(code, false)
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+
+        let data = snapshot_file(&backend);
+        let loaded = Backend::new();
+        let stats = super::super::load(&data, &loaded)?;
+        assert_eq!(stats.keys, 6);
+        assert_eq!(stats.functions, 1);
+        for key in ["string", "int", "hash", "set", "list", "zset"] {
+            assert_eq!(
+                loaded.get_value(key.as_bytes()),
+                backend.get_value(key.as_bytes())
+            );
+        }
+        assert!(loaded.pttl(b"string").unwrap() > 50_000);
+        assert_eq!(loaded.pttl(b"int"), None);
+        assert!(!loaded.exists(b"stream"));
+        assert_eq!(loaded.scripts().function_codes(), vec![code.to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_access_metadata() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        backend.set("key".into(), "value".into());
+        backend.set_access(b"key", Some(120_000), None);
+
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::AllKeysLru);
+        let loaded = Backend::new();
+        super::super::load(&snapshot_file(&backend), &loaded)?;
+        assert!(loaded.idle_time(b"key").unwrap() >= 120_000);
+
+        backend
+            .memory_config()
+            .set_policy(MaxMemoryPolicy::AllKeysLfu);
+        // the counter decays while the key is idle, set it after the idle time
+        backend.set_access(b"key", None, Some(42));
+        let loaded = Backend::new();
+        super::super::load(&snapshot_file(&backend), &loaded)?;
+        assert_eq!(loaded.access_freq(b"key"), Some(42));
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_and_bgsave() -> anyhow::Result<()> {
+        let dir = temp_dir("save");
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.set("a".into(), "1".into());
+        assert!(backend.dirty() > 0);
+
+        save(&backend)?;
+        assert_eq!(backend.dirty(), 0);
+        assert!(backend.lastsave() >= now_ms() / 1000 - 1);
+        let loaded = Backend::new();
+        super::super::load(&fs::read(dir.join("dump.rdb"))?, &loaded)?;
+        assert_eq!(loaded.get(b"a"), Ok(Some("1".into())));
+
+        backend.set("b".into(), "2".into());
+        backend
+            .save_config()
+            .set_dbfilename("other.rdb".to_string());
+        bgsave(&backend)?;
+        wait_for_bgsave(&backend);
+        assert!(backend.last_bgsave_ok());
+        assert_eq!(backend.dirty(), 0);
+        let loaded = Backend::new();
+        let stats = super::super::load(&fs::read(dir.join("other.rdb"))?, &loaded)?;
+        assert_eq!(stats.keys, 2);
+        // only the snapshots are left, not the temporary files they were written to
+        assert_eq!(fs::read_dir(&dir)?.count(), 2);
+
+        assert!(backend.begin_save(true));
+        assert_eq!(bgsave(&backend), Err(RdbError::SaveInProgress));
+        assert_eq!(save(&backend), Err(RdbError::SaveInProgress));
+        backend.end_save(true, 0, true);
+
+        backend.save_config().set_dir(dir.join("missing"));
+        assert!(matches!(save(&backend), Err(RdbError::Io(_))));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_if_needed() -> anyhow::Result<()> {
+        let dir = temp_dir("rules");
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.save_config().set_rules(vec![SaveRule {
+            seconds: 0,
+            changes: 2,
+        }]);
+        backend.set("a".into(), "1".into());
+        assert!(!save_if_needed(&backend));
+        backend.set("b".into(), "2".into());
+        assert!(save_if_needed(&backend));
+        wait_for_bgsave(&backend);
+        assert!(dir.join("dump.rdb").exists());
+        assert_eq!(backend.dirty(), 0);
+
+        // a failed save waits a bit before it is tried again
+        backend.save_config().set_dir(dir.join("missing"));
+        backend.set("c".into(), "3".into());
+        backend.set("d".into(), "4".into());
+        assert!(save_if_needed(&backend));
+        wait_for_bgsave(&backend);
+        assert!(!backend.last_bgsave_ok());
+        assert!(!save_if_needed(&backend));
+
+        backend.save_config().set_rules(vec![]);
+        assert!(!save_if_needed(&backend));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bgsave_copies_in_background() -> anyhow::Result<()> {
+        let dir = temp_dir("background");
+        let backend = Backend::new();
+        backend.save_config().set_dir(dir.clone());
+        backend.set("a".into(), "1".into());
+
+        // starting the save doesn't wait for a running transaction, the copy does
+        let guard = backend.lock_exclusive();
+        bgsave(&backend)?;
+        backend.set("b".into(), "2".into());
+        drop(guard);
+        wait_for_bgsave(&backend);
+        assert!(backend.last_bgsave_ok());
+        let loaded = Backend::new();
+        let stats = super::super::load(&fs::read(dir.join("dump.rdb"))?, &loaded)?;
+        assert_eq!(stats.keys, 2);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -3,8 +3,8 @@ use std::ops::Deref;
 use bytes::{Buf, BytesMut};
 
 use super::{
-    calc_total_length, extract_fixed_data, parse_length, RespDecoder, RespEncoder, RespError,
-    RespFrame, CRLF_LEN,
+    calc_total_length, encode_line, extract_fixed_data, parse_length, RespDecoder, RespEncoder,
+    RespError, RespFrame, CRLF_LEN,
 };
 
 #[derive(Debug, PartialEq, Clone)]
@@ -31,19 +31,17 @@ impl From<Vec<RespFrame>> for RespArray {
 }
 
 impl RespEncoder for RespArray {
-    fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(self.len() * 32);
-        buf.extend_from_slice(&format!("*{}\r\n", self.len()).into_bytes());
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'*', self.len());
         for frame in &self.0 {
-            buf.extend_from_slice(&frame.encode());
+            frame.encode_to(dst);
         }
-        buf
     }
 }
 
 impl RespEncoder for RespNullArray {
-    fn encode(&self) -> Vec<u8> {
-        b"*-1\r\n".to_vec()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.extend_from_slice(b"*-1\r\n");
     }
 }
 
@@ -76,7 +74,6 @@ impl RespDecoder for RespArray {
         // move the *<number-of-elements>\r\n
 
         for _ in 0..len {
-            println!("data :::::{:?}", String::from_utf8(data.to_vec()));
             frames.push(RespFrame::decode(data)?);
         }
         Ok(RespArray::new(frames))
@@ -104,6 +101,19 @@ mod tests {
         assert_eq!(frame.encode(), b"*2\r\n$3\r\nset\r\n$5\r\nhello\r\n");
     }
 
+    #[test]
+    fn test_array_encode_to() {
+        let frame: RespFrame = RespArray::new(vec![
+            RespArray::new(vec![RespFrame::Integer(-12), BulkString::new("a").into()]).into(),
+            RespNullArray.into(),
+        ])
+        .into();
+        // appends after whatever is already buffered
+        let mut dst = BytesMut::from(&b"+OK\r\n"[..]);
+        frame.encode_to(&mut dst);
+        assert_eq!(&dst[..], b"+OK\r\n*2\r\n*2\r\n:-12\r\n$1\r\na\r\n*-1\r\n");
+    }
+
     #[test]
     fn test_null_array_encode() {
         let frame = RespNullArray;
@@ -120,6 +130,11 @@ mod tests {
         let mut buf = BytesMut::new();
         buf.extend_from_slice(b"*2\r\n$3\r\nset\r\n");
 
+        let frame = RespArray::decode(&mut buf);
+        assert_eq!(frame.unwrap_err(), RespError::NotComplete);
+
+        // the last element is cut in the middle of its payload
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nset\r\n$5\r\nhel"[..]);
         let frame = RespArray::decode(&mut buf);
         assert_eq!(frame.unwrap_err(), RespError::NotComplete);
         Ok(())
@@ -0,0 +1,99 @@
+use bytes::{Buf, BytesMut};
+
+use super::{
+    calc_total_length, encode_line, map::map_key, parse_length, RespDecoder, RespEncoder,
+    RespError, RespFrame, RespMap, CRLF_LEN,
+};
+
+/// Auxiliary data about a reply, sent as a map right before the reply itself.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RespAttribute {
+    pub(crate) attributes: RespMap,
+    pub(crate) frame: Box<RespFrame>,
+}
+
+impl RespAttribute {
+    pub fn new(attributes: RespMap, frame: RespFrame) -> Self {
+        RespAttribute {
+            attributes,
+            frame: Box::new(frame),
+        }
+    }
+    pub fn attributes(&self) -> &RespMap {
+        &self.attributes
+    }
+    pub fn frame(&self) -> &RespFrame {
+        &self.frame
+    }
+}
+
+impl RespEncoder for RespAttribute {
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'|', self.attributes.len());
+        for (key, value) in self.attributes.iter() {
+            key.encode_to(dst);
+            value.encode_to(dst);
+        }
+        self.frame.encode_to(dst);
+    }
+}
+
+//- attribute: "|<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>"
+impl RespDecoder for RespAttribute {
+    const PREFIX: &'static str = "|";
+    fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        let total_len = Self::expect_length(data)?;
+        if data.len() < total_len {
+            return Err(RespError::NotComplete);
+        }
+        let (end, len) = parse_length(data, Self::PREFIX)?;
+        data.advance(end + CRLF_LEN);
+        let mut attributes = RespMap::new();
+        for _ in 0..len {
+            let key = map_key(RespFrame::decode(data)?)?;
+            let value = RespFrame::decode(data)?;
+            attributes.insert(key, value);
+        }
+        let frame = RespFrame::decode(data)?;
+        Ok(RespAttribute::new(attributes, frame))
+    }
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let attributes_len = calc_total_length(buf, len, end, Self::PREFIX)?;
+        if buf.len() < attributes_len {
+            return Err(RespError::NotComplete);
+        }
+        Ok(attributes_len + RespFrame::expect_length(&buf[attributes_len..])?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribute_encode() {
+        let mut attributes = RespMap::new();
+        attributes.insert("ttl", RespFrame::Integer(3600));
+        let frame = RespAttribute::new(attributes, RespFrame::Integer(1));
+        assert_eq!(frame.encode(), b"|1\r\n$3\r\nttl\r\n:3600\r\n:1\r\n");
+    }
+    #[test]
+    fn test_attribute_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::from(&b"|1\r\n+ttl\r\n:3600\r\n$2\r\nhi\r\n"[..]);
+        let frame = RespAttribute::decode(&mut buf)?;
+        assert_eq!(
+            frame.attributes().get(&b"ttl"[..]),
+            Some(&RespFrame::Integer(3600))
+        );
+        assert_eq!(frame.frame(), &RespFrame::from(b"hi"));
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(&b"|1\r\n+ttl\r\n:3600\r\n"[..]);
+        assert_eq!(
+            RespAttribute::decode(&mut buf).unwrap_err(),
+            RespError::NotComplete
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use bytes::{Buf, BufMut, BytesMut};
+use num_bigint::BigInt;
+
+use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF, CRLF_LEN};
+
+impl RespEncoder for BigInt {
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.put_u8(b'(');
+        dst.extend_from_slice(self.to_str_radix(10).as_bytes());
+        dst.extend_from_slice(CRLF);
+    }
+}
+
+//- big number: "([+|-]<number>\r\n"
+impl RespDecoder for BigInt {
+    const PREFIX: &'static str = "(";
+    fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        let end = extract_simple_frame_data(data, Self::PREFIX, 1)?;
+        let number = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]);
+        let frame = number
+            .strip_prefix('+')
+            .unwrap_or(&number)
+            .parse::<BigInt>()
+            .map_err(|_| RespError::InvalidFrame(format!("invalid big number {}", number)))?;
+        data.advance(end + CRLF_LEN);
+        Ok(frame)
+    }
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let end = extract_simple_frame_data(buf, Self::PREFIX, 1)?;
+        Ok(end + CRLF_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_big_number_encode() {
+        let frame: BigInt = "-3492890328409238509324850943850943825024385"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            frame.encode(),
+            b"(-3492890328409238509324850943850943825024385\r\n"
+        );
+    }
+    #[test]
+    fn test_big_number_decode() -> anyhow::Result<()> {
+        let mut data = BytesMut::new();
+        data.extend_from_slice(b"(3492890328409238509324850943850943825024385\r\n");
+        let result = BigInt::decode(&mut data)?;
+        assert_eq!(
+            result,
+            "3492890328409238509324850943850943825024385".parse::<BigInt>()?
+        );
+
+        let mut data = BytesMut::from(&b"(+12\r\n"[..]);
+        assert_eq!(BigInt::decode(&mut data)?, BigInt::from(12));
+
+        let mut data = BytesMut::from(&b"(12a\r\n"[..]);
+        assert!(BigInt::decode(&mut data).is_err());
+        Ok(())
+    }
+}
@@ -3,8 +3,8 @@ use bytes::BytesMut;
 use super::{extract_fixed_data, RespDecoder, RespEncoder, RespError};
 
 impl RespEncoder for bool {
-    fn encode(&self) -> Vec<u8> {
-        format!("#{}\r\n", if *self { "t" } else { "f" }).into_bytes()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.extend_from_slice(if *self { b"#t\r\n" } else { b"#f\r\n" });
     }
 }
 //- boolean: "#<t|f>\r\n"
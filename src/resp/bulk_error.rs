@@ -0,0 +1,74 @@
+use std::ops::Deref;
+
+use bytes::{Buf, BytesMut};
+
+use super::{encode_line, parse_length, RespDecoder, RespEncoder, RespError, CRLF, CRLF_LEN};
+
+/// An error that may hold any bytes, newlines included.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BulkError(pub(crate) Vec<u8>);
+
+impl Deref for BulkError {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl BulkError {
+    pub fn new(s: impl Into<Vec<u8>>) -> Self {
+        BulkError(s.into())
+    }
+}
+
+impl RespEncoder for BulkError {
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'!', self.len());
+        dst.extend_from_slice(&self.0);
+        dst.extend_from_slice(CRLF);
+    }
+}
+
+//- bulk error: "!<length>\r\n<error>\r\n"
+impl RespDecoder for BulkError {
+    const PREFIX: &'static str = "!";
+    fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        let (end, len) = parse_length(data, Self::PREFIX)?;
+        if data.len() < end + CRLF_LEN + len + CRLF_LEN {
+            return Err(RespError::NotComplete);
+        }
+        data.advance(end + CRLF_LEN);
+        let data = data.split_to(len + CRLF_LEN);
+        Ok(BulkError::new(data[..len].to_vec()))
+    }
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        Ok(end + CRLF_LEN + len + CRLF_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_error_encode() {
+        let frame = BulkError::new("SYNTAX invalid\r\nsyntax");
+        assert_eq!(frame.encode(), b"!22\r\nSYNTAX invalid\r\nsyntax\r\n");
+    }
+    #[test]
+    fn test_bulk_error_decode() -> anyhow::Result<()> {
+        let mut data = BytesMut::new();
+        data.extend_from_slice(b"!21\r\nSYNTAX invalid syntax\r\n");
+        let result = BulkError::decode(&mut data)?;
+        assert_eq!(result, BulkError::new("SYNTAX invalid syntax"));
+
+        let mut data = BytesMut::from(&b"!21\r\nSYNTAX"[..]);
+        assert_eq!(
+            BulkError::decode(&mut data).unwrap_err(),
+            RespError::NotComplete
+        );
+        Ok(())
+    }
+}
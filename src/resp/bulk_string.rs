@@ -1,18 +1,19 @@
-use std::ops::Deref;
+use std::{borrow::Borrow, ops::Deref};
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 
-use crate::resp::{parse_length, CRLF_LEN};
+use crate::resp::{encode_line, parse_length, CRLF, CRLF_LEN};
 
 use super::{extract_fixed_data, RespDecoder, RespEncoder, RespError, RespFrame};
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct BulkString(pub(crate) Vec<u8>);
+/// Binary payload shared with the buffer it was read from, cloning it is a reference count bump.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct BulkString(pub(crate) Bytes);
 #[derive(Debug, PartialEq, Clone)]
 pub struct RespNullBulkString;
 
 impl Deref for BulkString {
-    type Target = Vec<u8>;
+    type Target = Bytes;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -21,7 +22,13 @@ impl Deref for BulkString {
 
 impl BulkString {
     pub fn new(s: impl Into<Vec<u8>>) -> Self {
-        BulkString(s.into())
+        BulkString(Bytes::from(s.into()))
+    }
+}
+
+impl From<Bytes> for BulkString {
+    fn from(v: Bytes) -> Self {
+        BulkString(v)
     }
 }
 
@@ -31,25 +38,47 @@ impl AsRef<[u8]> for BulkString {
     }
 }
 
+// maps keyed by bulk strings can be looked up by plain bytes
+impl Borrow<[u8]> for BulkString {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 impl<const N: usize> From<&[u8; N]> for RespFrame {
     fn from(v: &[u8; N]) -> Self {
-        BulkString(v.to_vec()).into()
+        BulkString::new(v.to_vec()).into()
     }
 }
 impl<const N: usize> From<&[u8; N]> for BulkString {
     fn from(v: &[u8; N]) -> Self {
-        BulkString(v.to_vec())
+        BulkString::new(v.to_vec())
     }
 }
 
 impl From<&String> for BulkString {
     fn from(v: &String) -> Self {
-        BulkString(v.as_bytes().to_vec())
+        BulkString::new(v.as_bytes())
+    }
+}
+impl From<String> for BulkString {
+    fn from(v: String) -> Self {
+        BulkString::new(v)
+    }
+}
+impl From<&str> for BulkString {
+    fn from(v: &str) -> Self {
+        BulkString::new(v)
+    }
+}
+impl From<&[u8]> for BulkString {
+    fn from(v: &[u8]) -> Self {
+        BulkString::new(v)
     }
 }
 
 impl RespEncoder for BulkString {
-    fn encode(&self) -> Vec<u8> {
+    fn encode_to(&self, dst: &mut BytesMut) {
         /*
         第一种写法：
         生成中间的 String，并且对整个数据（包括 \r\n）进行了一次性转换，这会导致在生成中间 String 的时候可能会进行一次额外的内存分配。
@@ -59,23 +88,19 @@ impl RespEncoder for BulkString {
 
         /*
         第二种写法：
-        •预先为 Vec 分配足够的容量，避免重复的内存分配和复制，提高了性能。
+        •直接写入输出缓冲区 dst，不生成中间的 Vec，也不需要再复制一次。
         •不进行不必要的 UTF-8 转换，直接将字节数组 self.0 添加到缓冲区，这使得它更高效，尤其是在数据量较大时。
         */
-        let mut buf = Vec::with_capacity(self.len() + 16);
-        if self.len() == 0 {
-            b"$-1\r\n".to_vec()
-        } else {
-            buf.extend_from_slice(&format!("${}\r\n", self.len()).into_bytes());
-            buf.extend_from_slice(&self.0);
-            buf.extend_from_slice(b"\r\n");
-            buf
-        }
+        // an empty payload is still a string, "$0\r\n\r\n", only RespNullBulkString is nil
+        encode_line(dst, b'$', self.len());
+        dst.extend_from_slice(&self.0);
+        dst.extend_from_slice(CRLF);
     }
 }
+
 impl RespEncoder for RespNullBulkString {
-    fn encode(&self) -> Vec<u8> {
-        b"$-1\r\n".to_vec()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.extend_from_slice(b"$-1\r\n");
     }
 }
 
@@ -91,13 +116,8 @@ impl RespDecoder for BulkString {
             return Err(RespError::NotComplete);
         }
         data.advance(end + CRLF_LEN);
-        // println!("data after advance :::::{:?}", data);
-        let data = data.split_to(len + CRLF_LEN);
-        println!(
-            "data after split_to :::::{:?}",
-            String::from_utf8(data.to_vec())
-        );
-        let frame = BulkString::new(data[..len].to_vec());
+        let frame = BulkString(data.split_to(len).freeze());
+        data.advance(CRLF_LEN);
         Ok(frame)
     }
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
@@ -114,7 +134,7 @@ impl RespDecoder for RespNullBulkString {
         Ok(RespNullBulkString)
     }
     fn expect_length(_buf: &[u8]) -> Result<usize, RespError> {
-        Ok(4)
+        Ok(5)
     }
 }
 
@@ -129,11 +149,30 @@ mod tests {
         assert_eq!(frame.encode(), b"$5\r\nhello\r\n");
     }
 
-    // #[test]
-    // fn test_null_bulk_string_encode() {
-    //     let frame = RespNullBulkString;
-    //     assert_eq!(frame.encode(), b"$-1\r\n");
-    // }
+    #[test]
+    fn test_null_bulk_string_encode() {
+        let frame = RespNullBulkString;
+        assert_eq!(frame.encode(), b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_empty_bulk_string() -> anyhow::Result<()> {
+        let frame = BulkString::new("");
+        assert_eq!(frame.encode(), b"$0\r\n\r\n");
+
+        let mut data = BytesMut::from(&b"$0\r\n\r\n$-1\r\n"[..]);
+        assert_eq!(RespFrame::decode(&mut data)?, frame.into());
+        assert_eq!(RespFrame::decode(&mut data)?, RespNullBulkString.into());
+        assert!(data.is_empty());
+        Ok(())
+    }
+    #[test]
+    fn test_bulk_string_encode_to() {
+        let mut dst = BytesMut::new();
+        BulkString::new(b"hello").encode_to(&mut dst);
+        assert_eq!(&dst[..], b"$5\r\nhello\r\n");
+    }
+
     #[test]
     fn test_bulk_string_decode() -> anyhow::Result<()> {
         let mut data = BytesMut::new();
@@ -1,18 +1,16 @@
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 
 use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF_LEN};
 
 impl RespEncoder for f64 {
-    fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(32);
+    fn encode_to(&self, dst: &mut BytesMut) {
         let ret = if self.abs() > 1e+8 || self.abs() < 1e-8 {
             format!(",{:+e}\r\n", self)
         } else {
             // let sign = if self < &0.0 { "" } else { "+" };
             format!(",{}\r\n", self)
         };
-        buf.extend_from_slice(&ret.into_bytes());
-        buf
+        dst.extend_from_slice(ret.as_bytes());
     }
 }
 
@@ -22,6 +20,7 @@ impl RespDecoder for f64 {
     fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
         let end = extract_simple_frame_data(data, Self::PREFIX, 1)?;
         let frame = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]).parse()?;
+        data.advance(end + CRLF_LEN);
         Ok(frame)
     }
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
@@ -1,9 +1,11 @@
 use bytes::BytesMut;
 use enum_dispatch::enum_dispatch;
+use num_bigint::BigInt;
 
 use super::{
-    BulkString, RespArray, RespDecoder, RespError, RespMap, RespNull, RespNullArray,
-    RespNullBulkString, RespSet, SimpleError, SimpleString,
+    BulkError, BulkString, RespArray, RespAttribute, RespDecoder, RespError, RespMap, RespNull,
+    RespNullArray, RespNullBulkString, RespPush, RespSet, SimpleError, SimpleString,
+    VerbatimString,
 };
 
 #[enum_dispatch(RespEncoder)]
@@ -21,6 +23,11 @@ pub enum RespFrame {
     Double(f64),
     Map(RespMap),
     Set(RespSet),
+    BigNumber(BigInt),
+    BulkError(BulkError),
+    VerbatimString(VerbatimString),
+    Attribute(RespAttribute),
+    Push(RespPush),
 }
 
 impl RespDecoder for RespFrame {
@@ -44,22 +51,24 @@ impl RespDecoder for RespFrame {
                 let frame = bool::decode(data)?;
                 Ok(frame.into())
             }
-            Some(b'$') => match RespNullBulkString::decode(data) {
-                Ok(frame) => Ok(frame.into()),
-                Err(RespError::NotComplete) => Err(RespError::NotComplete),
-                Err(_) => {
-                    let frame = BulkString::decode(data)?;
-                    Ok(frame.into())
-                }
-            },
-            Some(b'*') => match RespNullArray::decode(data) {
-                Ok(frame) => Ok(frame.into()),
-                Err(RespError::NotComplete) => Err(RespError::NotComplete),
-                Err(_) => {
-                    let frame = RespArray::decode(data)?;
-                    Ok(frame.into())
-                }
-            },
+            // look at the length before trying the null variants, a failed attempt formats the
+            // whole buffer into its error
+            Some(b'$') if data.starts_with(b"$-") => {
+                let frame = RespNullBulkString::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'$') => {
+                let frame = BulkString::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'*') if data.starts_with(b"*-") => {
+                let frame = RespNullArray::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'*') => {
+                let frame = RespArray::decode(data)?;
+                Ok(frame.into())
+            }
             Some(b',') => {
                 let frame = f64::decode(data)?;
                 Ok(frame.into())
@@ -76,6 +85,26 @@ impl RespDecoder for RespFrame {
                 let frame = RespNull::decode(data)?;
                 Ok(frame.into())
             }
+            Some(b'(') => {
+                let frame = BigInt::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'!') => {
+                let frame = BulkError::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'=') => {
+                let frame = VerbatimString::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'|') => {
+                let frame = RespAttribute::decode(data)?;
+                Ok(frame.into())
+            }
+            Some(b'>') => {
+                let frame = RespPush::decode(data)?;
+                Ok(frame.into())
+            }
             None => Err(RespError::NotComplete),
             _ => Err(RespError::InvalidFrameType(format!(
                 "Decode unknown frame type {:?}",
@@ -87,6 +116,7 @@ impl RespDecoder for RespFrame {
     fn expect_length(data: &[u8]) -> anyhow::Result<usize, RespError> {
         let mut iter = data.iter().peekable();
         match iter.peek() {
+            Some(b'$') if data.starts_with(b"$-") => RespNullBulkString::expect_length(data),
             Some(b'$') => BulkString::expect_length(data),
             Some(b'*') => RespArray::expect_length(data),
             Some(b'%') => RespMap::expect_length(data),
@@ -97,10 +127,69 @@ impl RespDecoder for RespFrame {
             Some(b'+') => SimpleString::expect_length(data),
             Some(b'-') => SimpleError::expect_length(data),
             Some(b'_') => RespNull::expect_length(data),
+            Some(b'(') => BigInt::expect_length(data),
+            Some(b'!') => BulkError::expect_length(data),
+            Some(b'=') => VerbatimString::expect_length(data),
+            Some(b'|') => RespAttribute::expect_length(data),
+            Some(b'>') => RespPush::expect_length(data),
             _ => Err(RespError::NotComplete),
         }
     }
 }
+impl RespFrame {
+    /// Rewrite the RESP3 only types for a RESP2 client: maps become flat arrays of key and value,
+    /// sets and pushes become arrays, booleans integers, doubles, big numbers and verbatim strings
+    /// bulk strings, bulk errors simple errors and null a null bulk string. Attributes are dropped
+    /// in favor of the reply they annotate.
+    pub fn into_resp2(self) -> RespFrame {
+        match self {
+            RespFrame::Array(frames) => {
+                let frames = frames
+                    .0
+                    .into_iter()
+                    .map(RespFrame::into_resp2)
+                    .collect::<Vec<_>>();
+                RespArray::new(frames).into()
+            }
+            RespFrame::Set(frames) => {
+                let frames = frames
+                    .0
+                    .into_iter()
+                    .map(RespFrame::into_resp2)
+                    .collect::<Vec<_>>();
+                RespArray::new(frames).into()
+            }
+            RespFrame::Push(frames) => {
+                let frames = frames
+                    .0
+                    .into_iter()
+                    .map(RespFrame::into_resp2)
+                    .collect::<Vec<_>>();
+                RespArray::new(frames).into()
+            }
+            RespFrame::Map(map) => {
+                let mut frames = Vec::with_capacity(map.len() * 2);
+                for (key, value) in map.0 {
+                    frames.push(key.into());
+                    frames.push(value.into_resp2());
+                }
+                RespArray::new(frames).into()
+            }
+            RespFrame::Null(_) => RespNullBulkString.into(),
+            RespFrame::Boolean(b) => RespFrame::Integer(b as i64),
+            RespFrame::Double(d) => BulkString::new(d.to_string()).into(),
+            RespFrame::BigNumber(n) => BulkString::new(n.to_string()).into(),
+            RespFrame::VerbatimString(s) => BulkString::new(s.data).into(),
+            // simple errors can't hold newlines
+            RespFrame::BulkError(e) => {
+                SimpleError::new(String::from_utf8_lossy(&e).replace(['\r', '\n'], " ")).into()
+            }
+            RespFrame::Attribute(attribute) => attribute.frame.into_resp2(),
+            frame => frame,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::RespEncoder;
@@ -111,4 +200,33 @@ mod tests {
         let frame = RespNull;
         assert_eq!(frame.encode(), b"_\r\n");
     }
+    #[test]
+    fn test_resp3_frame_decode() -> anyhow::Result<()> {
+        let mut data = BytesMut::from(&b"(12\r\n!3\r\nERR\r\n=7\r\ntxt:abc\r\n>1\r\n:1\r\n"[..]);
+        assert_eq!(RespFrame::decode(&mut data)?, BigInt::from(12).into());
+        assert_eq!(RespFrame::decode(&mut data)?, BulkError::new("ERR").into());
+        assert_eq!(
+            RespFrame::decode(&mut data)?,
+            VerbatimString::new(*b"txt", "abc").into()
+        );
+        assert_eq!(
+            RespFrame::decode(&mut data)?,
+            RespPush::new([RespFrame::Integer(1)]).into()
+        );
+        assert!(data.is_empty());
+        Ok(())
+    }
+    #[test]
+    fn test_into_resp2() {
+        let mut map = RespMap::new();
+        map.insert("ok", true.into());
+        map.insert("score", 1.5.into());
+        let mut set = RespSet::new();
+        set.insert(RespFrame::Null(RespNull));
+        let frame: RespFrame = RespArray::new([map.into(), set.into()]).into();
+        assert_eq!(
+            frame.into_resp2().encode(),
+            b"*2\r\n*4\r\n$2\r\nok\r\n:1\r\n$5\r\nscore\r\n$3\r\n1.5\r\n*1\r\n$-1\r\n"
+        );
+    }
 }
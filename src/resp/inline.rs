@@ -0,0 +1,137 @@
+//! The inline form of a request, a plain text line as typed into telnet or netcat.
+
+/// Split an inline request into its arguments the way redis does: on whitespace, with double
+/// quoted arguments understanding `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH` escapes and single
+/// quoted ones only `\'`. `None` when a quote is not closed or is followed by something else
+/// than whitespace.
+pub(crate) fn split_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Some(args);
+        }
+        let mut arg = Vec::new();
+        match line[i] {
+            b'"' => {
+                i += 1;
+                loop {
+                    match *line.get(i)? {
+                        b'\\'
+                            if i + 3 < line.len()
+                                && line[i + 1] == b'x'
+                                && line[i + 2].is_ascii_hexdigit()
+                                && line[i + 3].is_ascii_hexdigit() =>
+                        {
+                            arg.push(hex(line[i + 2]) << 4 | hex(line[i + 3]));
+                            i += 4;
+                        }
+                        b'\\' if i + 1 < line.len() => {
+                            arg.push(match line[i + 1] {
+                                b'n' => b'\n',
+                                b'r' => b'\r',
+                                b't' => b'\t',
+                                b'b' => 0x08,
+                                b'a' => 0x07,
+                                c => c,
+                            });
+                            i += 2;
+                        }
+                        b'"' => {
+                            i += 1;
+                            break;
+                        }
+                        c => {
+                            arg.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                closing_quote_ends_arg(line, i)?;
+            }
+            b'\'' => {
+                i += 1;
+                loop {
+                    match *line.get(i)? {
+                        b'\\' if line.get(i + 1) == Some(&b'\'') => {
+                            arg.push(b'\'');
+                            i += 2;
+                        }
+                        b'\'' => {
+                            i += 1;
+                            break;
+                        }
+                        c => {
+                            arg.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                closing_quote_ends_arg(line, i)?;
+            }
+            _ => {
+                while i < line.len() && !line[i].is_ascii_whitespace() {
+                    arg.push(line[i]);
+                    i += 1;
+                }
+            }
+        }
+        args.push(arg);
+    }
+}
+
+// `"foo"bar` is as unbalanced as a quote that is never closed
+fn closing_quote_ends_arg(line: &[u8], i: usize) -> Option<()> {
+    match line.get(i) {
+        Some(c) if !c.is_ascii_whitespace() => None,
+        _ => Some(()),
+    }
+}
+
+fn hex(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        _ => c - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        let args = |line: &[u8]| split_args(line).map(|args| args.concat());
+        assert_eq!(
+            split_args(b"  SET a\tb  "),
+            Some(vec![b"SET".to_vec(), b"a".to_vec(), b"b".to_vec()])
+        );
+        assert_eq!(split_args(b"   "), Some(vec![]));
+        assert_eq!(
+            split_args(br#"set "hello world" 'it\'s'"#),
+            Some(vec![
+                b"set".to_vec(),
+                b"hello world".to_vec(),
+                b"it's".to_vec()
+            ])
+        );
+        assert_eq!(
+            args(br#""a\r\n\t\x41\x4a\"\\\q""#),
+            Some(b"a\r\n\tAJ\"\\q".to_vec())
+        );
+        assert_eq!(
+            args(br#"'no \n escapes'"#),
+            Some(br"no \n escapes".to_vec())
+        );
+        assert_eq!(split_args(br#""\xzz""#), Some(vec![b"xzz".to_vec()]));
+        assert_eq!(split_args(b"\"\""), Some(vec![vec![]]));
+
+        assert_eq!(split_args(b"get \"unterminated"), None);
+        assert_eq!(split_args(b"get 'unterminated"), None);
+        assert_eq!(split_args(b"get \"a\"b"), None);
+    }
+}
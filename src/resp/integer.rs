@@ -1,10 +1,12 @@
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 
-use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF_LEN};
+use super::{
+    encode_line, extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF_LEN,
+};
 
 impl RespEncoder for i64 {
-    fn encode(&self) -> Vec<u8> {
-        format!(":{}\r\n", self).into_bytes()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b':', *self)
     }
 }
 //integer: ":[<+|->]<value>\r\n"
@@ -13,6 +15,7 @@ impl RespDecoder for i64 {
     fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
         let end = extract_simple_frame_data(data, Self::PREFIX, 1)?;
         let frame = String::from_utf8_lossy(&data[Self::PREFIX.len()..end]).parse()?;
+        data.advance(end + CRLF_LEN);
         Ok(frame)
     }
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
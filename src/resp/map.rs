@@ -3,14 +3,15 @@ use std::{collections::BTreeMap, ops::Deref};
 use bytes::{Buf, BytesMut};
 
 use super::{
-    calc_total_length, parse_length, RespDecoder, RespEncoder, RespError, RespFrame, SimpleString,
-    CRLF_LEN,
+    calc_total_length, encode_line, parse_length, BulkString, RespDecoder, RespEncoder, RespError,
+    RespFrame, CRLF_LEN,
 };
 
+/// Keys are binary safe strings, sent as bulk strings.
 #[derive(Debug, PartialEq, Clone)]
-pub struct RespMap(pub(crate) BTreeMap<String, RespFrame>);
+pub struct RespMap(pub(crate) BTreeMap<BulkString, RespFrame>);
 impl Deref for RespMap {
-    type Target = BTreeMap<String, RespFrame>;
+    type Target = BTreeMap<BulkString, RespFrame>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -20,7 +21,7 @@ impl RespMap {
     pub fn new() -> Self {
         RespMap(BTreeMap::new())
     }
-    pub fn insert(&mut self, key: impl Into<String>, value: RespFrame) {
+    pub fn insert(&mut self, key: impl Into<BulkString>, value: RespFrame) {
         self.0.insert(key.into(), value);
     }
 }
@@ -30,16 +31,12 @@ impl Default for RespMap {
     }
 }
 impl RespEncoder for RespMap {
-    fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(self.len() * 32);
-        buf.extend_from_slice(&format!("%{}\r\n", self.len()).into_bytes());
-        // let mut vec:Vec<_>=self.0.iter().collect();
-        // vec.sort_by(|a,b|a.0.cmp(b.0));
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'%', self.len());
         for (key, value) in &self.0 {
-            buf.extend_from_slice(&SimpleString::new(key).encode());
-            buf.extend_from_slice(&value.encode());
+            key.encode_to(dst);
+            value.encode_to(dst);
         }
-        buf
     }
 }
 
@@ -55,9 +52,9 @@ impl RespDecoder for RespMap {
         data.advance(end + CRLF_LEN);
         let mut map = RespMap::new();
         for _ in 0..len {
-            let key = SimpleString::decode(data)?;
+            let key = map_key(RespFrame::decode(data)?)?;
             let value = RespFrame::decode(data)?;
-            map.insert(key.0, value);
+            map.insert(key, value);
         }
         Ok(map)
     }
@@ -68,6 +65,18 @@ impl RespDecoder for RespMap {
     }
 }
 
+/// Map and attribute keys may be sent as simple or bulk strings.
+pub(crate) fn map_key(frame: RespFrame) -> Result<BulkString, RespError> {
+    match frame {
+        RespFrame::SimpleString(s) => Ok(s.0.into()),
+        RespFrame::BulkString(s) => Ok(s),
+        frame => Err(RespError::InvalidFrame(format!(
+            "map key must be a string, got {:?}",
+            frame
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::BulkString;
@@ -83,7 +92,21 @@ mod tests {
         let frame = map;
         assert_eq!(
             frame.encode(),
-            b"%2\r\n+age\r\n:18\r\n+name\r\n$8\r\nzhangsan\r\n"
+            b"%2\r\n$3\r\nage\r\n:18\r\n$4\r\nname\r\n$8\r\nzhangsan\r\n"
         );
     }
+
+    #[test]
+    fn test_map_binary_keys() -> anyhow::Result<()> {
+        let mut map = RespMap::new();
+        map.insert(&b"\xff\x00k"[..], RespFrame::Integer(1));
+        let mut buf = BytesMut::from(&map.encode()[..]);
+        assert_eq!(&buf[..], b"%1\r\n$3\r\n\xff\x00k\r\n:1\r\n");
+        assert_eq!(RespMap::decode(&mut buf)?, map);
+
+        let mut buf = BytesMut::from(&b"%1\r\n+k\r\n:1\r\n"[..]);
+        let map = RespMap::decode(&mut buf)?;
+        assert_eq!(map.get(&b"k"[..]), Some(&RespFrame::Integer(1)));
+        Ok(())
+    }
 }
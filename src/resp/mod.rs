@@ -14,31 +14,47 @@
    - big number: "([+|-]<number>\r\n"
    - map: "%<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>"
    - set: "~<number-of-elements>\r\n<element-1>...<element-n>"
+   - verbatim string: "=<length>\r\n<format>:<data>\r\n"
+   - attribute: "|<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>"
+   - push: "><number-of-elements>\r\n<element-1>...<element-n>"
 */
 mod array;
+mod attribute;
+mod big_number;
 mod bool;
+mod bulk_error;
 mod bulk_string;
 mod double;
 mod frame;
+mod inline;
 mod integer;
 mod map;
 mod null;
+mod parser;
+mod push;
 mod set;
 mod simple_error;
 mod simple_string;
+mod verbatim_string;
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, BufMut, BytesMut};
 use enum_dispatch::enum_dispatch;
+use num_bigint::BigInt;
 
 pub use self::{
     array::{RespArray, RespNullArray},
+    attribute::RespAttribute,
+    bulk_error::BulkError,
     bulk_string::{BulkString, RespNullBulkString},
     frame::RespFrame,
     map::RespMap,
     null::RespNull,
+    parser::{ParserLimits, RespParser},
+    push::RespPush,
     set::RespSet,
     simple_error::SimpleError,
     simple_string::SimpleString,
+    verbatim_string::VerbatimString,
 };
 
 use thiserror::Error;
@@ -47,7 +63,21 @@ const CRLF: &[u8] = b"\r\n";
 const CRLF_LEN: usize = CRLF.len();
 #[enum_dispatch]
 pub trait RespEncoder {
-    fn encode(&self) -> Vec<u8>;
+    /// Appends the encoded frame to `dst`, aggregates write their elements in place.
+    fn encode_to(&self, dst: &mut BytesMut);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        self.encode_to(&mut buf);
+        buf.to_vec()
+    }
+}
+
+/// Writes a `<prefix><number>\r\n` line, the header of every length-prefixed frame.
+fn encode_line<N: itoa::Integer>(dst: &mut BytesMut, prefix: u8, n: N) {
+    dst.put_u8(prefix);
+    dst.extend_from_slice(itoa::Buffer::new().format(n).as_bytes());
+    dst.extend_from_slice(CRLF);
 }
 
 pub trait RespDecoder: Sized {
@@ -72,6 +102,8 @@ pub enum RespError {
     ParseFloatError(#[from] std::num::ParseFloatError),
     #[error("CommandError: {0}")]
     InvalidCommand(String),
+    #[error("Protocol error: {0}")]
+    Protocol(String),
 }
 
 fn extract_simple_frame_data(
@@ -140,23 +172,23 @@ fn calc_total_length(
     let mut data = &buf[total..];
     // println!("calc_total_length: {:?}", String::from_utf8(data.to_vec()));
     match prefix {
-        "*" | "~" => {
+        "*" | "~" | ">" => {
             for _ in 0..len {
                 // println!("for start : {:?}", String::from_utf8(data.to_vec()));
                 let len = RespFrame::expect_length(data)?;
-                data = &data[len..];
+                data = data.get(len..).ok_or(RespError::NotComplete)?;
                 total += len;
             }
             Ok(total)
         }
-        "%" => {
+        "%" | "|" => {
             for _ in 0..len {
-                let len1 = SimpleString::expect_length(data)?;
-                data = &data[len1..];
+                let len1 = RespFrame::expect_length(data)?;
+                data = data.get(len1..).ok_or(RespError::NotComplete)?;
                 total += len1;
 
                 let len2 = RespFrame::expect_length(data)?;
-                data = &data[len2..];
+                data = data.get(len2..).ok_or(RespError::NotComplete)?;
                 total += len2;
             }
             Ok(total)
@@ -17,8 +17,8 @@ impl Default for RespNull {
 }
 
 impl RespEncoder for RespNull {
-    fn encode(&self) -> Vec<u8> {
-        b"_\r\n".to_vec()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.extend_from_slice(b"_\r\n");
     }
 }
 //- null: "_\r\n"
@@ -0,0 +1,525 @@
+use std::collections::BTreeMap;
+
+use bytes::{Buf, Bytes, BytesMut};
+use num_bigint::BigInt;
+
+use super::{
+    inline::split_args, map::map_key, BulkError, BulkString, RespArray, RespAttribute, RespError,
+    RespFrame, RespMap, RespNull, RespNullArray, RespNullBulkString, RespPush, RespSet,
+    SimpleError, SimpleString, VerbatimString, CRLF_LEN,
+};
+
+// bulk payloads at least this long are not copied out of the read buffer
+const ZERO_COPY_MIN_LEN: usize = 16 * 1024;
+// a request starting with anything else is an inline command
+const RESP_TYPES: &[u8] = b"+-:$*_#,(!=%~>|";
+// longest inline command, the query buffer limit is for proper RESP requests
+const INLINE_MAX_LEN: usize = 64 * 1024;
+
+/// How much a client may send before the parser gives up on it, so that a malformed or hostile
+/// stream can neither make the server buffer without bound nor nest frames without end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    /// Longest bulk payload, `proto-max-bulk-len`.
+    pub max_bulk_len: usize,
+    /// Most elements in a single aggregate.
+    pub max_multibulk_len: usize,
+    /// Most aggregates nested inside each other.
+    pub max_depth: usize,
+    /// Most bytes buffered for a frame that is not complete yet, `client-query-buffer-limit`.
+    pub max_query_buffer: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: i32::MAX as usize,
+            max_depth: 64,
+            max_query_buffer: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Incremental RESP decoder for a byte stream.
+///
+/// Unlike `RespFrame::decode`, which needs the whole frame buffered and walks it once to measure
+/// it and again to decode it, the parser consumes every complete line or bulk payload as soon as
+/// it arrives and keeps the aggregates under construction on a stack, so a partial read resumes
+/// where the previous one stopped and every byte is looked at once.
+#[derive(Debug, Default)]
+pub struct RespParser {
+    // aggregates waiting for more elements, innermost last
+    stack: Vec<Aggregate>,
+    // header of a bulk frame whose payload is not complete yet
+    bulk: Option<(u8, usize)>,
+    // how much of the buffer is already known not to hold a CRLF
+    scanned: usize,
+    // bytes consumed by the frame being parsed so far
+    pending: usize,
+    limits: ParserLimits,
+}
+
+#[derive(Debug)]
+struct Aggregate {
+    kind: u8,
+    expected: usize,
+    frames: Vec<RespFrame>,
+}
+
+// a complete frame, or the header of an aggregate expecting that many elements
+enum Token {
+    Frame(RespFrame),
+    Aggregate(u8, usize),
+}
+
+impl RespParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: ParserLimits) {
+        self.limits = limits;
+    }
+
+    /// Consume as much of `src` as possible, returning the next complete frame if there is one.
+    /// Whatever was consumed without completing a frame is kept by the parser, so the next call
+    /// only has to look at the bytes received in the meantime.
+    pub fn parse(&mut self, src: &mut BytesMut) -> Result<Option<RespFrame>, RespError> {
+        loop {
+            let frame = match self.next_token(src)? {
+                None => {
+                    if self.pending + src.len() > self.limits.max_query_buffer {
+                        return Err(RespError::Protocol(
+                            "client query buffer limit reached".to_string(),
+                        ));
+                    }
+                    return Ok(None);
+                }
+                Some(Token::Frame(frame)) => frame,
+                Some(Token::Aggregate(kind, 0)) => finish(kind, Vec::new())?,
+                Some(Token::Aggregate(kind, expected)) => {
+                    if self.stack.len() >= self.limits.max_depth {
+                        return Err(RespError::Protocol(
+                            "too many nested aggregates".to_string(),
+                        ));
+                    }
+                    self.stack.push(Aggregate {
+                        kind,
+                        expected,
+                        frames: Vec::with_capacity(expected.min(1024)),
+                    });
+                    continue;
+                }
+            };
+            if let Some(frame) = self.complete(frame)? {
+                self.pending = 0;
+                return Ok(Some(frame));
+            }
+        }
+    }
+
+    // add a complete frame to the innermost aggregate, closing every aggregate it completes
+    fn complete(&mut self, mut frame: RespFrame) -> Result<Option<RespFrame>, RespError> {
+        loop {
+            let Some(aggregate) = self.stack.last_mut() else {
+                return Ok(Some(frame));
+            };
+            aggregate.frames.push(frame);
+            if aggregate.frames.len() < aggregate.expected {
+                return Ok(None);
+            }
+            let aggregate = self.stack.pop().expect("aggregate is on the stack");
+            frame = finish(aggregate.kind, aggregate.frames)?;
+        }
+    }
+
+    fn next_token(&mut self, src: &mut BytesMut) -> Result<Option<Token>, RespError> {
+        if let Some((kind, len)) = self.bulk {
+            return self.bulk_payload(src, kind, len);
+        }
+        if self.stack.is_empty() && src.first().is_some_and(|c| !RESP_TYPES.contains(c)) {
+            return self.inline(src);
+        }
+        let Some(end) = self.find_line(src) else {
+            return Ok(None);
+        };
+        if end == 0 {
+            return Err(RespError::InvalidFrame("empty line".to_string()));
+        }
+        let line = src.split_to(end + CRLF_LEN);
+        self.pending += line.len();
+        let (kind, body) = (line[0], &line[1..end]);
+        let token = match kind {
+            b'+' => Token::Frame(SimpleString::new(utf8(body)?).into()),
+            b'-' => Token::Frame(SimpleError::new(utf8(body)?).into()),
+            b':' => Token::Frame(RespFrame::Integer(utf8(body)?.parse()?)),
+            b',' => Token::Frame(RespFrame::Double(utf8(body)?.parse()?)),
+            b'(' => {
+                let number = utf8(body)?;
+                let number = number
+                    .strip_prefix('+')
+                    .unwrap_or(number)
+                    .parse::<BigInt>()
+                    .map_err(|_| {
+                        RespError::InvalidFrame(format!("invalid big number {}", number))
+                    })?;
+                Token::Frame(number.into())
+            }
+            b'#' => match body {
+                b"t" => Token::Frame(true.into()),
+                b"f" => Token::Frame(false.into()),
+                _ => {
+                    return Err(RespError::InvalidFrame(format!(
+                        "invalid boolean {:?}",
+                        body
+                    )))
+                }
+            },
+            b'_' if body.is_empty() => Token::Frame(RespNull.into()),
+            b'$' | b'!' | b'=' => match length(body)? {
+                None if kind == b'$' => Token::Frame(RespNullBulkString.into()),
+                None => return Err(RespError::InvalidFrameLength(-1)),
+                Some(len) if len > self.limits.max_bulk_len => {
+                    return Err(RespError::Protocol("invalid bulk length".to_string()))
+                }
+                Some(len) => return self.bulk_payload(src, kind, len),
+            },
+            b'*' | b'~' | b'>' => match length(body)? {
+                None if kind == b'*' => Token::Frame(RespNullArray.into()),
+                None => return Err(RespError::InvalidFrameLength(-1)),
+                Some(len) => Token::Aggregate(kind, self.multibulk_len(len)?),
+            },
+            // maps and attributes are made of key and value pairs, attributes are followed by
+            // the reply they annotate
+            b'%' | b'|' => match length(body)? {
+                Some(len) => {
+                    let expected = self.multibulk_len(len.saturating_mul(2))?;
+                    Token::Aggregate(kind, expected + (kind == b'|') as usize)
+                }
+                None => return Err(RespError::InvalidFrameLength(-1)),
+            },
+            _ => {
+                return Err(RespError::InvalidFrameType(format!(
+                    "Decode unknown frame type {:?}",
+                    line
+                )))
+            }
+        };
+        Ok(Some(token))
+    }
+
+    // a line of arguments typed by hand, ended by LF or CRLF, blank lines are skipped
+    fn inline(&mut self, src: &mut BytesMut) -> Result<Option<Token>, RespError> {
+        loop {
+            match src.first() {
+                None => return Ok(None),
+                Some(c) if RESP_TYPES.contains(c) => return self.next_token(src),
+                Some(_) => {}
+            }
+            let Some(end) = src.iter().position(|&c| c == b'\n') else {
+                if src.len() > INLINE_MAX_LEN {
+                    return Err(RespError::Protocol("too big inline request".to_string()));
+                }
+                return Ok(None);
+            };
+            let line = src.split_to(end + 1);
+            let line = &line[..end];
+            let args = split_args(line.strip_suffix(b"\r").unwrap_or(line))
+                .ok_or_else(|| RespError::Protocol("unbalanced quotes in request".to_string()))?;
+            if !args.is_empty() {
+                let args = args
+                    .into_iter()
+                    .map(|arg| BulkString::new(arg).into())
+                    .collect::<Vec<RespFrame>>();
+                return Ok(Some(Token::Frame(RespArray::new(args).into())));
+            }
+        }
+    }
+
+    fn bulk_payload(
+        &mut self,
+        src: &mut BytesMut,
+        kind: u8,
+        len: usize,
+    ) -> Result<Option<Token>, RespError> {
+        if src.len() < len + CRLF_LEN {
+            self.bulk = Some((kind, len));
+            return Ok(None);
+        }
+        self.bulk = None;
+        self.pending += len + CRLF_LEN;
+        if &src[len..len + CRLF_LEN] != b"\r\n" {
+            return Err(RespError::InvalidFrame(
+                "bulk payload not terminated by CRLF".to_string(),
+            ));
+        }
+        // large payloads share the read buffer, small ones are copied so they don't keep a whole
+        // buffer alive for as long as they are stored
+        let data = if len >= ZERO_COPY_MIN_LEN {
+            src.split_to(len).freeze()
+        } else {
+            Bytes::copy_from_slice(&src.split_to(len))
+        };
+        src.advance(CRLF_LEN);
+        let frame = match kind {
+            b'$' => BulkString::from(data).into(),
+            b'!' => BulkError::new(data).into(),
+            _ => {
+                if data.len() < 4 || data[3] != b':' {
+                    return Err(RespError::InvalidFrame(
+                        "verbatim string without format".to_string(),
+                    ));
+                }
+                VerbatimString::new([data[0], data[1], data[2]], &data[4..]).into()
+            }
+        };
+        Ok(Some(Token::Frame(frame)))
+    }
+
+    fn multibulk_len(&self, len: usize) -> Result<usize, RespError> {
+        if len > self.limits.max_multibulk_len {
+            return Err(RespError::Protocol("invalid multibulk length".to_string()));
+        }
+        Ok(len)
+    }
+
+    // position of the CR ending the first line of `src`, remembering how far it got when the
+    // line is not complete yet
+    fn find_line(&mut self, src: &[u8]) -> Option<usize> {
+        let start = self.scanned.min(src.len());
+        match src[start..].windows(CRLF_LEN).position(|w| w == b"\r\n") {
+            Some(pos) => {
+                self.scanned = 0;
+                Some(start + pos)
+            }
+            None => {
+                // the last byte may be the CR of a CRLF split across reads
+                self.scanned = src.len().saturating_sub(1);
+                None
+            }
+        }
+    }
+}
+
+fn finish(kind: u8, mut frames: Vec<RespFrame>) -> Result<RespFrame, RespError> {
+    let frame = match kind {
+        b'*' => RespArray::new(frames).into(),
+        b'>' => RespPush::new(frames).into(),
+        b'~' => {
+            let mut set = RespSet::new();
+            for frame in frames {
+                set.insert(frame);
+            }
+            set.into()
+        }
+        b'%' => map(frames)?.into(),
+        _ => {
+            let frame = frames.pop().expect("attribute is followed by a reply");
+            RespAttribute::new(map(frames)?, frame).into()
+        }
+    };
+    Ok(frame)
+}
+
+fn map(frames: Vec<RespFrame>) -> Result<RespMap, RespError> {
+    let mut map = BTreeMap::new();
+    let mut frames = frames.into_iter();
+    while let (Some(key), Some(value)) = (frames.next(), frames.next()) {
+        map.insert(map_key(key)?, value);
+    }
+    Ok(RespMap(map))
+}
+
+fn utf8(body: &[u8]) -> Result<&str, RespError> {
+    std::str::from_utf8(body)
+        .map_err(|_| RespError::InvalidFrame(format!("invalid utf8 {:?}", body)))
+}
+
+// the length of a bulk or aggregate frame, `None` for -1
+fn length(body: &[u8]) -> Result<Option<usize>, RespError> {
+    match utf8(body)?.parse::<isize>()? {
+        -1 => Ok(None),
+        len if len < 0 => Err(RespError::InvalidFrameLength(len)),
+        len => Ok(Some(len as usize)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_across_partial_reads() -> anyhow::Result<()> {
+        let data = b"*3\r\n$3\r\nset\r\n$5\r\nhello\r\n%1\r\n+k\r\n~2\r\n:1\r\n#t\r\n+OK\r\n";
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        // feed a byte at a time, nothing may be lost at any boundary
+        for b in data {
+            buf.extend_from_slice(&[*b]);
+            while let Some(frame) = parser.parse(&mut buf)? {
+                frames.push(frame);
+            }
+        }
+        let mut set = RespSet::new();
+        set.insert(RespFrame::Integer(1));
+        set.insert(true.into());
+        let mut map = RespMap::new();
+        map.insert("k", set.into());
+        assert_eq!(
+            frames,
+            vec![
+                RespArray::new([b"set".into(), b"hello".into(), map.into()]).into(),
+                SimpleString::new("OK").into(),
+            ]
+        );
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_matches_decoder() -> anyhow::Result<()> {
+        let data: &[u8] = b"$-1\r\n*-1\r\n*0\r\n_\r\n,1.5\r\n(123456789012345678901234567890\r\n!3\r\nERR\r\n=7\r\ntxt:abc\r\n|1\r\n+ttl\r\n:1\r\n>1\r\n-ERR x\r\n";
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::from(data);
+        let mut expected = BytesMut::from(data);
+        while !expected.is_empty() {
+            let frame = parser.parse(&mut buf)?;
+            assert_eq!(frame, Some(crate::RespDecoder::decode(&mut expected)?));
+        }
+        assert!(buf.is_empty());
+        assert_eq!(parser.parse(&mut buf)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_large_bulk_without_copy() -> anyhow::Result<()> {
+        let payload = vec![b'x'; ZERO_COPY_MIN_LEN];
+        let mut buf = BytesMut::new();
+        let header = format!("${}\r\n", payload.len());
+        buf.extend_from_slice(header.as_bytes());
+        buf.extend_from_slice(&payload);
+        buf.extend_from_slice(b"\r\n$1\r\ny\r\n");
+        let payload_start = buf[header.len()..].as_ptr();
+
+        let mut parser = RespParser::new();
+        match parser.parse(&mut buf)? {
+            Some(RespFrame::BulkString(s)) => {
+                assert_eq!(s.as_ptr(), payload_start);
+                // and so does every clone of it, stored or replied
+                assert_eq!(s.clone().as_ptr(), payload_start);
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        assert_eq!(parser.parse(&mut buf)?, Some(b"y".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = ParserLimits {
+            max_bulk_len: 8,
+            max_multibulk_len: 4,
+            max_depth: 2,
+            max_query_buffer: 32,
+        };
+        let parse = |data: &[u8]| RespParser::with_limits(limits).parse(&mut BytesMut::from(data));
+        let protocol_error = |msg: &str| Err(RespError::Protocol(msg.to_string()));
+
+        assert!(matches!(parse(b"$8\r\n12345678\r\n"), Ok(Some(_))));
+        assert_eq!(parse(b"$9\r\n"), protocol_error("invalid bulk length"));
+        assert_eq!(parse(b"*5\r\n"), protocol_error("invalid multibulk length"));
+        assert_eq!(parse(b"%3\r\n"), protocol_error("invalid multibulk length"));
+        assert!(matches!(parse(b"*1\r\n*1\r\n:1\r\n"), Ok(Some(_))));
+        assert_eq!(
+            parse(b"*1\r\n*1\r\n*1\r\n"),
+            protocol_error("too many nested aggregates")
+        );
+
+        // what is still buffered counts, complete frames consumed before it don't
+        let mut parser = RespParser::with_limits(limits);
+        let mut buf = BytesMut::from(&b"*4\r\n$8\r\n12345678\r\n$8\r\n12345678\r\n"[..]);
+        assert_eq!(parser.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"$8\r\n1234");
+        assert_eq!(
+            parser.parse(&mut buf),
+            protocol_error("client query buffer limit reached")
+        );
+        let mut parser = RespParser::with_limits(limits);
+        let mut buf = BytesMut::from(&[b"+OK\r\n".as_slice(); 10].concat()[..]);
+        while let Some(frame) = parser.parse(&mut buf).unwrap() {
+            assert_eq!(frame, SimpleString::new("OK").into());
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut parser = RespParser::new();
+        assert!(parser
+            .parse(&mut BytesMut::from(&b"*1\r\n?\r\n"[..]))
+            .is_err());
+        let mut parser = RespParser::new();
+        assert!(parser
+            .parse(&mut BytesMut::from(&b"$3\r\nabcd\r\n"[..]))
+            .is_err());
+        let mut parser = RespParser::new();
+        assert!(parser.parse(&mut BytesMut::from(&b"*-2\r\n"[..])).is_err());
+        let mut parser = RespParser::new();
+        assert!(parser
+            .parse(&mut BytesMut::from(&b"*1\r\n\r\n"[..]))
+            .is_err());
+        let mut parser = RespParser::new();
+        assert_eq!(
+            parser.parse(&mut BytesMut::from(&b"get \"k\r\n"[..])),
+            Err(RespError::Protocol(
+                "unbalanced quotes in request".to_string()
+            ))
+        );
+        let mut parser = RespParser::new();
+        assert_eq!(
+            parser.parse(&mut BytesMut::from(&vec![b'a'; INLINE_MAX_LEN + 1][..])),
+            Err(RespError::Protocol("too big inline request".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_inline() -> anyhow::Result<()> {
+        let command = |args: &[&str]| -> RespFrame {
+            RespArray::new(
+                args.iter()
+                    .map(|arg| BulkString::new(*arg).into())
+                    .collect::<Vec<RespFrame>>(),
+            )
+            .into()
+        };
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        // inline and RESP requests mixed, with blank lines in between, a byte at a time
+        for b in b"PING\n\r\n  \nset k \"a b\"\r\n*1\r\n$3\r\nget\r\n\nGET k\n" {
+            buf.extend_from_slice(&[*b]);
+            while let Some(frame) = parser.parse(&mut buf)? {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(
+            frames,
+            vec![
+                command(&["PING"]),
+                command(&["set", "k", "a b"]),
+                command(&["get"]),
+                command(&["GET", "k"]),
+            ]
+        );
+        assert!(buf.is_empty());
+        Ok(())
+    }
+}
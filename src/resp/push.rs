@@ -0,0 +1,92 @@
+use std::ops::Deref;
+
+use bytes::{Buf, BytesMut};
+
+use super::{
+    calc_total_length, encode_line, parse_length, RespDecoder, RespEncoder, RespError, RespFrame,
+    CRLF_LEN,
+};
+
+/// Out of band data sent by the server without a matching request, like pub/sub messages.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RespPush(pub(crate) Vec<RespFrame>);
+
+impl Deref for RespPush {
+    type Target = Vec<RespFrame>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl RespPush {
+    pub fn new(s: impl Into<Vec<RespFrame>>) -> Self {
+        RespPush(s.into())
+    }
+}
+
+impl RespEncoder for RespPush {
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'>', self.len());
+        for frame in &self.0 {
+            frame.encode_to(dst);
+        }
+    }
+}
+
+//- push: "><number-of-elements>\r\n<element-1>...<element-n>"
+impl RespDecoder for RespPush {
+    const PREFIX: &'static str = ">";
+    fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        let (end, len) = parse_length(data, Self::PREFIX)?;
+        let total_len = calc_total_length(data, len, end, Self::PREFIX)?;
+        if data.len() < total_len {
+            return Err(RespError::NotComplete);
+        }
+        data.advance(end + CRLF_LEN);
+        let mut frames = Vec::with_capacity(len);
+        for _ in 0..len {
+            frames.push(RespFrame::decode(data)?);
+        }
+        Ok(RespPush::new(frames))
+    }
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        calc_total_length(buf, len, end, Self::PREFIX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BulkString;
+
+    use super::*;
+
+    #[test]
+    fn test_push_encode() {
+        let frame = RespPush::new([
+            BulkString::new("message").into(),
+            BulkString::new("news").into(),
+            BulkString::new("hi").into(),
+        ]);
+        assert_eq!(
+            frame.encode(),
+            b">3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n"
+        );
+    }
+    #[test]
+    fn test_push_decode() -> anyhow::Result<()> {
+        let mut buf = BytesMut::from(&b">2\r\n$7\r\nmessage\r\n:1\r\n"[..]);
+        let frame = RespPush::decode(&mut buf)?;
+        assert_eq!(
+            frame,
+            RespPush::new([b"message".into(), RespFrame::Integer(1)])
+        );
+
+        let mut buf = BytesMut::from(&b">2\r\n$7\r\nmessage\r\n"[..]);
+        assert_eq!(
+            RespPush::decode(&mut buf).unwrap_err(),
+            RespError::NotComplete
+        );
+        Ok(())
+    }
+}
@@ -3,7 +3,8 @@ use std::ops::Deref;
 use bytes::{Buf, BytesMut};
 
 use super::{
-    calc_total_length, parse_length, RespDecoder, RespEncoder, RespError, RespFrame, CRLF_LEN,
+    calc_total_length, encode_line, parse_length, RespDecoder, RespEncoder, RespError, RespFrame,
+    CRLF_LEN,
 };
 
 #[derive(Debug, PartialEq, Clone)]
@@ -33,13 +34,11 @@ impl RespSet {
     }
 }
 impl RespEncoder for RespSet {
-    fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(self.len() * 32);
-        buf.extend_from_slice(&format!("~{}\r\n", self.len()).into_bytes());
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'~', self.len());
         for frame in &self.0 {
-            buf.extend_from_slice(&frame.encode());
+            frame.encode_to(dst);
         }
-        buf
     }
 }
 //- set: "~<number-of-elements>\r\n<element-1>...<element-n>"
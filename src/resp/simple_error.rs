@@ -1,8 +1,8 @@
 use std::ops::Deref;
 
-use bytes::BytesMut;
+use bytes::{Buf, BufMut, BytesMut};
 
-use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF_LEN};
+use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF, CRLF_LEN};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct SimpleError(pub(crate) String);
@@ -21,8 +21,10 @@ impl SimpleError {
 }
 
 impl RespEncoder for SimpleError {
-    fn encode(&self) -> Vec<u8> {
-        format!("-{}\r\n", self.0).into_bytes()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.put_u8(b'-');
+        dst.extend_from_slice(self.0.as_bytes());
+        dst.extend_from_slice(CRLF);
     }
 }
 //- error: "-Error message\r\n"
@@ -31,6 +33,7 @@ impl RespDecoder for SimpleError {
     fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
         let end = extract_simple_frame_data(data, Self::PREFIX, 1)?;
         let frame = SimpleError::new(String::from_utf8_lossy(&data[Self::PREFIX.len()..end]));
+        data.advance(end + CRLF_LEN);
         Ok(frame)
     }
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
@@ -1,8 +1,8 @@
 use std::ops::Deref;
 
-use bytes::BytesMut;
+use bytes::{Buf, BufMut, BytesMut};
 
-use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF_LEN};
+use super::{extract_simple_frame_data, RespDecoder, RespEncoder, RespError, CRLF, CRLF_LEN};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct SimpleString(pub(crate) String);
@@ -34,13 +34,15 @@ impl AsRef<str> for SimpleString {
 }
 
 impl RespEncoder for SimpleString {
-    fn encode(&self) -> Vec<u8> {
-        format!("+{}\r\n", self.0).into_bytes()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        self.0.encode_to(dst)
     }
 }
 impl RespEncoder for String {
-    fn encode(&self) -> Vec<u8> {
-        format!("+{}\r\n", self).into_bytes()
+    fn encode_to(&self, dst: &mut BytesMut) {
+        dst.put_u8(b'+');
+        dst.extend_from_slice(self.as_bytes());
+        dst.extend_from_slice(CRLF);
     }
 }
 //simple string:00 "+OK\r\n"
@@ -49,6 +51,7 @@ impl RespDecoder for SimpleString {
     fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
         let end = extract_simple_frame_data(data, Self::PREFIX, 1)?;
         let frame = SimpleString::new(String::from_utf8_lossy(&data[Self::PREFIX.len()..end]));
+        data.advance(end + CRLF_LEN);
         Ok(frame)
     }
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
@@ -0,0 +1,83 @@
+use bytes::{Buf, BufMut, BytesMut};
+
+use super::{encode_line, parse_length, RespDecoder, RespEncoder, RespError, CRLF, CRLF_LEN};
+
+/// A string along with the three letter format it should be displayed as, `txt` or `mkd`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VerbatimString {
+    pub(crate) format: [u8; 3],
+    pub(crate) data: Vec<u8>,
+}
+
+impl VerbatimString {
+    pub fn new(format: [u8; 3], data: impl Into<Vec<u8>>) -> Self {
+        VerbatimString {
+            format,
+            data: data.into(),
+        }
+    }
+    pub fn format(&self) -> &[u8; 3] {
+        &self.format
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl RespEncoder for VerbatimString {
+    fn encode_to(&self, dst: &mut BytesMut) {
+        encode_line(dst, b'=', self.data.len() + 4);
+        dst.extend_from_slice(&self.format);
+        dst.put_u8(b':');
+        dst.extend_from_slice(&self.data);
+        dst.extend_from_slice(CRLF);
+    }
+}
+
+//- verbatim string: "=<length>\r\n<format>:<data>\r\n", the length counts the format and colon
+impl RespDecoder for VerbatimString {
+    const PREFIX: &'static str = "=";
+    fn decode(data: &mut BytesMut) -> anyhow::Result<Self, RespError> {
+        let (end, len) = parse_length(data, Self::PREFIX)?;
+        if data.len() < end + CRLF_LEN + len + CRLF_LEN {
+            return Err(RespError::NotComplete);
+        }
+        let body = &data[end + CRLF_LEN..end + CRLF_LEN + len];
+        if len < 4 || body[3] != b':' {
+            return Err(RespError::InvalidFrame(format!(
+                "verbatim string without format: {:?}",
+                body
+            )));
+        }
+        let frame = VerbatimString::new([body[0], body[1], body[2]], &body[4..]);
+        data.advance(end + CRLF_LEN + len + CRLF_LEN);
+        Ok(frame)
+    }
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        Ok(end + CRLF_LEN + len + CRLF_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbatim_string_encode() {
+        let frame = VerbatimString::new(*b"txt", "Some string");
+        assert_eq!(frame.encode(), b"=15\r\ntxt:Some string\r\n");
+    }
+    #[test]
+    fn test_verbatim_string_decode() -> anyhow::Result<()> {
+        let mut data = BytesMut::new();
+        data.extend_from_slice(b"=15\r\ntxt:Some string\r\n");
+        let result = VerbatimString::decode(&mut data)?;
+        assert_eq!(result, VerbatimString::new(*b"txt", "Some string"));
+        assert!(data.is_empty());
+
+        let mut data = BytesMut::from(&b"=3\r\ntxt\r\n"[..]);
+        assert!(VerbatimString::decode(&mut data).is_err());
+        Ok(())
+    }
+}
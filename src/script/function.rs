@@ -0,0 +1,677 @@
+/*
+   FUNCTION libraries and FCALL.
+
+   - library code runs once on FUNCTION LOAD, in the same sandboxed state as EVAL scripts
+   - libraries are saved with the dataset in RDB snapshots, one FUNCTION2 opcode each, and
+     loaded back with it at startup
+*/
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use mlua::{Function, Lua, MultiValue, Table, Value};
+
+use super::{
+    error_reply, find_command_error, string_sequence, ScriptCommandError, ScriptEngine,
+    LOAD_TIME_LIMIT,
+};
+use crate::{
+    glob_match, now_ms,
+    rdb::{self, RdbError},
+    Backend, BulkString, RespArray, RespFrame, RespMap, RespNull, RespSet, SimpleError,
+    SimpleString,
+};
+
+// registry tables holding the callback of every function, and the ones registered by the
+// library being loaded
+const FUNCTIONS_REGISTRY: &str = "functions";
+const PENDING_REGISTRY: &str = "pending_functions";
+
+const FUNCTION_FLAGS: [&str; 5] = [
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+#[derive(Debug, Clone)]
+pub(super) struct Library {
+    code: String,
+    functions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct FunctionInfo {
+    library: String,
+    description: Option<String>,
+    flags: Vec<String>,
+}
+
+/// How FUNCTION RESTORE handles libraries that already exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePolicy {
+    Append,
+    Replace,
+    Flush,
+}
+
+impl ScriptEngine {
+    /// Load a function library, returning its name.
+    pub fn function_load(&self, code: &str, replace: bool) -> Result<String, SimpleError> {
+        let lua = self.lua();
+        self.load_library(&lua, code, replace)
+    }
+
+    pub fn function_delete(&self, library: &str) -> Result<(), SimpleError> {
+        let lua = self.lua();
+        if !self.libraries.contains_key(library) {
+            return Err(SimpleError::new("ERR Library not found"));
+        }
+        self.remove_library(&lua, library);
+        Ok(())
+    }
+
+    pub fn function_flush(&self) {
+        let lua = self.lua();
+        self.clear_libraries(&lua);
+    }
+
+    /// The code of every library, which is all it takes to load them again.
+    pub fn function_codes(&self) -> Vec<String> {
+        let mut libraries = self
+            .libraries
+            .iter()
+            .map(|lib| (lib.key().clone(), lib.code.clone()))
+            .collect::<Vec<_>>();
+        libraries.sort();
+        libraries.into_iter().map(|(_, code)| code).collect()
+    }
+
+    pub fn function_dump(&self) -> Vec<u8> {
+        rdb::dump_functions(&self.function_codes())
+    }
+
+    /// Load the libraries of a FUNCTION DUMP payload, either all of them or none.
+    pub fn function_restore(
+        &self,
+        payload: &[u8],
+        policy: RestorePolicy,
+    ) -> Result<(), SimpleError> {
+        let codes = rdb::restore_functions(payload).map_err(|e| match e {
+            RdbError::BadPayload => SimpleError::new("ERR payload version or checksum are wrong"),
+            e => SimpleError::new(format!("ERR {}", e)),
+        })?;
+        let lua = self.lua();
+        let previous = self.function_codes();
+        if policy == RestorePolicy::Flush {
+            self.clear_libraries(&lua);
+        }
+        for code in &codes {
+            if let Err(e) = self.load_library(&lua, code, policy == RestorePolicy::Replace) {
+                self.clear_libraries(&lua);
+                for code in &previous {
+                    if let Err(e) = self.load_library(&lua, code, false) {
+                        tracing::warn!("failed to reload function library: {:?}", e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// FUNCTION LIST, optionally only libraries whose name matches `pattern`.
+    pub fn function_list(&self, pattern: Option<&str>, with_code: bool) -> RespFrame {
+        let mut names = self
+            .libraries
+            .iter()
+            .map(|lib| lib.key().clone())
+            .filter(|name| pattern.is_none_or(|p| glob_match(p.as_bytes(), name.as_bytes())))
+            .collect::<Vec<String>>();
+        names.sort();
+
+        let mut libraries = Vec::with_capacity(names.len());
+        for name in names {
+            let Some(library) = self.libraries.get(&name).map(|lib| lib.clone()) else {
+                continue;
+            };
+            let mut functions = Vec::with_capacity(library.functions.len());
+            for function in &library.functions {
+                let Some(info) = self.functions.get(function).map(|f| f.clone()) else {
+                    continue;
+                };
+                let mut entry = RespMap::new();
+                entry.insert("name", BulkString::new(function.as_str()).into());
+                entry.insert(
+                    "description",
+                    match info.description {
+                        Some(description) => BulkString::new(description).into(),
+                        None => RespFrame::Null(RespNull),
+                    },
+                );
+                let mut flags = RespSet::new();
+                for flag in info.flags {
+                    flags.insert(SimpleString::new(flag).into());
+                }
+                entry.insert("flags", flags.into());
+                functions.push(entry.into());
+            }
+            let mut entry = RespMap::new();
+            entry.insert("library_name", BulkString::new(name).into());
+            entry.insert("engine", BulkString::new("LUA").into());
+            entry.insert("functions", RespArray::new(functions).into());
+            if with_code {
+                entry.insert("library_code", BulkString::new(library.code).into());
+            }
+            libraries.push(entry.into());
+        }
+        RespArray::new(libraries).into()
+    }
+
+    /// Call a function, the caller must hold the backend exclusive lock.
+    pub fn fcall(
+        &self,
+        backend: &Backend,
+        name: &str,
+        keys: &[Bytes],
+        args: &[Vec<u8>],
+        read_only: bool,
+    ) -> RespFrame {
+        let Some(info) = self.functions.get(name).map(|f| f.clone()) else {
+            return SimpleError::new("ERR Function not found").into();
+        };
+        let no_writes = info.flags.iter().any(|f| f == "no-writes");
+        if read_only && !no_writes {
+            return SimpleError::new(
+                "ERR Can not execute a script with write flag using *_ro command.",
+            )
+            .into();
+        }
+        let lua = self.lua();
+        let prepared = (|| -> mlua::Result<_> {
+            let functions: Table = lua.named_registry_value(FUNCTIONS_REGISTRY)?;
+            let func: Function = functions.raw_get(name)?;
+            Ok((
+                func,
+                string_sequence(&lua, keys)?,
+                string_sequence(&lua, args)?,
+            ))
+        })();
+        match prepared {
+            Ok((func, keys, args)) => self.run(&lua, backend, func, (keys, args), no_writes),
+            Err(e) => error_reply(&e).into(),
+        }
+    }
+
+    fn load_library(&self, lua: &Lua, code: &str, replace: bool) -> Result<String, SimpleError> {
+        let (name, body) = parse_metadata(code)?;
+        if !replace && self.libraries.contains_key(&name) {
+            return Err(SimpleError::new(format!(
+                "ERR Library '{}' already exists",
+                name
+            )));
+        }
+        // the code runs outside of a script so FUNCTION KILL can't stop it, the hook fails it
+        // once it runs past the load time limit instead
+        let deadline = now_ms() + LOAD_TIME_LIMIT.as_millis() as u64;
+        self.load_deadline.store(deadline, Ordering::Release);
+        let registered = register_functions(lua, &name, &body);
+        self.load_deadline.store(0, Ordering::Release);
+        let registered = registered.map_err(|e| match e {
+            mlua::Error::SyntaxError { message, .. } => {
+                SimpleError::new(format!("ERR Error compiling function: {}", message))
+            }
+            e => match find_command_error(&e) {
+                Some(e) => SimpleError::new(e.0.clone()),
+                None => SimpleError::new(format!("ERR Error registering functions: {}", e)),
+            },
+        })?;
+        if registered.is_empty() {
+            return Err(SimpleError::new("ERR No functions registered"));
+        }
+        for (function, _, _) in &registered {
+            if let Some(info) = self.functions.get(function) {
+                if info.library != name {
+                    return Err(SimpleError::new(format!(
+                        "ERR Function {} already exists",
+                        function
+                    )));
+                }
+            }
+        }
+
+        self.remove_library(lua, &name);
+        let ret = (|| -> mlua::Result<()> {
+            let functions = functions_table(lua)?;
+            for (function, entry, info) in &registered {
+                functions.raw_set(function.as_str(), entry.get::<_, Function>("callback")?)?;
+                self.functions.insert(function.clone(), info.clone());
+            }
+            Ok(())
+        })();
+        if let Err(e) = ret {
+            self.remove_library(lua, &name);
+            return Err(SimpleError::new(format!("ERR {}", e)));
+        }
+        let library = Library {
+            code: code.to_string(),
+            functions: registered.into_iter().map(|(f, _, _)| f).collect(),
+        };
+        self.libraries.insert(name.clone(), library);
+        Ok(name)
+    }
+
+    fn remove_library(&self, lua: &Lua, name: &str) {
+        let functions = functions_table(lua);
+        if let Some((_, library)) = self.libraries.remove(name) {
+            for function in library.functions {
+                self.functions.remove(&function);
+                if let Ok(functions) = &functions {
+                    if let Err(e) = functions.raw_remove(function.as_str()) {
+                        tracing::warn!("failed to remove function {}: {}", function, e);
+                    }
+                }
+            }
+        }
+        // functions registered before the library failed to load
+        self.functions.retain(|_, info| info.library != name);
+    }
+
+    fn clear_libraries(&self, lua: &Lua) {
+        let names = self
+            .libraries
+            .iter()
+            .map(|lib| lib.key().clone())
+            .collect::<Vec<String>>();
+        for name in names {
+            self.remove_library(lua, &name);
+        }
+    }
+}
+
+/// `redis.register_function`, only available while FUNCTION LOAD runs the library code.
+pub(super) fn register_function(lua: &Lua, args: MultiValue) -> mlua::Result<()> {
+    let pending: Option<Table> = lua.named_registry_value(PENDING_REGISTRY)?;
+    let Some(pending) = pending else {
+        return Err(script_error(
+            "ERR redis.register_function can only be called on FUNCTION LOAD command",
+        ));
+    };
+    let args = args.into_vec();
+    let (name, callback, description, flags) = match args.as_slice() {
+        [Value::String(name), Value::Function(callback)] => {
+            (name.to_str()?.to_string(), callback.clone(), None, vec![])
+        }
+        [Value::Table(table)] => parse_registration_table(table)?,
+        _ => {
+            return Err(script_error(
+                "ERR wrong number of arguments to redis.register_function",
+            ))
+        }
+    };
+    if !is_valid_name(&name) {
+        return Err(script_error(
+            "ERR Function names can only contain letters, numbers, or underscores(_) and must be at least one character long",
+        ));
+    }
+    if pending.contains_key(name.as_str())? {
+        return Err(script_error("ERR Function already exists in the library"));
+    }
+    let entry = lua.create_table()?;
+    entry.set("callback", callback)?;
+    entry.set("description", description)?;
+    entry.set("flags", lua.create_sequence_from(flags)?)?;
+    pending.raw_set(name, entry)
+}
+
+type Registration<'lua> = (String, Function<'lua>, Option<String>, Vec<String>);
+
+fn parse_registration_table<'lua>(table: &Table<'lua>) -> mlua::Result<Registration<'lua>> {
+    let mut name = None;
+    let mut callback = None;
+    let mut description = None;
+    let mut flags = vec![];
+    for pair in table.clone().pairs::<String, Value>() {
+        let (key, value) = pair?;
+        match (key.as_str(), value) {
+            ("function_name", Value::String(v)) => name = Some(v.to_str()?.to_string()),
+            ("callback", Value::Function(v)) => callback = Some(v),
+            ("description", Value::String(v)) => description = Some(v.to_str()?.to_string()),
+            ("flags", Value::Table(v)) => {
+                for flag in v.sequence_values::<String>() {
+                    let flag = flag?;
+                    if !FUNCTION_FLAGS.contains(&flag.as_str()) {
+                        return Err(script_error("ERR Unknown flag given"));
+                    }
+                    flags.push(flag);
+                }
+            }
+            ("function_name" | "callback" | "description" | "flags", _) => {
+                return Err(script_error(&format!(
+                    "ERR {} argument given to redis.register_function has the wrong type",
+                    key
+                )))
+            }
+            _ => {
+                return Err(script_error(
+                    "ERR unknown argument given to redis.register_function",
+                ))
+            }
+        }
+    }
+    match (name, callback) {
+        (Some(name), Some(callback)) => Ok((name, callback, description, flags)),
+        (None, _) => Err(script_error(
+            "ERR redis.register_function must get a function name argument",
+        )),
+        (_, None) => Err(script_error(
+            "ERR redis.register_function must get a callback argument",
+        )),
+    }
+}
+
+// runs the library code, returning the name, entry and details of every function it registered
+fn register_functions<'lua>(
+    lua: &'lua Lua,
+    library: &str,
+    body: &str,
+) -> mlua::Result<Vec<(String, Table<'lua>, FunctionInfo)>> {
+    let chunk = lua.load(body).set_name("@user_function").into_function()?;
+    let pending = lua.create_table()?;
+    lua.set_named_registry_value(PENDING_REGISTRY, pending.clone())?;
+    let ret = chunk.call::<_, ()>(());
+    lua.unset_named_registry_value(PENDING_REGISTRY)?;
+    ret?;
+
+    let mut registered = vec![];
+    for pair in pending.pairs::<String, Table>() {
+        let (name, entry) = pair?;
+        let info = FunctionInfo {
+            library: library.to_string(),
+            description: entry.get("description")?,
+            flags: entry.get("flags")?,
+        };
+        registered.push((name, entry, info));
+    }
+    registered.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(registered)
+}
+
+// `#!lua name=<library>` on the first line, the code itself keeps its line numbers
+fn parse_metadata(code: &str) -> Result<(String, String), SimpleError> {
+    let (shebang, body) = code.split_once('\n').unwrap_or((code, ""));
+    let Some(shebang) = shebang.strip_prefix("#!") else {
+        return Err(SimpleError::new("ERR Missing library metadata"));
+    };
+    let mut parts = shebang.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(SimpleError::new(format!(
+            "ERR Engine '{}' not found",
+            engine
+        )));
+    }
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(v) => name = Some(v.to_string()),
+            None => {
+                return Err(SimpleError::new(format!(
+                    "ERR Invalid metadata value given: {}",
+                    part
+                )))
+            }
+        }
+    }
+    let Some(name) = name else {
+        return Err(SimpleError::new("ERR Library name was not given"));
+    };
+    if !is_valid_name(&name) {
+        return Err(SimpleError::new(
+            "ERR Library names can only contain letters, numbers, or underscores(_) and must be at least one character long",
+        ));
+    }
+    Ok((name, format!("\n{}", body)))
+}
+
+fn functions_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+    match lua.named_registry_value::<Option<Table>>(FUNCTIONS_REGISTRY)? {
+        Some(table) => Ok(table),
+        None => {
+            let table = lua.create_table()?;
+            lua.set_named_registry_value(FUNCTIONS_REGISTRY, table.clone())?;
+            Ok(table)
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
+}
+
+fn script_error(msg: &str) -> mlua::Error {
+    mlua::Error::external(ScriptCommandError(msg.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::script::LOAD_TIMEOUT;
+
+    const LIBRARY: &str = "#!lua name=mylib
+redis.register_function('setget', function(keys, args)
+    redis.call('set', keys[1], args[1])
+    return redis.call('get', keys[1])
+end)
+redis.register_function{
+    function_name = 'getter',
+    callback = function(keys) return redis.call('get', keys[1]) end,
+    flags = {'no-writes'},
+}
+redis.register_function{
+    function_name = 'bad_getter',
+    callback = function(keys) return redis.call('set', keys[1], 'x') end,
+    flags = {'no-writes'},
+}";
+
+    #[test]
+    fn test_function_load_and_fcall() {
+        let backend = Backend::new();
+        let engine = ScriptEngine::new();
+        assert_eq!(engine.function_load(LIBRARY, false).unwrap(), "mylib");
+        assert_eq!(
+            engine.function_load(LIBRARY, false),
+            Err(SimpleError::new("ERR Library 'mylib' already exists"))
+        );
+        assert!(engine.function_load(LIBRARY, true).is_ok());
+
+        let keys = ["hello".into()];
+        let ret = engine.fcall(&backend, "setget", &keys, &[b"world".to_vec()], false);
+        assert_eq!(ret, b"world".into());
+        let ret = engine.fcall(&backend, "getter", &keys, &[], true);
+        assert_eq!(ret, b"world".into());
+        let ret = engine.fcall(&backend, "setget", &keys, &[b"x".to_vec()], true);
+        assert!(matches!(ret, RespFrame::Error(e) if e.contains("*_ro")));
+        let ret = engine.fcall(&backend, "bad_getter", &keys, &[], false);
+        assert_eq!(
+            ret,
+            SimpleError::new("ERR Write commands are not allowed from read-only scripts.").into()
+        );
+        assert_eq!(
+            engine.fcall(&backend, "missing", &keys, &[], false),
+            SimpleError::new("ERR Function not found").into()
+        );
+
+        // SCRIPT FLUSH leaves functions alone
+        engine.flush();
+        assert_eq!(
+            engine.fcall(&backend, "getter", &keys, &[], false),
+            b"world".into()
+        );
+    }
+
+    #[test]
+    fn test_function_sandbox() {
+        let backend = Backend::new();
+        let engine = ScriptEngine::new();
+        for code in [
+            "#!lua name=lib\nos.exit(1)",
+            "#!lua name=lib\nio.open('/etc/passwd')",
+            "#!lua name=lib\nshared = 1\nredis.register_function('f', function() end)",
+        ] {
+            assert!(engine.function_load(code, false).is_err());
+        }
+        engine
+            .function_load(
+                "#!lua name=lib\nredis.register_function('f', function() redis = nil end)",
+                false,
+            )
+            .unwrap();
+        let ret = engine.fcall(&backend, "f", &[], &[], false);
+        assert!(matches!(ret, RespFrame::Error(_)));
+    }
+
+    #[test]
+    fn test_function_load_timeout() {
+        let engine = ScriptEngine::new();
+        let start = Instant::now();
+        let ret = engine.function_load("#!lua name=lib\nwhile true do end", false);
+        assert_eq!(ret, Err(SimpleError::new(LOAD_TIMEOUT)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the state is usable again, with no deadline left behind for later calls
+        engine
+            .function_load(
+                "#!lua name=lib\nredis.register_function('f', function() return 1 end)",
+                false,
+            )
+            .unwrap();
+        let ret = engine.fcall(&Backend::new(), "f", &[], &[], false);
+        assert_eq!(ret, RespFrame::Integer(1));
+    }
+
+    #[test]
+    fn test_function_persisted_with_dataset() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        backend.scripts().function_load(LIBRARY, false).unwrap();
+        let data = rdb::snapshot_file(&backend);
+
+        let other = Backend::new();
+        assert_eq!(rdb::load(&data, &other)?.functions, 1);
+        let keys = ["hello".into()];
+        let ret = other
+            .scripts()
+            .fcall(&other, "setget", &keys, &[b"world".to_vec()], false);
+        assert_eq!(ret, b"world".into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_function_load_errors() {
+        let engine = ScriptEngine::new();
+        let cases = [
+            ("return 1", "ERR Missing library metadata"),
+            ("#!js name=lib\n", "ERR Engine 'js' not found"),
+            ("#!lua name=lib\nlocal x = 1", "ERR No functions registered"),
+            (
+                "#!lua name=lib\nredis.register_function('f', function() end)\nredis.register_function('f', function() end)",
+                "ERR Function already exists in the library",
+            ),
+            (
+                "#!lua name=lib\nredis.register_function{function_name='f', callback=function() end, flags={'bad'}}",
+                "ERR Unknown flag given",
+            ),
+        ];
+        for (code, err) in cases {
+            assert_eq!(
+                engine.function_load(code, false),
+                Err(SimpleError::new(err))
+            );
+        }
+        assert!(matches!(
+            engine.function_load("#!lua name=lib\nredis.register_function(", false),
+            Err(e) if e.starts_with("ERR Error compiling function")
+        ));
+
+        engine
+            .function_load(
+                "#!lua name=lib1\nredis.register_function('f', function() end)",
+                false,
+            )
+            .unwrap();
+        assert_eq!(
+            engine.function_load(
+                "#!lua name=lib2\nredis.register_function('f', function() end)",
+                false
+            ),
+            Err(SimpleError::new("ERR Function f already exists"))
+        );
+    }
+
+    #[test]
+    fn test_function_delete_dump_restore() {
+        let backend = Backend::new();
+        let engine = ScriptEngine::new();
+        engine.function_load(LIBRARY, false).unwrap();
+        let payload = engine.function_dump();
+
+        assert_eq!(
+            engine.function_delete("missing"),
+            Err(SimpleError::new("ERR Library not found"))
+        );
+        engine.function_delete("mylib").unwrap();
+        assert_eq!(
+            engine.function_list(None, false),
+            RespArray::new(Vec::<RespFrame>::new()).into()
+        );
+
+        engine
+            .function_restore(&payload, RestorePolicy::Append)
+            .unwrap();
+        assert!(engine
+            .function_restore(&payload, RestorePolicy::Append)
+            .is_err());
+        engine
+            .function_restore(&payload, RestorePolicy::Replace)
+            .unwrap();
+        engine
+            .function_restore(&payload, RestorePolicy::Flush)
+            .unwrap();
+        assert_eq!(engine.function_codes(), vec![LIBRARY.to_string()]);
+        let ret = engine.fcall(&backend, "getter", &["k".into()], &[], true);
+        assert_eq!(ret, RespFrame::Null(RespNull));
+
+        engine.function_flush();
+        assert!(engine.function_codes().is_empty());
+    }
+
+    #[test]
+    fn test_function_list() {
+        let engine = ScriptEngine::new();
+        engine
+            .function_load(
+                "#!lua name=lib\nredis.register_function{function_name='f', callback=function() end, description='desc', flags={'no-writes'}}",
+                false,
+            )
+            .unwrap();
+        let mut function = RespMap::new();
+        function.insert("name", BulkString::new("f").into());
+        function.insert("description", BulkString::new("desc").into());
+        let mut flags = RespSet::new();
+        flags.insert(SimpleString::new("no-writes").into());
+        function.insert("flags", flags.into());
+        let mut library = RespMap::new();
+        library.insert("library_name", BulkString::new("lib").into());
+        library.insert("engine", BulkString::new("LUA").into());
+        library.insert("functions", RespArray::new([function.into()]).into());
+        assert_eq!(
+            engine.function_list(Some("l*"), false),
+            RespArray::new([library.into()]).into()
+        );
+        assert_eq!(
+            engine.function_list(Some("x*"), true),
+            RespArray::new(Vec::<RespFrame>::new()).into()
+        );
+    }
+}
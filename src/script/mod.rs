@@ -0,0 +1,695 @@
+/*
+   Lua scripting for EVAL/EVALSHA and FCALL.
+
+   - redis.call/redis.pcall dispatch through `Command` and `CommandExcetor`
+   - a script runs while its caller holds the backend exclusive lock
+   - a hook checks every 1000 instructions whether SCRIPT KILL was requested
+   - function libraries share the Lua state, their callbacks live in the registry
+   - only the base, table, string and math libraries are loaded, without the base functions
+     reaching files or other environments
+   - globals are read-only: the globals table stays empty and reads through to the real ones,
+     so one script can't break the ones that follow
+*/
+mod function;
+
+pub use function::RestorePolicy;
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use mlua::{
+    Function, HookTriggers, IntoLuaMulti, Lua, LuaOptions, MultiValue, StdLib, Table, Value,
+    Variadic,
+};
+use thiserror::Error;
+
+use crate::{
+    cmd::Command, now_ms, Backend, BulkString, CommandExcetor, RespArray, RespFrame, RespNull,
+    SimpleError, SimpleString,
+};
+
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(5);
+// how long the code of a function library may run while it is loaded, like redis
+const LOAD_TIME_LIMIT: Duration = Duration::from_millis(500);
+// the globals scripts read through to, the redis library as scripts can't change it, and the
+// compiled scripts by sha1 digest
+const BASE_REGISTRY: &str = "base_globals";
+const REDIS_REGISTRY: &str = "redis";
+const SCRIPTS_REGISTRY: &str = "scripts";
+// base functions loading code or files, or escaping the read-only globals
+const UNSAFE_GLOBALS: [&str; 7] = [
+    "dofile",
+    "loadfile",
+    "load",
+    "loadstring",
+    "rawset",
+    "setfenv",
+    "getfenv",
+];
+const READONLY_ERROR: &str = "Attempt to modify a readonly table";
+const KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+const LOAD_TIMEOUT: &str = "ERR FUNCTION LOAD timeout";
+
+/// An error reply produced by a command called from a script.
+#[derive(Error, Debug)]
+#[error("{0}")]
+struct ScriptCommandError(String);
+
+pub struct ScriptEngine {
+    lua: Mutex<Lua>,
+    // script bodies by their sha1 digest
+    scripts: DashMap<String, String>,
+    // when the running script started, `None` if no script is running
+    running: Mutex<Option<Instant>>,
+    kill: Arc<AtomicBool>,
+    // unix milliseconds the library being loaded must be done by, 0 while none is
+    load_deadline: Arc<AtomicU64>,
+    // the running script called a write command, it can't be killed anymore
+    wrote: AtomicBool,
+    // the requests redoing the writes of the last script, for the append only file
+    effects: Mutex<Vec<RespFrame>>,
+    time_limit_ms: AtomicU64,
+    // function libraries by name, and the library and flags of every function by name
+    libraries: DashMap<String, function::Library>,
+    functions: DashMap<String, function::FunctionInfo>,
+}
+
+impl fmt::Debug for ScriptEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptEngine")
+            .field("scripts", &self.scripts.len())
+            .field("libraries", &self.libraries.len())
+            .field("running", &self.running)
+            .finish()
+    }
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        let kill = Arc::new(AtomicBool::new(false));
+        let load_deadline = Arc::new(AtomicU64::new(0));
+        ScriptEngine {
+            lua: Mutex::new(new_lua(&kill, &load_deadline)),
+            scripts: DashMap::new(),
+            running: Mutex::new(None),
+            kill,
+            load_deadline,
+            wrote: AtomicBool::new(false),
+            effects: Mutex::new(Vec::new()),
+            time_limit_ms: AtomicU64::new(DEFAULT_TIME_LIMIT.as_millis() as u64),
+            libraries: DashMap::new(),
+            functions: DashMap::new(),
+        }
+    }
+
+    /// How long a script may run before other clients get BUSY and it can be killed.
+    pub fn set_time_limit(&self, limit: Duration) {
+        self.time_limit_ms
+            .store(limit.as_millis() as u64, Ordering::Relaxed);
+    }
+    pub fn time_limit(&self) -> Duration {
+        Duration::from_millis(self.time_limit_ms.load(Ordering::Relaxed))
+    }
+
+    /// Compile and cache `body`, returning its sha1 digest.
+    pub fn load(&self, body: &str) -> Result<String, SimpleError> {
+        let sha = sha1_hex(body.as_bytes());
+        if self.scripts.contains_key(&sha) {
+            return Ok(sha);
+        }
+        let lua = self.lua();
+        let func = lua
+            .load(body)
+            .set_name("@user_script")
+            .into_function()
+            .map_err(|e| {
+                SimpleError::new(format!("ERR Error compiling script (new function): {}", e))
+            })?;
+        lua.named_registry_value::<Table>(SCRIPTS_REGISTRY)
+            .and_then(|scripts| scripts.raw_set(sha.as_str(), func))
+            .map_err(|e| SimpleError::new(format!("ERR {}", e)))?;
+        self.scripts.insert(sha.clone(), body.to_string());
+        Ok(sha)
+    }
+
+    /// The body of a cached script.
+    pub fn body(&self, sha: &str) -> Option<String> {
+        self.scripts
+            .get(&sha.to_ascii_lowercase())
+            .map(|body| body.clone())
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        self.scripts.contains_key(&sha.to_ascii_lowercase())
+    }
+
+    /// Forget every cached script, function libraries are kept.
+    pub fn flush(&self) {
+        let lua = self.lua();
+        let scripts = lua.create_table();
+        if let Err(e) = scripts.and_then(|t| lua.set_named_registry_value(SCRIPTS_REGISTRY, t)) {
+            tracing::warn!("failed to remove the cached scripts: {}", e);
+        }
+        self.scripts.clear();
+    }
+
+    /// Run a cached script, the caller must hold the backend exclusive lock.
+    pub fn eval(
+        &self,
+        backend: &Backend,
+        sha: &str,
+        keys: &[Bytes],
+        args: &[Vec<u8>],
+    ) -> RespFrame {
+        let sha = sha.to_ascii_lowercase();
+        if !self.scripts.contains_key(&sha) {
+            return SimpleError::new("NOSCRIPT No matching script. Please use EVAL.").into();
+        }
+        let lua = self.lua();
+        let func = match prepare_eval(&lua, &sha, keys, args) {
+            Ok(func) => func,
+            Err(e) => return error_reply(&e).into(),
+        };
+        self.run(&lua, backend, func, (), false)
+    }
+
+    /// Whether a script has been running for longer than the time limit.
+    pub fn is_busy(&self) -> bool {
+        let limit = Duration::from_millis(self.time_limit_ms.load(Ordering::Relaxed));
+        self.running().is_some_and(|start| start.elapsed() >= limit)
+    }
+
+    pub fn kill(&self) -> RespFrame {
+        if self.running().is_none() {
+            return SimpleError::new("NOTBUSY No scripts in execution right now.").into();
+        }
+        if self.wrote.load(Ordering::Acquire) {
+            return SimpleError::new(
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.",
+            )
+            .into();
+        }
+        self.kill.store(true, Ordering::Release);
+        SimpleString::new("OK").into()
+    }
+
+    /// The requests redoing what the last script or function wrote, rather than running it
+    /// again, which may not do the same.
+    pub(crate) fn take_effects(&self) -> Vec<RespFrame> {
+        std::mem::take(&mut *self.effects())
+    }
+
+    // run a script or function, tracking it so it can be reported BUSY and killed
+    fn run<'lua>(
+        &self,
+        lua: &'lua Lua,
+        backend: &Backend,
+        func: Function<'lua>,
+        args: impl IntoLuaMulti<'lua>,
+        read_only: bool,
+    ) -> RespFrame {
+        self.kill.store(false, Ordering::Release);
+        self.wrote.store(false, Ordering::Release);
+        self.effects().clear();
+        *self.running() = Some(Instant::now());
+        let ret = self.call(lua, backend, func, args, read_only);
+        *self.running() = None;
+        match ret {
+            Ok(frame) => frame,
+            Err(e) => error_reply(&e).into(),
+        }
+    }
+
+    fn call<'lua>(
+        &self,
+        lua: &'lua Lua,
+        backend: &Backend,
+        func: Function<'lua>,
+        args: impl IntoLuaMulti<'lua>,
+        read_only: bool,
+    ) -> mlua::Result<RespFrame> {
+        let redis: Table = lua.named_registry_value(REDIS_REGISTRY)?;
+        lua.scope(|scope| {
+            let call = scope.create_function(|lua, args: Variadic<Value>| {
+                match self.call_command(backend, args, read_only) {
+                    Ok(frame) => frame_to_lua(lua, frame),
+                    Err(e) => Err(mlua::Error::external(ScriptCommandError(e))),
+                }
+            })?;
+            let pcall = scope.create_function(|lua, args: Variadic<Value>| {
+                match self.call_command(backend, args, read_only) {
+                    Ok(frame) => frame_to_lua(lua, frame),
+                    Err(e) => frame_to_lua(lua, SimpleError::new(e).into()),
+                }
+            })?;
+            redis.set("call", call)?;
+            redis.set("pcall", pcall)?;
+            let ret: MultiValue = func.call(args)?;
+            lua_to_frame(ret.into_iter().next().unwrap_or(Value::Nil))
+        })
+    }
+
+    fn call_command(
+        &self,
+        backend: &Backend,
+        args: Variadic<Value>,
+        read_only: bool,
+    ) -> Result<RespFrame, String> {
+        if args.is_empty() {
+            return Err(
+                "ERR Please specify at least one argument for this redis lib call".to_string(),
+            );
+        }
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Value::String(s) => Ok(BulkString::new(s.as_bytes()).into()),
+                Value::Integer(i) => Ok(BulkString::new(i.to_string()).into()),
+                Value::Number(n) => Ok(BulkString::new(n.to_string()).into()),
+                _ => Err(
+                    "ERR Lua redis lib command arguments must be strings or integers".to_string(),
+                ),
+            })
+            .collect::<Result<Vec<RespFrame>, String>>()?;
+        let request = RespArray::new(args);
+        let cmd = Command::try_from(request.clone()).map_err(|e| format!("ERR {}", e))?;
+        match cmd {
+            Command::Unrecognized(_) => {
+                return Err("ERR Unknown Redis command called from script".to_string())
+            }
+            Command::Multi(_)
+            | Command::Exec(_)
+            | Command::Discard(_)
+            | Command::Watch(_)
+            | Command::Unwatch(_)
+            | Command::Eval(_)
+            | Command::EvalSha(_)
+            | Command::Script(_)
+            | Command::Function(_)
+            | Command::FCall(_)
+            | Command::Subscribe(_)
+            | Command::Unsubscribe(_)
+            | Command::PSubscribe(_)
+            | Command::PUnsubscribe(_)
+            | Command::SSubscribe(_)
+            | Command::SUnsubscribe(_)
+            | Command::Config(_)
+            | Command::Hello(_)
+            | Command::Quit(_)
+            | Command::Reset(_)
+            | Command::Migrate(_) => {
+                return Err("ERR This Redis command is not allowed from script".to_string())
+            }
+            _ => {}
+        }
+        if cmd.is_write() && read_only {
+            return Err("ERR Write commands are not allowed from read-only scripts.".to_string());
+        }
+        if cmd.is_write() {
+            self.wrote.store(true, Ordering::Release);
+        }
+        let reply = cmd.execute(backend);
+        if cmd.is_write() && backend.aof().is_enabled() {
+            let requests = cmd.propagated(request.into(), &reply, backend);
+            self.effects().extend(requests);
+        }
+        match reply {
+            RespFrame::Error(e) => Err(e.0),
+            frame => Ok(frame),
+        }
+    }
+
+    fn lua(&self) -> MutexGuard<'_, Lua> {
+        self.lua.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn running(&self) -> MutexGuard<'_, Option<Instant>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn effects(&self) -> MutexGuard<'_, Vec<RespFrame>> {
+        self.effects.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// KEYS and ARGV are globals for EVAL scripts
+fn prepare_eval<'lua>(
+    lua: &'lua Lua,
+    sha: &str,
+    keys: &[Bytes],
+    args: &[Vec<u8>],
+) -> mlua::Result<Function<'lua>> {
+    let scripts: Table = lua.named_registry_value(SCRIPTS_REGISTRY)?;
+    let func: Function = scripts.raw_get(sha)?;
+    let base: Table = lua.named_registry_value(BASE_REGISTRY)?;
+    base.raw_set("KEYS", string_sequence(lua, keys)?)?;
+    base.raw_set("ARGV", string_sequence(lua, args)?)?;
+    Ok(func)
+}
+
+fn string_sequence<'lua>(lua: &'lua Lua, values: &[impl AsRef<[u8]>]) -> mlua::Result<Table<'lua>> {
+    let values = values
+        .iter()
+        .map(|v| lua.create_string(v))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    lua.create_sequence_from(values)
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+fn new_lua(kill: &Arc<AtomicBool>, load_deadline: &Arc<AtomicU64>) -> Lua {
+    // only the unsafe libraries, which are left out, can fail to load
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )
+    .expect("the safe lua libraries always load");
+    let (kill, load_deadline) = (kill.clone(), load_deadline.clone());
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(1000),
+        move |_lua, _debug| {
+            if kill.load(Ordering::Acquire) {
+                return Err(mlua::Error::external(ScriptCommandError(
+                    KILLED.to_string(),
+                )));
+            }
+            let deadline = load_deadline.load(Ordering::Acquire);
+            if deadline != 0 && now_ms() >= deadline {
+                return Err(mlua::Error::external(ScriptCommandError(
+                    LOAD_TIMEOUT.to_string(),
+                )));
+            }
+            Ok(())
+        },
+    );
+    if let Err(e) = register_redis_lib(&lua).and_then(|_| sandbox(&lua)) {
+        tracing::warn!("failed to set up the lua state: {}", e);
+    }
+    lua
+}
+
+// Move every global but the unsafe ones into a base table, the libraries behind read-only
+// proxies, and leave the globals table empty, reading through to the base and refusing writes.
+fn sandbox(lua: &Lua) -> mlua::Result<()> {
+    // strings find their methods through their metatable, which must not lead to the library
+    lua.load("getmetatable('').__metatable = false").exec()?;
+    lua.set_named_registry_value(SCRIPTS_REGISTRY, lua.create_table()?)?;
+    let globals = lua.globals();
+    let base = lua.create_table()?;
+    for pair in globals.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        match (&key, value) {
+            (Value::String(name), _) if UNSAFE_GLOBALS.contains(&name.to_str()?) => {}
+            (Value::String(name), _) if name == "_G" => {}
+            (_, Value::Table(table)) => base.raw_set(key, readonly(lua, table)?)?,
+            (_, value) => base.raw_set(key, value)?,
+        }
+    }
+    globals.clear()?;
+    base.raw_set("_G", globals.clone())?;
+    let meta = lua.create_table()?;
+    meta.raw_set("__index", base.clone())?;
+    meta.raw_set("__newindex", readonly_error(lua)?)?;
+    meta.raw_set("__metatable", false)?;
+    globals.set_metatable(Some(meta));
+    lua.set_named_registry_value(BASE_REGISTRY, base)
+}
+
+// an empty table reading through to `table`, refusing writes and hiding its metatable
+fn readonly<'lua>(lua: &'lua Lua, table: Table<'lua>) -> mlua::Result<Table<'lua>> {
+    let proxy = lua.create_table()?;
+    let meta = lua.create_table()?;
+    meta.raw_set("__index", table)?;
+    meta.raw_set("__newindex", readonly_error(lua)?)?;
+    meta.raw_set("__metatable", false)?;
+    proxy.set_metatable(Some(meta));
+    Ok(proxy)
+}
+
+fn readonly_error(lua: &Lua) -> mlua::Result<Function<'_>> {
+    lua.create_function(|_, _: MultiValue| -> mlua::Result<()> {
+        Err(mlua::Error::RuntimeError(READONLY_ERROR.to_string()))
+    })
+}
+
+fn register_redis_lib(lua: &Lua) -> mlua::Result<()> {
+    let redis = lua.create_table()?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, msg: mlua::String| {
+            let reply = lua.create_table()?;
+            reply.set("err", msg)?;
+            Ok(reply)
+        })?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, msg: mlua::String| {
+            let reply = lua.create_table()?;
+            reply.set("ok", msg)?;
+            Ok(reply)
+        })?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, data: mlua::String| Ok(sha1_hex(data.as_bytes())))?,
+    )?;
+    redis.set(
+        "register_function",
+        lua.create_function(function::register_function)?,
+    )?;
+    lua.set_named_registry_value(REDIS_REGISTRY, redis.clone())?;
+    lua.globals().set("redis", redis)
+}
+
+// the error reply of a failed script, a failed redis.call is returned as is
+fn error_reply(err: &mlua::Error) -> SimpleError {
+    if let Some(e) = find_command_error(err) {
+        return SimpleError::new(e.0.clone());
+    }
+    SimpleError::new(format!("ERR Error running script: {}", err))
+}
+
+fn find_command_error(err: &mlua::Error) -> Option<&ScriptCommandError> {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => find_command_error(cause),
+        err => err.downcast_ref::<ScriptCommandError>(),
+    }
+}
+
+/// Convert a command reply into the Lua value a script sees.
+pub(crate) fn frame_to_lua(lua: &Lua, frame: RespFrame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        RespFrame::Integer(i) => Value::Number(i as f64),
+        RespFrame::BulkString(s) => Value::String(lua.create_string(&s[..])?),
+        RespFrame::SimpleString(s) => {
+            let reply = lua.create_table()?;
+            reply.set("ok", s.0)?;
+            Value::Table(reply)
+        }
+        RespFrame::Error(e) => {
+            let reply = lua.create_table()?;
+            reply.set("err", e.0)?;
+            Value::Table(reply)
+        }
+        RespFrame::NullBulkString(_) | RespFrame::NullArray(_) | RespFrame::Null(_) => {
+            Value::Boolean(false)
+        }
+        RespFrame::Boolean(b) => Value::Boolean(b),
+        RespFrame::Double(d) => Value::Number(d),
+        RespFrame::BigNumber(n) => Value::String(lua.create_string(n.to_string())?),
+        RespFrame::VerbatimString(s) => Value::String(lua.create_string(s.data())?),
+        RespFrame::BulkError(e) => {
+            let reply = lua.create_table()?;
+            reply.set("err", lua.create_string(e.as_slice())?)?;
+            Value::Table(reply)
+        }
+        RespFrame::Attribute(attribute) => frame_to_lua(lua, *attribute.frame)?,
+        RespFrame::Array(frames) => sequence_to_lua(lua, frames.0)?,
+        RespFrame::Push(frames) => sequence_to_lua(lua, frames.0)?,
+        RespFrame::Set(frames) => sequence_to_lua(lua, frames.0)?,
+        RespFrame::Map(map) => {
+            let mut frames = Vec::with_capacity(map.len() * 2);
+            for (key, value) in map.0 {
+                frames.push(key.into());
+                frames.push(value);
+            }
+            sequence_to_lua(lua, frames)?
+        }
+    };
+    Ok(value)
+}
+
+fn sequence_to_lua(lua: &Lua, frames: Vec<RespFrame>) -> mlua::Result<Value<'_>> {
+    let values = frames
+        .into_iter()
+        .map(|frame| frame_to_lua(lua, frame))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    Ok(Value::Table(lua.create_sequence_from(values)?))
+}
+
+/// Convert the value returned by a script into a reply.
+pub(crate) fn lua_to_frame(value: Value) -> mlua::Result<RespFrame> {
+    let frame = match value {
+        Value::Nil => RespFrame::Null(RespNull),
+        Value::Boolean(true) => RespFrame::Integer(1),
+        Value::Boolean(false) => RespFrame::Null(RespNull),
+        Value::Integer(i) => RespFrame::Integer(i),
+        Value::Number(n) => RespFrame::Integer(n as i64),
+        Value::String(s) => BulkString::new(s.as_bytes()).into(),
+        Value::Table(table) => {
+            if let Some(err) = table.get::<_, Option<mlua::String>>("err")? {
+                return Ok(SimpleError::new(err.to_string_lossy()).into());
+            }
+            if let Some(ok) = table.get::<_, Option<mlua::String>>("ok")? {
+                return Ok(SimpleString::new(ok.to_string_lossy()).into());
+            }
+            // like redis, the array stops at the first nil
+            let mut frames = Vec::new();
+            for value in table.sequence_values::<Value>() {
+                frames.push(lua_to_frame(value?)?);
+            }
+            RespArray::new(frames).into()
+        }
+        _ => RespFrame::Null(RespNull),
+    };
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha1_hex() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[test]
+    fn test_eval_redis_call() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let engine = ScriptEngine::new();
+        let sha = engine.load(
+            "redis.call('set', KEYS[1], ARGV[1]) return {redis.call('get', KEYS[1]), 10, false}",
+        ).unwrap();
+        assert!(engine.exists(&sha));
+        let ret = engine.eval(&backend, &sha, &["hello".into()], &[b"world".to_vec()]);
+        assert_eq!(
+            ret,
+            RespArray::new([
+                b"world".into(),
+                RespFrame::Integer(10),
+                RespFrame::Null(RespNull)
+            ])
+            .into()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_errors() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let engine = ScriptEngine::new();
+        assert!(engine.load("return (").is_err());
+
+        backend.sadd("set".into(), &["a".into()]).unwrap();
+        let sha = engine
+            .load("return redis.call('sort', 'set', 'ALPHA', 'FOO')")
+            .unwrap();
+        let ret = engine.eval(&backend, &sha, &[], &[]);
+        assert_eq!(
+            ret,
+            SimpleError::new("ERR Invalid argument:syntax error").into()
+        );
+
+        let sha = engine.load("return redis.pcall('exec')").unwrap();
+        let ret = engine.eval(&backend, &sha, &[], &[]);
+        assert_eq!(
+            ret,
+            SimpleError::new("ERR This Redis command is not allowed from script").into()
+        );
+
+        let sha = engine.load("return redis.status_reply('PONG')").unwrap();
+        let ret = engine.eval(&backend, &sha, &[], &[]);
+        assert_eq!(ret, SimpleString::new("PONG").into());
+
+        engine.flush();
+        let ret = engine.eval(&backend, &sha, &[], &[]);
+        assert!(matches!(ret, RespFrame::Error(e) if e.starts_with("NOSCRIPT")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sandbox() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let engine = ScriptEngine::new();
+        let sha = engine.load(
+            "return {type(os), type(io), type(package), type(debug), type(loadstring), type(dofile)}",
+        ).unwrap();
+        let nil = RespFrame::from(b"nil");
+        assert_eq!(
+            engine.eval(&backend, &sha, &[], &[]),
+            RespArray::new(vec![nil; 6]).into()
+        );
+        for script in [
+            "x = 1",
+            "redis = nil",
+            "KEYS = {}",
+            "redis.call = nil",
+            "string.rep = nil",
+            "_G.redis = nil",
+            "getmetatable('').__index.rep = nil",
+            "setmetatable(_G, nil)",
+        ] {
+            let sha = engine.load(script).unwrap();
+            let ret = engine.eval(&backend, &sha, &[], &[]);
+            assert!(matches!(ret, RespFrame::Error(_)), "{} was allowed", script);
+        }
+        // the scripts that follow still see everything as it was
+        let sha = engine
+            .load("redis.call('set', KEYS[1], string.rep(ARGV[1], 2)) return #KEYS")
+            .unwrap();
+        let ret = engine.eval(&backend, &sha, &["key".into()], &[b"ab".to_vec()]);
+        assert_eq!(ret, RespFrame::Integer(1));
+        assert_eq!(backend.get(b"key"), Ok(Some("abab".into())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_kill() -> anyhow::Result<()> {
+        let backend = Backend::new();
+        let engine = Arc::new(ScriptEngine::new());
+        engine.set_time_limit(Duration::from_millis(10));
+        assert!(matches!(engine.kill(), RespFrame::Error(e) if e.starts_with("NOTBUSY")));
+
+        let sha = engine.load("while true do end").unwrap();
+        let handle = {
+            let engine = engine.clone();
+            std::thread::spawn(move || engine.eval(&backend, &sha, &[], &[]))
+        };
+        while !engine.is_busy() {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(engine.kill(), SimpleString::new("OK").into());
+        let ret = handle.join().unwrap();
+        assert_eq!(ret, SimpleError::new(KILLED).into());
+        assert!(!engine.is_busy());
+        Ok(())
+    }
+}